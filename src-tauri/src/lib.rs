@@ -4,11 +4,13 @@ use dashmap::{DashMap, DashSet};
 use lazy_static::lazy_static;
 use platform::PathInfo;
 use rayon::prelude::*;
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, AtomicUsize, Ordering};
 use std::sync::Arc;
-use std::sync::Mutex;
+use std::sync::{Mutex, RwLock};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 use tauri::Emitter;
 
 /// Contains analytics information for a directory or file
@@ -20,22 +22,40 @@ struct AnalyticsInfo {
   size_bytes: u64,
   /// Total size in bytes on disk
   size_allocated_bytes: u64,
+  /// Total size in bytes counting each physical file once, however many hard links to it
+  /// were scanned. What deleting the whole tree would free, links outside it aside
+  unique_bytes: u64,
+  /// Total size in bytes on disk counting each physical file once
+  unique_allocated_bytes: u64,
   /// Total number of entries (files and directories)
   entry_count: u64,
   /// Number of files
   file_count: u64,
   /// Number of directories
   directory_count: u64,
+  /// How many levels the subtree extends below this entry, 0 for files and empty directories
+  max_child_depth: u32,
+  /// Direct children that were listed but never recorded, e.g. because they errored or the
+  /// scan was cancelled. The totals leave them out, so they are incomplete when nonzero
+  skipped_children: u32,
   /// Last modified time (Unix timestamp in seconds)
   last_modified_time: u64,
   /// Owner of the file or directory
   owner_name: Option<String>,
   /// Path info
   path_info: Option<PathInfo>,
+  /// MIME type detected from the file's magic bytes, when content type detection was on
+  content_type: Option<String>,
+  /// Where a symlink points, as stored in the link (possibly relative or dangling). A followed
+  /// directory link whose target lies inside the scan is only an entry referencing it, and
+  /// gets the canonical path of the target counted in its place
+  link_target: Option<PathBuf>,
+  /// BLAKE3 hash of the file's contents as hex, for the files picked by `hash_largest`
+  content_hash: Option<String>,
 }
 
 /// Represents a node in the file system tree
-#[derive(Clone, Debug, Serialize)]
+#[derive(Clone, Debug, Default, Serialize)]
 struct FileSystemTreeNode {
   /// Path to the file or directory
   path: PathBuf,
@@ -51,8 +71,15 @@ struct FileSystemTreeNode {
   file_count: u64,
   /// Number of directories
   directory_count: u64,
+  /// How many levels the subtree extends below this node, 0 for files and empty directories
+  max_child_depth: u32,
+  /// Direct children left out of the totals because they couldn't be scanned
+  skipped_children: u32,
   /// Percentage of parent size (0-100)
   percent_of_parent: f64,
+  /// Percentage of parent size on disk (0-100), which differs from `percent_of_parent` for
+  /// compressed, sparse or slack-heavy children
+  percent_of_parent_allocated: f64,
   /// Last modified time (Unix timestamp in seconds)
   last_modified_time: u64,
   /// Owner of the file or directory
@@ -60,6 +87,65 @@ struct FileSystemTreeNode {
   /// Child nodes
   children: Vec<FileSystemTreeNode>,
   is_virtual_directory: bool,
+  /// Category for picking an icon: image, video, audio, archive, code, document,
+  /// executable, folder, symlink or other
+  file_kind_hint: String,
+  /// Whether the node is a symlink (or junction) rather than the file or directory itself
+  is_symlink: bool,
+  /// Where the symlink points, also set when the target doesn't exist
+  symlink_target: Option<PathBuf>,
+  /// BLAKE3 hash of the file's contents as hex, only for files the scan hashed
+  content_hash: Option<String>,
+  /// Whether this is the node the user located and the UI should scroll to
+  highlight: bool,
+}
+
+impl FileSystemTreeNode {
+  // The node of a cached entry without its children, percentages are for the parent to fill
+  // in once it knows its own totals
+  fn from_entry(entry: &AnalyticsInfo) -> Self {
+    FileSystemTreeNode {
+      path: entry.path.clone(),
+      name: node_name(&entry.path),
+      size_bytes: entry.size_bytes,
+      size_allocated_bytes: entry.size_allocated_bytes,
+      entry_count: entry.entry_count,
+      file_count: entry.file_count,
+      directory_count: entry.directory_count,
+      max_child_depth: entry.max_child_depth,
+      skipped_children: entry.skipped_children,
+      percent_of_parent: 100.0,
+      percent_of_parent_allocated: 100.0,
+      last_modified_time: entry.last_modified_time,
+      owner_name: entry.owner_name.clone(),
+      file_kind_hint: entry_kind_hint(entry),
+      is_symlink: is_symlink_entry(entry),
+      symlink_target: entry_symlink_target(entry),
+      content_hash: entry.content_hash.clone(),
+      ..Default::default()
+    }
+  }
+
+  // A virtual folder summarizing other entries, like `[others]` or `[N Files]`, with
+  // nothing counted yet
+  fn synthetic(path: PathBuf, name: String) -> Self {
+    FileSystemTreeNode {
+      path,
+      name,
+      is_virtual_directory: true,
+      file_kind_hint: "folder".to_string(),
+      ..Default::default()
+    }
+  }
+}
+
+// Display name of a tree node, the last component of its path
+fn node_name(path: &Path) -> String {
+  path
+    .file_name()
+    .and_then(|n| n.to_str())
+    .unwrap_or("unknown")
+    .to_string()
 }
 
 /// Complete scan result with tree representation
@@ -71,211 +157,1199 @@ struct DirectoryScanResult {
   tree: FileSystemTreeNode,
   /// Total scan time in milliseconds
   scan_time_ms: u64,
+  /// Total scan time in microseconds, for scans too fast to measure in milliseconds
+  scan_time_us: u64,
+  /// Symlinks whose target had already been scanned, as (symlink_path, target_path)
+  /// Their contents are only counted once, under the target
+  symlink_cycles: Vec<(PathBuf, PathBuf)>,
+  /// Paths that had to be skipped, sorted by path
+  errors: Vec<ScanError>,
+  /// Whether many directories couldn't be read because macOS hasn't granted the app Full
+  /// Disk Access, so the UI should ask the user to grant it in System Settings
+  needs_full_disk_access: bool,
+  /// Whether the scan stopped at its deadline, so the totals only cover what was reached
+  partial: bool,
+  /// Whether the scanned root is a system directory that moves refuse without
+  /// `allow_protected`
+  is_protected_root: bool,
+  /// How the root compares to its parent directory, with `include_parent_context`
+  parent_context: Option<ParentContext>,
+}
+
+/// Size of the scanned root's parent, measured without scanning it
+#[derive(Clone, Debug, Serialize)]
+struct ParentContext {
+  /// The parent directory of the scanned root
+  parent_path: PathBuf,
+  /// The parent's total size in bytes from the previous scan, or when it wasn't part of
+  /// one, the used space of the whole volume
+  parent_total_bytes: u64,
+  /// Whether `parent_total_bytes` is the volume's used space rather than the parent's size
+  is_volume_total: bool,
+  /// The scanned root's size as a percentage (0-100) of `parent_total_bytes`
+  percent_of_parent: f64,
+}
+
+/// A path the scan had to skip
+#[derive(Clone, Debug, Serialize)]
+struct ScanError {
+  /// The skipped path
+  path: PathBuf,
+  /// Why it was skipped, e.g. "timeout" or "permission denied"
+  reason: String,
+}
+
+/// How allocated sizes are reported
+/// - `None` keeps the sizes reported by the OS: apparent sizes match `du --apparent-size`
+///   and `ls -l`, allocated sizes match `du` on Unix
+/// - `ClusterUp` rounds every file's allocated size up to a whole number of clusters,
+///   matching Windows Explorer's "Size on disk"
+/// - Files on FAT/exFAT volumes are always rounded up, since Windows doesn't report their
+///   allocated size cheaply
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Eq)]
+enum RoundingMode {
+  #[default]
+  None,
+  ClusterUp,
+}
+
+/// Options controlling what a scan visits
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+struct ScanOptions {
+  /// Maximum directory depth to descend into below the scan root (None = unlimited)
+  /// Directories at the limit are recorded with their own size but not read
+  max_depth: Option<usize>,
+  /// File or directory names to skip entirely (matched against the entry name)
+  exclude_names: Vec<String>,
+  /// How allocated sizes are rounded
+  rounding: RoundingMode,
+  /// Emit `scan-preview` and `partial-node` events while the scan runs, so the UI can show
+  /// the root's children before the whole tree has been measured
+  preview: bool,
+  /// Give up on an entry whose metadata takes longer than this to read (None = wait forever)
+  /// Keeps one unresponsive file on a network share from stalling the whole scan
+  per_entry_timeout_ms: Option<u64>,
+  /// Count the sizes reported for devices, FIFOs and sockets instead of treating them as 0
+  include_special_sizes: bool,
+  /// Graft unchanged directories in from the cached scan instead of reading them again
+  /// A directory's mtime changes when entries are added, removed or renamed directly inside
+  /// it, so a cached subtree is reused only if every directory in it still has its cached
+  /// mtime. Files rewritten in place don't touch their directory's mtime and won't be noticed
+  /// The cached scan is assumed to have used the same options
+  reuse_cache: bool,
+  /// Descend into symlinks to directories instead of counting just the link
+  follow_dir_symlinks: bool,
+  /// Descend into Windows directory junctions, e.g. `C:\Documents and Settings`, which
+  /// points at `C:\Users` and double-counts it when followed
+  follow_junctions: bool,
+  /// Count the size of a symlinked file's target instead of the link itself
+  follow_file_symlinks: bool,
+  /// Read the start of files of at least `CONTENT_TYPE_MIN_SIZE` bytes to detect their real
+  /// type, for files with a wrong or missing extension. Costs an open and read per file
+  detect_content_type: bool,
+  /// Stop descending after this many seconds and return what was scanned so far
+  /// (None = no limit)
+  deadline_secs: Option<u64>,
+  /// Measure how much disk space entries take up. When false, allocated sizes are just
+  /// copies of the apparent sizes and allocated totals mean nothing, in exchange for
+  /// skipping the extra per-file call Windows needs for compressed and sparse files
+  compute_allocated: bool,
+  /// List entries on pseudo-filesystems like /proc and /sys (always as 0 bytes). When
+  /// false they are skipped, unless the scan starts inside one
+  include_pseudo_filesystems: bool,
+  /// Files smaller than this many bytes still count towards their directory's totals but
+  /// get no entry of their own, keeping the cache small on volumes with millions of tiny
+  /// files. Listings show them as one `[small files]` node (0 = record every file)
+  min_record_size: u64,
+  /// Report the root's share of its parent directory in the scan result. The parent isn't
+  /// scanned: its size comes from the previous scan if that covered it, else the share of
+  /// the volume's used space is reported
+  include_parent_context: bool,
+  /// Only count entries, files and directories. Every size in the result is 0, and plain
+  /// files are never stat'ed: their type comes from `read_dir` and directories are totalled
+  /// from the types recorded for their children. That makes the scan several times faster
+  /// but leaves out owners, times and hard link detection for files
+  counts_only: bool,
+  /// Once the scan is done, hash the contents of this many of the largest files with BLAKE3
+  /// and return the digests as their `content_hash` (None = hash nothing). Reads every byte
+  /// of those files, so it can take a while on big ones
+  hash_largest: Option<usize>,
+}
+
+impl Default for ScanOptions {
+  fn default() -> Self {
+    ScanOptions {
+      max_depth: None,
+      exclude_names: Vec::new(),
+      rounding: RoundingMode::default(),
+      preview: false,
+      per_entry_timeout_ms: None,
+      include_special_sizes: false,
+      reuse_cache: false,
+      follow_dir_symlinks: false,
+      follow_junctions: false,
+      follow_file_symlinks: false,
+      detect_content_type: false,
+      deadline_secs: None,
+      compute_allocated: true,
+      include_pseudo_filesystems: true,
+      min_record_size: 0,
+      counts_only: false,
+      include_parent_context: false,
+      hash_largest: None,
+    }
+  }
+}
+
+/// Compact totals for a path, returned without building a tree or touching the cache
+#[derive(Clone, Debug, Serialize)]
+struct DirectorySizeSummary {
+  /// Total size in bytes
+  size_bytes: u64,
+  /// Total size in bytes on disk
+  size_allocated_bytes: u64,
+  /// Total size in bytes counting each hard-linked file once
+  unique_bytes: u64,
+  /// Total size in bytes on disk counting each hard-linked file once
+  unique_allocated_bytes: u64,
+  /// Number of files
+  file_count: u64,
+  /// Number of directories
+  directory_count: u64,
+  /// Number of devices, FIFOs and sockets, counted as entries but not as files
+  special_file_count: u64,
+  /// Total scan time in milliseconds
+  scan_time_ms: u64,
+}
+
+/// Collections filled in by a scan, shared between the scanning threads and the caller
+#[derive(Default)]
+struct ScanState {
+  /// Totals for every path scanned so far
+  analytics_map: DashMap<PathBuf, Arc<AnalyticsInfo>>,
+  /// (device, inode) pairs already counted, used to find hard links and skip cycles
+  visited_inodes: DashSet<(u64, u64)>,
+  /// Paths already scanned
+  processed_paths: DashSet<PathBuf>,
+  /// Symlinks whose target had already been scanned, mapped to that target
+  symlink_cycles: DashMap<PathBuf, PathBuf>,
+  /// Paths that had to be skipped, mapped to the reason
+  errors: DashMap<PathBuf, String>,
+  /// Number of devices, FIFOs and sockets seen
+  special_file_count: AtomicU64,
+  /// Number of entries scanned so far, for throughput reporting
+  entries_scanned: AtomicU64,
+  /// Bytes of the entries scanned so far, each counted at its own size
+  bytes_scanned: AtomicU64,
+  /// An earlier scan whose unchanged subtrees are grafted in instead of being read again
+  previous_scan: Option<Arc<ScanCache>>,
+  /// Set to stop the scan reading any more entries, e.g. once its deadline passes
+  /// Directories already being scanned still finish with totals of what was read
+  cancelled: AtomicBool,
+}
+
+impl ScanState {
+  // Counts one more scanned entry of `size_bytes` towards the throughput totals
+  fn record_scanned(&self, size_bytes: u64) {
+    self.entries_scanned.fetch_add(1, Ordering::Relaxed);
+    self.bytes_scanned.fetch_add(size_bytes, Ordering::Relaxed);
+  }
+
+  // Collects the recorded errors, sorted by path
+  fn errors_sorted(&self) -> Vec<ScanError> {
+    let mut errors: Vec<ScanError> = self
+      .errors
+      .iter()
+      .map(|item| ScanError {
+        path: item.key().clone(),
+        reason: item.value().clone(),
+      })
+      .collect();
+    errors.sort_by(|a, b| a.path.cmp(&b.path));
+    errors
+  }
+
+  // Fraction of the scanned directories whose contents couldn't be read for lack of permission
+  fn permission_denied_ratio(&self) -> f64 {
+    let denied = self
+      .errors
+      .iter()
+      .filter(|item| item.value() == PERMISSION_DENIED_REASON)
+      .count();
+    let directories = self
+      .analytics_map
+      .iter()
+      .filter(|item| item.path_info.as_ref().is_some_and(|info| info.is_dir))
+      .count();
+    if directories == 0 {
+      0.0
+    } else {
+      denied as f64 / directories as f64
+    }
+  }
+
+  // Whether the scan came back partial because macOS is withholding Full Disk Access
+  // Only checks the protected folder when enough directories were denied to matter
+  fn needs_full_disk_access(&self) -> bool {
+    cfg!(target_os = "macos")
+      && self.permission_denied_ratio() >= FULL_DISK_ACCESS_DENIED_RATIO
+      && !platform::has_full_disk_access()
+  }
+}
+
+// Reason recorded for directories whose contents couldn't be listed for lack of permission
+const PERMISSION_DENIED_REASON: &str = "permission denied";
+
+// Share of denied directories above which a macOS scan is assumed to be missing Full Disk Access
+const FULL_DISK_ACCESS_DENIED_RATIO: f64 = 0.05;
+
+// Partial results reported while a scan is still running
+enum ScanEvent {
+  // The scan root with its direct children listed by name and zero sizes
+  Preview(FileSystemTreeNode),
+  // A direct child of the scan root whose subtree has finished scanning
+  PartialNode(FileSystemTreeNode),
+}
+
+// Receives scan events, called from the scanning threads
+type ScanObserver<'a> = dyn Fn(ScanEvent) + Sync + 'a;
+
+// Shared state for a single scan, borrowed by every task spawned in the Rayon scope
+struct ScanContext<'a> {
+  // The path being scanned, canonicalized by the caller
+  root: &'a Path,
+  state: &'a ScanState,
+  options: &'a ScanOptions,
+  observer: Option<&'a ScanObserver<'a>>,
+  // Information about each volume seen so far, fetched once per volume
+  volumes: DashMap<platform::VolumeKey, VolumeInfo>,
+}
+
+// Per-volume information used to adjust allocated sizes
+#[derive(Clone, Copy, Debug)]
+struct VolumeInfo {
+  cluster_size: Option<u64>,
+  // FAT and exFAT volumes get allocated = apparent from the cheap Windows metadata path,
+  // which hides the cluster slack that dominates on large-cluster exFAT drives
+  is_fat: bool,
+  // Total size of the volume, no file on it can be larger
+  capacity: Option<u64>,
+}
+
+// A directory whose children are still being scanned
+// Each child task decrements `remaining` when it finishes, and whichever task brings it
+// to zero aggregates the directory and moves on to the parent. Completion therefore walks
+// up the tree in a loop, so scan depth is bounded by the heap rather than the stack
+struct PendingDirectory {
+  path: PathBuf,
+  path_info: PathInfo,
+  depth: usize,
+  children: Vec<PathBuf>,
+  remaining: AtomicUsize,
+  // Children that couldn't be recorded, as opposed to ones left out on purpose (excluded
+  // names, pseudo filesystems, paths already scanned through another link)
+  skipped: AtomicU32,
+  parent: Option<Arc<PendingDirectory>>,
 }
 
 // Efficient sync function that uses Rayon for parallel processing
 fn calculate_size_sync(
   path: &Path,
-  analytics_map: Arc<DashMap<PathBuf, Arc<AnalyticsInfo>>>,
-  target_dir_path: &Path,
-  visited_inodes: Arc<DashSet<(u64, u64)>>,
-  processed_paths: Arc<DashSet<PathBuf>>,
+  state: &ScanState,
+  options: &ScanOptions,
+  observer: Option<&ScanObserver<'_>>,
 ) -> std::io::Result<()> {
+  let context = ScanContext {
+    root: path,
+    state,
+    options,
+    observer: observer.filter(|_| options.preview),
+    volumes: DashMap::new(),
+  };
+
+  // Every directory becomes its own Rayon task instead of a nested par_iter call, so
+  // deeply nested trees can't overflow the worker threads' stacks
+  rayon::scope(|scope| {
+    scan_path(scope, &context, path.to_path_buf(), None, 0, None);
+  });
+
+  // Counting only leaves every file at 0 bytes, so there is no largest to pick
+  if let Some(count) = options.hash_largest.filter(|_| !options.counts_only) {
+    hash_largest_files(state, count);
+  }
+
+  Ok(())
+}
+
+// BLAKE3 hash of a file's contents as hex
+fn hash_file_contents(path: &Path) -> std::io::Result<String> {
+  let mut hasher = blake3::Hasher::new();
+  hasher.update_reader(std::fs::File::open(path)?)?;
+  Ok(hasher.finalize().to_hex().to_string())
+}
+
+// Hashes the `count` largest regular files of a finished scan in parallel and stores the
+// digests on their entries. Files that can't be read are left without one
+fn hash_largest_files(state: &ScanState, count: usize) {
+  if count == 0 {
+    return;
+  }
+  let mut files: Vec<(u64, PathBuf)> = state
+    .analytics_map
+    .iter()
+    .filter(|entry| {
+      entry.file_count == 1
+        && entry.directory_count == 0
+        && entry.path_info.as_ref().is_some_and(|info| info.is_file)
+    })
+    .map(|entry| (entry.size_bytes, entry.key().clone()))
+    .collect();
+  // Only the largest `count` need to be found, not sorted
+  if files.len() > count {
+    files.select_nth_unstable_by(count - 1, |a, b| b.cmp(a));
+    files.truncate(count);
+  }
+
+  files.par_iter().for_each(|(_, path)| {
+    if state.cancelled.load(Ordering::Relaxed) {
+      return;
+    }
+    let Ok(hash) = hash_file_contents(path) else {
+      return;
+    };
+    if let Some(mut entry) = state.analytics_map.get_mut(path) {
+      Arc::make_mut(&mut entry).content_hash = Some(hash);
+    }
+  });
+}
+
+// Files smaller than this aren't worth an open and read to detect their content type
+const CONTENT_TYPE_MIN_SIZE: u64 = 4096;
+// Bytes read from the start of a file for content type detection, enough for every format
+// `infer` knows
+const CONTENT_TYPE_SAMPLE_SIZE: u64 = 8192;
+
+// Identifies a file's MIME type from its magic bytes, None if it isn't a known format
+fn detect_content_type(path: &Path) -> Option<String> {
+  use std::io::Read;
+
+  let mut header = Vec::new();
+  std::fs::File::open(path)
+    .ok()?
+    .take(CONTENT_TYPE_SAMPLE_SIZE)
+    .read_to_end(&mut header)
+    .ok()?;
+  infer::get(&header).map(|kind| kind.mime_type().to_string())
+}
+
+// What `read_dir` already knows about a child, so its task can skip some system calls
+struct PrefetchedEntry {
+  // The child's type, which most filesystems return along with its name
+  file_type: Option<std::fs::FileType>,
+  // The child's metadata, which only Windows fills in without another call per entry
+  metadata: Option<std::fs::Metadata>,
+}
+
+impl PrefetchedEntry {
+  fn from_dir_entry(entry: &std::fs::DirEntry) -> Self {
+    let file_type = entry.file_type().ok();
+    let metadata = if cfg!(windows) && file_type.is_some_and(|t| !t.is_symlink()) {
+      entry.metadata().ok()
+    } else {
+      None
+    };
+    PrefetchedEntry {
+      file_type,
+      metadata,
+    }
+  }
+}
+
+// Scans a single path, spawning tasks for the children of directories
+fn scan_path<'scope>(
+  scope: &rayon::Scope<'scope>,
+  context: &'scope ScanContext<'scope>,
+  path: PathBuf,
+  prefetched: Option<PrefetchedEntry>,
+  depth: usize,
+  parent: Option<Arc<PendingDirectory>>,
+) {
+  // Skip excluded names and everything after a cancel, but never the scan root itself
+  // Only the latter leaves the parent's totals incomplete
+  if depth > 0 && is_excluded(&path, context.options) {
+    finish_child(context, parent);
+    return;
+  }
+  if depth > 0 && context.state.cancelled.load(Ordering::Relaxed) {
+    skip_child(context, parent);
+    return;
+  }
+
   // If we've already processed this path, skip it
-  if !processed_paths.insert(path.to_path_buf()) {
-    return Ok(());
+  if !context.state.processed_paths.insert(path.clone()) {
+    finish_child(context, parent);
+    return;
   }
 
   // Get path info using our platform-agnostic function - will work for files, dirs and symlinks
-  let is_symlink = path.is_symlink();
-  let path_info = match platform::get_path_info(path, is_symlink) {
+  // Whatever the parent's read_dir already returned is used instead of asking again
+  let compute_allocated = context.options.compute_allocated;
+  let (file_type, metadata) = prefetched
+    .map(|entry| (entry.file_type, entry.metadata))
+    .unwrap_or_default();
+  let read_path_info = move |path: PathBuf| {
+    let is_symlink = file_type.map_or_else(|| path.is_symlink(), |t| t.is_symlink());
+    let path_info = platform::get_path_info_with_allocation(&path, is_symlink, compute_allocated);
+    (is_symlink, path_info)
+  };
+  let (is_symlink, path_info) = match (file_type, metadata, context.options.per_entry_timeout_ms) {
+    // When only counting, the type is all a file needs
+    (Some(file_type), _, _)
+      if context.options.counts_only && !file_type.is_dir() && !file_type.is_symlink() =>
+    {
+      (
+        false,
+        Some(platform::get_path_info_from_file_type(&file_type)),
+      )
+    }
+    // Prefetched metadata is only handed out for entries known not to be symlinks
+    (_, Some(metadata), _) => (
+      false,
+      platform::get_path_info_from_metadata(&path, &metadata, compute_allocated),
+    ),
+    (_, None, Some(timeout_ms)) => {
+      let path_for_read = path.clone();
+      match run_with_timeout(Duration::from_millis(timeout_ms), move || {
+        read_path_info(path_for_read)
+      }) {
+        Some(result) => result,
+        None => {
+          context.state.errors.insert(path, "timeout".to_string());
+          skip_child(context, parent);
+          return;
+        }
+      }
+    }
+    (_, None, None) => read_path_info(path.clone()),
+  };
+  // A link whose target is missing is still listed, as the link itself
+  let mut path_info = match path_info.or_else(|| {
+    is_symlink
+      .then(|| platform::get_path_info_with_allocation(&path, false, compute_allocated))
+      .flatten()
+  }) {
     Some(info) => info,
-    None => return Ok(()),
+    None => {
+      skip_child(context, parent);
+      return;
+    }
   };
+  if depth > 0 && path_info.is_pseudo_fs && !context.options.include_pseudo_filesystems {
+    finish_child(context, parent);
+    return;
+  }
+  // Followed symlinks report the target's metadata, remember that the path is a link
+  path_info.is_symlink = is_symlink;
+
+  // Links that aren't followed count as themselves. Directory links keep the target's
+  // metadata either way, so links back into the scan are still reported as cycles
+  let follow_link = is_symlink && should_follow_link(&path, &path_info, context.options);
+  if is_symlink && !follow_link && !path_info.is_dir {
+    if let Some(mut link_info) =
+      platform::get_path_info_with_allocation(&path, false, compute_allocated)
+    {
+      link_info.is_symlink = true;
+      path_info = link_info;
+    }
+  }
+
+  // A followed link to a directory the scan reaches anyway only references it. Scanning it
+  // under the link's path as well would store a parallel copy of the subtree, and which
+  // copy won would depend on which path a thread got to first
+  if follow_link && path_info.is_dir && record_link_to_scanned_dir(context, &path, depth) {
+    finish_child(context, parent);
+    return;
+  }
 
   // Check for cycles using device and inode numbers if available
   // This handles both directory cycles AND symlinks properly
+  // A file seen again through another hard link is still listed, but its bytes aren't unique
+  let mut is_repeat_hard_link = false;
   if let Some(inode_pair) = path_info.inode_device {
-    if !visited_inodes.insert(inode_pair) {
+    let first_visit = context.state.visited_inodes.insert(inode_pair);
+    if !first_visit && !path_info.is_file {
       // We've already seen this inode, skip it
-      return Ok(());
+      // For symlinks, remember where they pointed so the user can see why the
+      // target's contents don't show up under the link
+      if is_symlink {
+        let target = path
+          .canonicalize()
+          .or_else(|_| std::fs::read_link(&path))
+          .unwrap_or_default();
+        context.state.symlink_cycles.entry(path).or_insert(target);
+      }
+      finish_child(context, parent);
+      return;
+    }
+    is_repeat_hard_link = !first_visit;
+  }
+
+  // Reuse the previous scan's results for directories that haven't changed since
+  if path_info.is_dir && !is_symlink && graft_cached_subtree(context, &path, &path_info) {
+    if depth == 1 {
+      emit_partial_node(context, &path);
+    }
+    finish_child(context, parent);
+    return;
+  }
+
+  // Round file allocations up to whole clusters when asked to match Explorer, or when the
+  // volume is FAT/exFAT and the reported allocation can't be trusted. Sizes larger than the
+  // volume itself come from corrupt metadata and are clamped so they can't swamp the totals
+  if path_info.is_file && !context.options.counts_only {
+    if let Some(volume) = get_cached_volume_info(context, &path, &path_info) {
+      path_info.size_bytes = clamp_to_capacity(&path, path_info.size_bytes, volume.capacity);
+      path_info.size_allocated_bytes = if context.options.compute_allocated {
+        clamp_to_capacity(
+          &path,
+          adjusted_allocated_size(
+            path_info.size_allocated_bytes,
+            &volume,
+            context.options.rounding,
+          ),
+          volume.capacity,
+        )
+      } else {
+        path_info.size_bytes
+      };
     }
   }
 
-  // Count entry as file or directory, symlinks count as entries but not as files or dirs
+  // Devices, FIFOs and sockets report sizes that aren't disk usage, so they count as
+  // zero-sized entries unless asked otherwise
+  let is_special = path_info.kind.is_special();
+  if is_special {
+    context
+      .state
+      .special_file_count
+      .fetch_add(1, Ordering::Relaxed);
+    if !context.options.include_special_sizes {
+      path_info.size_bytes = 0;
+      path_info.size_allocated_bytes = 0;
+    }
+  }
+  if context.options.counts_only {
+    path_info.size_bytes = 0;
+    path_info.size_allocated_bytes = 0;
+  }
+
+  // Count entry as file or directory, symlinks and special files count as entries but not
+  // as files or dirs
   let entry_count = 1; // Count this file/directory/symlink as 1 entry
-  let file_count = if path_info.is_dir || is_symlink { 0 } else { 1 };
+  let file_count = if path_info.is_dir || is_symlink || is_special {
+    0
+  } else {
+    1
+  };
   let directory_count = if path_info.is_dir && !is_symlink {
     1
   } else {
     0
   };
 
+  // Sniff the file's type from its contents. This runs on the entry's own rayon task, so
+  // the reads happen in parallel like the rest of the scan
+  let content_type = if context.options.detect_content_type
+    && path_info.is_file
+    && !is_special
+    && path_info.size_bytes >= CONTENT_TYPE_MIN_SIZE
+  {
+    detect_content_type(&path)
+  } else {
+    None
+  };
+
   // Add entry to analytics map with initial values (will be updated later for directories)
-  let _entry_analytics = match analytics_map.entry(path.to_path_buf()) {
-    dashmap::mapref::entry::Entry::Occupied(e) => e.get().clone(),
-    dashmap::mapref::entry::Entry::Vacant(e) => {
-      let analytics = Arc::new(AnalyticsInfo {
-        path: path.to_path_buf(),
+  context
+    .state
+    .analytics_map
+    .entry(path.clone())
+    .or_insert_with(|| {
+      Arc::new(AnalyticsInfo {
+        path: path.clone(),
         size_bytes: path_info.size_bytes,
         size_allocated_bytes: path_info.size_allocated_bytes,
+        unique_bytes: if is_repeat_hard_link {
+          0
+        } else {
+          path_info.size_bytes
+        },
+        unique_allocated_bytes: if is_repeat_hard_link {
+          0
+        } else {
+          path_info.size_allocated_bytes
+        },
         entry_count,
         file_count,
         directory_count,
+        max_child_depth: 0,
+        skipped_children: 0,
         last_modified_time: path_info.times.0 as u64,
         owner_name: path_info.owner_name.clone(),
         path_info: Some(path_info.clone()),
-      });
-      e.insert(analytics.clone());
-      analytics
+        content_type,
+        link_target: if is_symlink {
+          std::fs::read_link(&path).ok()
+        } else {
+          None
+        },
+        content_hash: None,
+      })
+    });
+  context.state.record_scanned(path_info.size_bytes);
+
+  // Files and unfollowed links are done, only directories have children to wait for
+  if !path_info.is_dir || (is_symlink && !follow_link) {
+    if depth == 1 {
+      emit_partial_node(context, &path);
     }
-  };
+    finish_child(context, parent);
+    return;
+  }
 
-  // For directories, process all children (but don't follow symlinks)
-  if path_info.is_dir && !is_symlink {
-    // Read directory entries
-    let entries = match std::fs::read_dir(&path) {
-      Ok(dir_entries) => {
-        let mut entry_paths = Vec::with_capacity(32); // Pre-allocate for common case
-        for entry_result in dir_entries {
-          if let Ok(entry) = entry_result {
-            entry_paths.push(entry.path());
-          }
+  // Read directory entries, unless this directory sits at the depth limit
+  let at_max_depth = context
+    .options
+    .max_depth
+    .is_some_and(|max_depth| depth >= max_depth);
+  let (children, prefetched): (Vec<_>, Vec<_>) = if at_max_depth {
+    (Vec::new(), Vec::new())
+  } else {
+    match std::fs::read_dir(&path) {
+      Ok(dir_entries) => dir_entries
+        .flatten()
+        .map(|entry| (entry.path(), PrefetchedEntry::from_dir_entry(&entry)))
+        .unzip(),
+      Err(e) => {
+        // Record denied directories so a partial result can be explained to the user
+        if e.kind() == std::io::ErrorKind::PermissionDenied {
+          context
+            .state
+            .errors
+            .insert(path.clone(), PERMISSION_DENIED_REASON.to_string());
         }
-        entry_paths
+        (Vec::new(), Vec::new())
       }
-      Err(_) => Vec::new(),
-    };
+    }
+  };
 
-    // Process all children in parallel using Rayon
-    entries.par_iter().for_each(|child_path| {
-      let _ = calculate_size_sync(
-        child_path,
-        analytics_map.clone(),
-        target_dir_path,
-        visited_inodes.clone(),
-        processed_paths.clone(),
-      );
-    });
+  if depth == 0 {
+    emit_preview(context, &path, &path_info, &children);
+  }
 
-    // Now compute the total size based on children
-    let dir_own_size = path_info.size_bytes; // Start with directory's own size
-    let dir_own_allocated_size = path_info.size_allocated_bytes; // Start with directory's own allocated size
-    let mut total_size = dir_own_size;
-    let mut total_allocated_size = dir_own_allocated_size;
-    let mut total_entries = 1; // Start with the directory itself
-    let mut total_files = 0; // Directories don't count as files
-    let mut total_dirs = 1; // Count this directory
+  // One extra count guards against the directory completing while children are still
+  // being spawned; it is released right after the spawn loop
+  let directory = Arc::new(PendingDirectory {
+    path,
+    path_info,
+    depth,
+    remaining: AtomicUsize::new(children.len() + 1),
+    children,
+    skipped: AtomicU32::new(0),
+    parent,
+  });
 
-    // Sum up all children's contributions
-    for child_path in &entries {
-      // Check if the child is a direct file (not a symlink pointing to a file)
-      let child_is_file = child_path.is_file() && !child_path.is_symlink();
+  // Process all children in parallel using Rayon
+  for (child_path, prefetched) in directory.children.iter().zip(prefetched) {
+    let child_path = child_path.clone();
+    let directory = directory.clone();
+    scope.spawn(move |scope| {
+      scan_path(
+        scope,
+        context,
+        child_path,
+        Some(prefetched),
+        depth + 1,
+        Some(directory),
+      )
+    });
+  }
 
-      // Update counts for direct files first
-      if child_is_file {
-        total_files += 1;
-        total_entries += 1;
-      }
+  finish_child(context, Some(directory));
+}
 
-      // Get analytics info for the child if it exists
-      if let Some(child_analytics) = analytics_map.get(child_path) {
-        let child_size = child_analytics.size_bytes;
-        let child_allocated_size = child_analytics.size_allocated_bytes;
-        let child_entries = child_analytics.entry_count;
-        let child_files = child_analytics.file_count;
-        let child_dirs = child_analytics.directory_count;
+type TimeoutJob = Box<dyn FnOnce() + Send>;
 
-        total_size += child_size;
-        total_allocated_size += child_allocated_size;
+// Threads that run_with_timeout hands its calls to
+const TIMEOUT_POOL_THREADS: usize = 16;
 
-        // For symlinks, count the entry but not as file/dir
-        if child_path.is_symlink() {
-          total_entries += 1; // Count the symlink as an entry
-        } else {
-          // For non-symlinks, add all the counts
-          if !child_is_file {
-            // Avoid double counting files we already counted
-            total_entries += child_entries;
-            total_files += child_files;
-          }
-          total_dirs += child_dirs;
+lazy_static! {
+  // Queue of the fixed pool of helper threads, started on first use. A call that hangs
+  // (e.g. on an unresponsive network share) ties up its thread for good, but never adds one:
+  // once every thread hangs, later calls wait in the queue and time out instead
+  static ref TIMEOUT_POOL: std::sync::mpsc::Sender<TimeoutJob> = {
+    let (sender, receiver) = std::sync::mpsc::channel::<TimeoutJob>();
+    let receiver = Arc::new(Mutex::new(receiver));
+    for _ in 0..TIMEOUT_POOL_THREADS {
+      let receiver = receiver.clone();
+      std::thread::spawn(move || loop {
+        // Only the wait for a job holds the lock, the job runs without it
+        let job = match receiver.lock() {
+          Ok(receiver) => receiver.recv(),
+          Err(_) => return,
+        };
+        match job {
+          Ok(job) => job(),
+          Err(_) => return,
         }
-      }
-    }
-
-    // Get a mutable reference to modify the Arc<AnalyticsInfo>
-    if let Some(mut analytics_ref) = analytics_map.get_mut(&path.to_path_buf()) {
-      // Access and modify the inner AnalyticsInfo fields
-      let analytics = Arc::make_mut(&mut analytics_ref);
-
-      // Update this directory's values
-      analytics.size_bytes = total_size;
-      analytics.size_allocated_bytes = total_allocated_size;
-      analytics.entry_count = total_entries;
-      analytics.file_count = total_files;
-      analytics.directory_count = total_dirs;
+      });
     }
-  }
+    sender
+  };
+}
 
-  Ok(())
+// Runs `f` on a helper thread and waits at most `timeout` for it, including any wait for a
+// free thread. A call that never returns leaves its thread blocked, but the caller moves on
+fn run_with_timeout<T, F>(timeout: Duration, f: F) -> Option<T>
+where
+  T: Send + 'static,
+  F: FnOnce() -> T + Send + 'static,
+{
+  let (sender, receiver) = std::sync::mpsc::channel();
+  TIMEOUT_POOL
+    .send(Box::new(move || {
+      // The receiver is gone if we already gave up, so the result is just dropped
+      let _ = sender.send(f());
+    }))
+    .ok()?;
+  receiver.recv_timeout(timeout).ok()
 }
 
-// This function converts the analytics map to a vector of AnalyticsInfo objects
-fn analytics_map_to_entries(map: &DashMap<PathBuf, Arc<AnalyticsInfo>>) -> Vec<Arc<AnalyticsInfo>> {
-  map
-    .iter()
-    .map(|item| item.value().clone()) // Just clone the Arc pointer, not the inner data
-    .collect()
+// Checks the scan options for whether to follow a link, depending on what kind of link it is
+fn should_follow_link(path: &Path, path_info: &PathInfo, options: &ScanOptions) -> bool {
+  match platform::get_link_kind(path, path_info.is_dir) {
+    platform::LinkKind::FileSymlink => options.follow_file_symlinks,
+    platform::LinkKind::DirSymlink => options.follow_dir_symlinks,
+    platform::LinkKind::Junction => options.follow_junctions,
+  }
 }
 
-// This function builds a tree from the flat list of entries with a limited depth
-fn build_tree_from_entries_with_depth(
-  entries: &[Arc<AnalyticsInfo>],
-  root_path: &Path,
-  max_depth: usize,
-  // Whether to build a virtual directory node for the root path
-  // that contains all the children(files, no any dirs) of the root path
-  // it is just a analytics node, not a real node in the file system
-  // for example:
-  // root_path: /Users/username/Documents
-  // /Users/username/Documents/file1.txt
-  // /Users/username/Documents/file2.txt
-  // /Users/username/Documents/file3.txt
-  // /Users/username/Documents/subdir/
-  // will be a virtual directory node with 3 children file1, file2, file3
-  // and will move file1, file2, file3 to the children of the virtual directory node
-  // should total size of the virtual directory node is the sum of the size of file1, file2, file3
-  build_virtual_directory_node: bool,
-) -> FileSystemTreeNode {
-  // Create a map of path -> entry for quick lookups
-  let path_map: HashMap<PathBuf, &Arc<AnalyticsInfo>> = entries
-    .iter()
-    .map(|entry| (entry.path.clone(), entry))
-    .collect();
+// Records a directory link whose target lies inside the scan as a minimal entry of the link
+// itself, pointing at the target. Returns false if the target is outside the scan and
+// has to be scanned through the link
+fn record_link_to_scanned_dir(context: &ScanContext, path: &Path, depth: usize) -> bool {
+  let Ok(target) = path.canonicalize() else {
+    return false;
+  };
+  if !target.starts_with(context.root) {
+    return false;
+  }
+  let Some(mut link_info) =
+    platform::get_path_info_with_allocation(path, false, context.options.compute_allocated)
+  else {
+    return false;
+  };
+  link_info.is_symlink = true;
+  if context.options.counts_only {
+    link_info.size_bytes = 0;
+    link_info.size_allocated_bytes = 0;
+  }
 
-  // Create a map of parent path -> child paths
-  let mut children_map: HashMap<PathBuf, Vec<PathBuf>> = HashMap::new();
+  context.state.record_scanned(link_info.size_bytes);
+  context.state.analytics_map.insert(
+    path.to_path_buf(),
+    Arc::new(AnalyticsInfo {
+      path: path.to_path_buf(),
+      size_bytes: link_info.size_bytes,
+      size_allocated_bytes: link_info.size_allocated_bytes,
+      unique_bytes: link_info.size_bytes,
+      unique_allocated_bytes: link_info.size_allocated_bytes,
+      entry_count: 1,
+      file_count: 0,
+      directory_count: 0,
+      max_child_depth: 0,
+      skipped_children: 0,
+      last_modified_time: link_info.times.0 as u64,
+      owner_name: link_info.owner_name.clone(),
+      path_info: Some(link_info),
+      content_type: None,
+      link_target: Some(target),
+      content_hash: None,
+    }),
+  );
+  if depth == 1 {
+    emit_partial_node(context, path);
+  }
+  true
+}
 
-  // Find the root node and build the parent-child relationships
-  let mut root_entry = None;
+// Copies a directory's subtree from the previous scan into this one, if every directory in it
+// still has the mtime it had back then. Returns whether the subtree was grafted
+fn graft_cached_subtree(context: &ScanContext, path: &Path, path_info: &PathInfo) -> bool {
+  let Some(previous) = context.state.previous_scan.as_deref() else {
+    return false;
+  };
+  let Some(&root_index) = previous.path_map.get(path) else {
+    return false;
+  };
 
-  for entry in entries {
-    if entry.path == root_path {
-      root_entry = Some(entry);
-      continue;
+  let mut subtree = Vec::new();
+  let mut stack = vec![root_index];
+  while let Some(index) = stack.pop() {
+    let entry = &previous.entries[index];
+    if is_directory_entry(entry) {
+      let modified_time = if entry.path == path {
+        path_info.times.0
+      } else {
+        match platform::get_path_info(&entry.path, false) {
+          Some(info) if info.is_dir => info.times.0,
+          _ => return false,
+        }
+      };
+      if modified_time as u64 != entry.last_modified_time {
+        return false;
+      }
+      if let Some(children) = previous.children_map.get(&entry.path) {
+        stack.extend(children.iter().copied());
+      }
     }
+    subtree.push(index);
+  }
 
-    if let Some(parent_path) = entry.path.parent().map(|p| p.to_path_buf()) {
-      children_map
-        .entry(parent_path)
-        .or_default()
-        .push(entry.path.clone());
+  for index in subtree {
+    let entry = &previous.entries[index];
+    context.state.processed_paths.insert(entry.path.clone());
+    if let Some(inode_pair) = entry.path_info.as_ref().and_then(|info| info.inode_device) {
+      context.state.visited_inodes.insert(inode_pair);
     }
+    context
+      .state
+      .analytics_map
+      .insert(entry.path.clone(), entry.clone());
+    context
+      .state
+      .record_scanned(entry.path_info.as_ref().map_or(0, |info| info.size_bytes));
   }
+  true
+}
 
-  // Use the first entry if root not found
-  let root_entry = root_entry.unwrap_or_else(|| {
+// Looks up the volume containing a path, querying each volume once
+fn get_cached_volume_info(
+  context: &ScanContext,
+  path: &Path,
+  path_info: &PathInfo,
+) -> Option<VolumeInfo> {
+  let volume_key = platform::get_volume_key(path, path_info)?;
+  if let Some(volume) = context.volumes.get(&volume_key) {
+    return Some(*volume);
+  }
+
+  let volume = VolumeInfo {
+    cluster_size: platform::get_cluster_size(path),
+    is_fat: platform::get_filesystem_name(path)
+      .is_some_and(|name| platform::is_fat_filesystem(&name)),
+    capacity: platform::get_space_info(path).map(|(total, _, _)| total),
+  };
+  context.volumes.insert(volume_key, volume);
+  Some(volume)
+}
+
+// Applies cluster rounding to a file's allocated size where the scan or volume calls for it
+fn adjusted_allocated_size(allocated: u64, volume: &VolumeInfo, rounding: RoundingMode) -> u64 {
+  match volume.cluster_size {
+    Some(cluster_size) if rounding == RoundingMode::ClusterUp || volume.is_fat => {
+      round_up_to_cluster(allocated, cluster_size)
+    }
+    _ => allocated,
+  }
+}
+
+// Limits a file's size to the capacity of its volume, logging when the reported size is bogus
+fn clamp_to_capacity(path: &Path, size: u64, capacity: Option<u64>) -> u64 {
+  match capacity {
+    Some(capacity) if size > capacity => {
+      eprintln!(
+        "Clamping size of {} from {} to the volume capacity {}",
+        path.display(),
+        size,
+        capacity
+      );
+      capacity
+    }
+    _ => size,
+  }
+}
+
+// Rounds a size up to a whole number of clusters
+fn round_up_to_cluster(size: u64, cluster_size: u64) -> u64 {
+  if cluster_size == 0 {
+    return size;
+  }
+  size.div_ceil(cluster_size).saturating_mul(cluster_size)
+}
+
+// Checks whether a path's name is in the scan's exclude list
+fn is_excluded(path: &Path, options: &ScanOptions) -> bool {
+  if options.exclude_names.is_empty() {
+    return false;
+  }
+  path
+    .file_name()
+    .and_then(|n| n.to_str())
+    .is_some_and(|name| {
+      options
+        .exclude_names
+        .iter()
+        .any(|excluded| excluded == name)
+    })
+}
+
+// Marks one child of `parent` as finished without anything recorded for it, so the parent's
+// totals are known to be incomplete
+fn skip_child(context: &ScanContext, parent: Option<Arc<PendingDirectory>>) {
+  if let Some(directory) = &parent {
+    directory.skipped.fetch_add(1, Ordering::Relaxed);
+  }
+  finish_child(context, parent);
+}
+
+// Marks one child of `parent` as finished, aggregating every ancestor whose children
+// have now all completed
+fn finish_child(context: &ScanContext, parent: Option<Arc<PendingDirectory>>) {
+  let mut current = parent;
+  while let Some(directory) = current {
+    if directory.remaining.fetch_sub(1, Ordering::AcqRel) != 1 {
+      return;
+    }
+
+    aggregate_directory(context, &directory);
+    if directory.depth == 1 {
+      emit_partial_node(context, &directory.path);
+    }
+    current = directory.parent.clone();
+  }
+}
+
+// Sends the scan root's direct children to the observer before any of them is measured
+fn emit_preview(context: &ScanContext, path: &Path, path_info: &PathInfo, children: &[PathBuf]) {
+  let Some(observer) = context.observer else {
+    return;
+  };
+
+  let children = children
+    .iter()
+    .map(|child_path| {
+      let is_dir = std::fs::symlink_metadata(child_path).is_ok_and(|m| m.is_dir());
+      FileSystemTreeNode {
+        path: child_path.clone(),
+        name: node_name(child_path),
+        entry_count: 1,
+        file_count: if is_dir { 0 } else { 1 },
+        directory_count: if is_dir { 1 } else { 0 },
+        file_kind_hint: file_kind_hint(child_path, is_dir, child_path.is_symlink()),
+        // Filled in with the link's target once the child itself is scanned
+        is_symlink: child_path.is_symlink(),
+        ..Default::default()
+      }
+    })
+    .collect();
+
+  observer(ScanEvent::Preview(FileSystemTreeNode {
+    path: path.to_path_buf(),
+    name: node_name(path),
+    entry_count: 1,
+    directory_count: 1,
+    percent_of_parent: 100.0,
+    percent_of_parent_allocated: 100.0,
+    last_modified_time: path_info.times.0 as u64,
+    owner_name: path_info.owner_name.clone(),
+    children,
+    file_kind_hint: "folder".to_string(),
+    ..Default::default()
+  }));
+}
+
+// Sends a finished direct child of the scan root to the observer
+// Its percentage of the root is unknown until the whole scan completes, so it is left at 0
+fn emit_partial_node(context: &ScanContext, path: &Path) {
+  let Some(observer) = context.observer else {
+    return;
+  };
+  let Some(entry) = context.state.analytics_map.get(path).map(|e| e.clone()) else {
+    return;
+  };
+
+  observer(ScanEvent::PartialNode(FileSystemTreeNode {
+    percent_of_parent: 0.0,
+    percent_of_parent_allocated: 0.0,
+    ..FileSystemTreeNode::from_entry(&entry)
+  }));
+}
+
+// Computes a directory's totals from its children, which have all finished scanning
+fn aggregate_directory(context: &ScanContext, directory: &PendingDirectory) {
+  let analytics_map = &context.state.analytics_map;
+
+  // Now compute the total size based on children
+  let dir_own_size = directory.path_info.size_bytes; // Start with directory's own size
+  let dir_own_allocated_size = directory.path_info.size_allocated_bytes; // Start with directory's own allocated size
+  let mut total_size = dir_own_size;
+  let mut total_allocated_size = dir_own_allocated_size;
+  let mut total_unique_size = dir_own_size;
+  let mut total_unique_allocated_size = dir_own_allocated_size;
+  let mut total_entries = 1; // Start with the directory itself
+  let mut total_files = 0; // Directories don't count as files
+  let mut total_dirs = 1; // Count this directory
+  let mut max_child_depth = 0; // Files and empty directories end right here
+
+  // Sum up all children's contributions
+  for child_path in &directory.children {
+    // Get analytics info for the child if it exists, children that were skipped (e.g. by
+    // a cancelled scan) count for nothing
+    if let Some(child_analytics) = analytics_map.get(child_path) {
+      // The child's type was recorded when it was scanned, asking the filesystem again would
+      // cost a stat per child. A direct file is one that isn't a symlink pointing to a file
+      let (child_is_file, child_is_symlink) = child_analytics
+        .path_info
+        .as_ref()
+        .map_or((false, false), |info| {
+          (info.is_file && !info.is_symlink, info.is_symlink)
+        });
+
+      // Update counts for direct files first
+      if child_is_file {
+        total_files += 1;
+        total_entries += 1;
+      }
+
+      let child_size = child_analytics.size_bytes;
+      let child_allocated_size = child_analytics.size_allocated_bytes;
+      let child_entries = child_analytics.entry_count;
+      let child_files = child_analytics.file_count;
+      let child_dirs = child_analytics.directory_count;
+
+      // A symlink is one level down, whatever it points to
+      let child_depth = if child_is_symlink {
+        0
+      } else {
+        child_analytics.max_child_depth
+      };
+      max_child_depth = max_child_depth.max(child_depth + 1);
+
+      // Saturate rather than overflow if a bogus size slipped through
+      total_size = total_size.saturating_add(child_size);
+      total_allocated_size = total_allocated_size.saturating_add(child_allocated_size);
+      total_unique_size = total_unique_size.saturating_add(child_analytics.unique_bytes);
+      total_unique_allocated_size =
+        total_unique_allocated_size.saturating_add(child_analytics.unique_allocated_bytes);
+
+      // For symlinks, count the entry but not as file/dir
+      if child_is_symlink {
+        total_entries += 1; // Count the symlink as an entry
+      } else {
+        // For non-symlinks, add all the counts
+        if !child_is_file {
+          // Avoid double counting files we already counted
+          total_entries += child_entries;
+          total_files += child_files;
+        }
+        total_dirs += child_dirs;
+      }
+    }
+  }
+
+  // Get a mutable reference to modify the Arc<AnalyticsInfo>
+  if let Some(mut analytics_ref) = analytics_map.get_mut(&directory.path) {
+    // Access and modify the inner AnalyticsInfo fields
+    let analytics = Arc::make_mut(&mut analytics_ref);
+
+    // Update this directory's values
+    analytics.size_bytes = total_size;
+    analytics.size_allocated_bytes = total_allocated_size;
+    analytics.unique_bytes = total_unique_size;
+    analytics.unique_allocated_bytes = total_unique_allocated_size;
+    analytics.entry_count = total_entries;
+    analytics.file_count = total_files;
+    analytics.directory_count = total_dirs;
+    analytics.max_child_depth = max_child_depth;
+    analytics.skipped_children = directory.skipped.load(Ordering::Relaxed);
+  }
+
+  // Files below the recording threshold only live on in the totals just computed
+  let min_record_size = context.options.min_record_size;
+  if min_record_size > 0 {
+    for child_path in &directory.children {
+      analytics_map.remove_if(child_path, |_, child| {
+        // Only regular files count one file and no directories
+        child.file_count == 1 && child.directory_count == 0 && child.size_bytes < min_record_size
+      });
+    }
+  }
+}
+
+// This function converts the analytics map to a vector of AnalyticsInfo objects
+fn analytics_map_to_entries(map: &DashMap<PathBuf, Arc<AnalyticsInfo>>) -> Vec<Arc<AnalyticsInfo>> {
+  map
+    .iter()
+    .map(|item| item.value().clone()) // Just clone the Arc pointer, not the inner data
+    .collect()
+}
+
+// Largest first, with equal sizes ordered by path so repeated builds come out identical
+fn by_size_then_path(a: &FileSystemTreeNode, b: &FileSystemTreeNode) -> std::cmp::Ordering {
+  b.size_bytes
+    .cmp(&a.size_bytes)
+    .then_with(|| a.path.cmp(&b.path))
+}
+
+// This function builds a tree from the flat list of entries with a limited depth
+fn build_tree_from_entries_with_depth(
+  entries: &[Arc<AnalyticsInfo>],
+  root_path: &Path,
+  max_depth: usize,
+  // Whether to build a virtual directory node for the root path
+  // that contains all the children(files, no any dirs) of the root path
+  // it is just a analytics node, not a real node in the file system
+  // for example:
+  // root_path: /Users/username/Documents
+  // /Users/username/Documents/file1.txt
+  // /Users/username/Documents/file2.txt
+  // /Users/username/Documents/file3.txt
+  // /Users/username/Documents/subdir/
+  // will be a virtual directory node with 3 children file1, file2, file3
+  // and will move file1, file2, file3 to the children of the virtual directory node
+  // should total size of the virtual directory node is the sum of the size of file1, file2, file3
+  build_virtual_directory_node: bool,
+) -> FileSystemTreeNode {
+  // Create a map of path -> entry for quick lookups
+  let path_map: HashMap<PathBuf, &Arc<AnalyticsInfo>> = entries
+    .iter()
+    .map(|entry| (entry.path.clone(), entry))
+    .collect();
+
+  // Create a map of parent path -> child paths
+  let mut children_map: HashMap<PathBuf, Vec<PathBuf>> = HashMap::new();
+
+  // Find the root node and build the parent-child relationships
+  let mut root_entry = None;
+
+  for entry in entries {
+    if entry.path == root_path {
+      root_entry = Some(entry);
+      continue;
+    }
+
+    if let Some(parent_path) = entry.path.parent().map(|p| p.to_path_buf()) {
+      children_map
+        .entry(parent_path)
+        .or_default()
+        .push(entry.path.clone());
+    }
+  }
+
+  // Use the first entry if root not found
+  let root_entry = root_entry.unwrap_or_else(|| {
     entries.first().unwrap_or_else(|| {
       panic!("No entries found to build tree");
     })
@@ -316,39 +1390,31 @@ fn build_tree_from_entries_with_depth(
         }
 
         // Sort children by size (largest first)
-        children.sort_by(|a, b| b.size_bytes.cmp(&a.size_bytes));
+        children.sort_by(by_size_then_path);
       }
     }
 
     // Calculate percentages for children
     let parent_size = entry.size_bytes;
     for child in &mut children {
-      if parent_size > 0 {
-        child.percent_of_parent = (child.size_bytes as f64 / parent_size as f64) * 100.0;
-      } else {
-        child.percent_of_parent = 0.0;
-      }
+      child.percent_of_parent = percent_of(child.size_bytes, parent_size);
+      child.percent_of_parent_allocated =
+        percent_of(child.size_allocated_bytes, entry.size_allocated_bytes);
     }
 
     FileSystemTreeNode {
-      path: entry.path.clone(),
       name,
-      size_bytes: entry.size_bytes,
-      size_allocated_bytes: entry.size_allocated_bytes,
-      entry_count: entry.entry_count,
-      file_count: entry.file_count,
-      directory_count: entry.directory_count,
-      percent_of_parent: 100.0, // Default value, will be updated by parent
-      last_modified_time: entry.last_modified_time,
-      owner_name: entry.owner_name.clone(),
       children,
-      is_virtual_directory: false,
+      ..FileSystemTreeNode::from_entry(entry)
     }
   }
 
   // If we're not building a virtual directory node, build the tree normally
-  // not build a virtual directory node if no files in the root path
-  if !build_virtual_directory_node || (build_virtual_directory_node && root_entry.file_count == 0) {
+  // not build a virtual directory node if no files in the root path, or the root is a file
+  if !build_virtual_directory_node
+    || (build_virtual_directory_node && root_entry.file_count == 0)
+    || is_file_entry(root_entry)
+  {
     // Build the tree starting from the root with depth limit
     return build_node(
       &root_entry.path,
@@ -396,15 +1462,13 @@ fn build_tree_from_entries_with_depth(
   }
 
   // Sort virtual directory children by size (largest first)
-  virtual_dir_children.sort_by(|a, b| b.size_bytes.cmp(&a.size_bytes));
+  virtual_dir_children.sort_by(by_size_then_path);
 
   // Calculate percentages for virtual directory children
   for child in &mut virtual_dir_children {
-    if virtual_dir_size_bytes > 0 {
-      child.percent_of_parent = (child.size_bytes as f64 / virtual_dir_size_bytes as f64) * 100.0;
-    } else {
-      child.percent_of_parent = 0.0;
-    }
+    child.percent_of_parent = percent_of(child.size_bytes, virtual_dir_size_bytes);
+    child.percent_of_parent_allocated =
+      percent_of(child.size_allocated_bytes, virtual_dir_size_allocated_bytes);
   }
 
   // Create the virtual directory node
@@ -426,22 +1490,20 @@ fn build_tree_from_entries_with_depth(
   let virtual_display_name = format!("[{} Files]", virtual_dir_file_count);
 
   let virtual_dir_node = FileSystemTreeNode {
-    path: virtual_dir_path,
-    name: virtual_display_name,
     size_bytes: virtual_dir_size_bytes,
     size_allocated_bytes: virtual_dir_size_allocated_bytes,
     entry_count: virtual_dir_entry_count,
     file_count: virtual_dir_file_count,
-    directory_count: 0, // Virtual directory is not a real directory
-    percent_of_parent: if root_entry.size_bytes > 0 {
-      (virtual_dir_size_bytes as f64 / root_entry.size_bytes as f64) * 100.0
-    } else {
-      0.0
-    },
+    max_child_depth: 1,
+    percent_of_parent: percent_of(virtual_dir_size_bytes, root_entry.size_bytes),
+    percent_of_parent_allocated: percent_of(
+      virtual_dir_size_allocated_bytes,
+      root_entry.size_allocated_bytes,
+    ),
     last_modified_time: root_entry.last_modified_time,
     owner_name: root_entry.owner_name.clone(),
     children: virtual_dir_children,
-    is_virtual_directory: true,
+    ..FileSystemTreeNode::synthetic(virtual_dir_path, virtual_display_name)
   };
 
   // Now build the main tree but exclude the files that are in the virtual directory
@@ -461,17 +1523,13 @@ fn build_tree_from_entries_with_depth(
   main_tree.children.push(virtual_dir_node);
 
   // Resort the main tree's children by size
-  main_tree
-    .children
-    .sort_by(|a, b| b.size_bytes.cmp(&a.size_bytes));
+  main_tree.children.sort_by(by_size_then_path);
 
   // Update percentages for all children
   for child in &mut main_tree.children {
-    if main_tree.size_bytes > 0 {
-      child.percent_of_parent = (child.size_bytes as f64 / main_tree.size_bytes as f64) * 100.0;
-    } else {
-      child.percent_of_parent = 0.0;
-    }
+    child.percent_of_parent = percent_of(child.size_bytes, main_tree.size_bytes);
+    child.percent_of_parent_allocated =
+      percent_of(child.size_allocated_bytes, main_tree.size_allocated_bytes);
   }
 
   main_tree
@@ -519,27 +1577,12 @@ fn build_tree_from_indices(
           let child_entry = &entries[child_idx];
 
           let child_node = FileSystemTreeNode {
-            path: child_entry.path.clone(),
-            name: child_entry
-              .path
-              .file_name()
-              .and_then(|n| n.to_str())
-              .unwrap_or("unknown")
-              .to_string(),
-            size_bytes: child_entry.size_bytes,
-            size_allocated_bytes: child_entry.size_allocated_bytes,
-            entry_count: child_entry.entry_count,
-            file_count: child_entry.file_count,
-            directory_count: child_entry.directory_count,
-            percent_of_parent: if entry.size_bytes > 0 {
-              (child_entry.size_bytes as f64 / entry.size_bytes as f64) * 100.0
-            } else {
-              0.0
-            },
-            last_modified_time: child_entry.last_modified_time,
-            owner_name: child_entry.owner_name.clone(),
-            children: Vec::new(), // No need to build children of children here
-            is_virtual_directory: false,
+            percent_of_parent: percent_of(child_entry.size_bytes, entry.size_bytes),
+            percent_of_parent_allocated: percent_of(
+              child_entry.size_allocated_bytes,
+              entry.size_allocated_bytes,
+            ),
+            ..FileSystemTreeNode::from_entry(child_entry)
           };
 
           children.push(child_node);
@@ -550,24 +1593,17 @@ fn build_tree_from_indices(
     }
 
     FileSystemTreeNode {
-      path: entry.path.clone(),
       name,
-      size_bytes: entry.size_bytes,
-      size_allocated_bytes: entry.size_allocated_bytes,
-      entry_count: entry.entry_count,
-      file_count: entry.file_count,
-      directory_count: entry.directory_count,
-      percent_of_parent: 100.0, // Default value, will be updated by parent
-      last_modified_time: entry.last_modified_time,
-      owner_name: entry.owner_name.clone(),
       children,
-      is_virtual_directory: false,
+      ..FileSystemTreeNode::from_entry(entry)
     }
   }
 
   // If not building a virtual directory node, just build the tree normally
-  // not build a virtual directory node if no files in the target path
-  if !build_virtual_directory_node || (build_virtual_directory_node && target_entry.file_count == 0)
+  // not build a virtual directory node if no files in the target path, or the target is a file
+  if !build_virtual_directory_node
+    || (build_virtual_directory_node && target_entry.file_count == 0)
+    || is_file_entry(target_entry)
   {
     // Build the tree node
     let children_indices = children_map.get(target_path);
@@ -629,23 +1665,10 @@ fn build_tree_from_indices(
 
       // Create a node for each file
       let file_node = FileSystemTreeNode {
-        path: file_entry.path.clone(),
-        name: file_entry
-          .path
-          .file_name()
-          .and_then(|n| n.to_str())
-          .unwrap_or("unknown")
-          .to_string(),
-        size_bytes: file_entry.size_bytes,
-        size_allocated_bytes: file_entry.size_allocated_bytes,
-        entry_count: file_entry.entry_count,
-        file_count: file_entry.file_count,
         directory_count: 0,
         percent_of_parent: 0.0, // Will be updated later
-        last_modified_time: file_entry.last_modified_time,
-        owner_name: file_entry.owner_name.clone(),
-        children: Vec::new(),
-        is_virtual_directory: false,
+        percent_of_parent_allocated: 0.0,
+        ..FileSystemTreeNode::from_entry(file_entry)
       };
 
       // Update virtual directory stats
@@ -656,13 +1679,13 @@ fn build_tree_from_indices(
     }
 
     // Sort virtual directory children by size (largest first)
-    virtual_dir_children.sort_by(|a, b| b.size_bytes.cmp(&a.size_bytes));
+    virtual_dir_children.sort_by(by_size_then_path);
 
     // Update percentages for virtual directory children
     for child in &mut virtual_dir_children {
-      if virtual_dir_size_bytes > 0 {
-        child.percent_of_parent = (child.size_bytes as f64 / virtual_dir_size_bytes as f64) * 100.0;
-      }
+      child.percent_of_parent = percent_of(child.size_bytes, virtual_dir_size_bytes);
+      child.percent_of_parent_allocated =
+        percent_of(child.size_allocated_bytes, virtual_dir_size_allocated_bytes);
     }
 
     // Extract the root directory name and append "Files" to it
@@ -683,771 +1706,9053 @@ fn build_tree_from_indices(
 
     // Create the virtual directory node
     let virtual_dir_node = FileSystemTreeNode {
-      path: virtual_dir_path,
-      name: virtual_display_name,
       size_bytes: virtual_dir_size_bytes,
       size_allocated_bytes: virtual_dir_size_allocated_bytes,
       entry_count: virtual_dir_entry_count,
       file_count: virtual_dir_file_count,
-      directory_count: 0,
-      percent_of_parent: if main_tree.size_bytes > 0 {
-        (virtual_dir_size_bytes as f64 / main_tree.size_bytes as f64) * 100.0
-      } else {
-        0.0
-      },
+      max_child_depth: 1,
+      percent_of_parent: percent_of(virtual_dir_size_bytes, main_tree.size_bytes),
+      percent_of_parent_allocated: percent_of(
+        virtual_dir_size_allocated_bytes,
+        main_tree.size_allocated_bytes,
+      ),
       last_modified_time: target_entry.last_modified_time,
       owner_name: target_entry.owner_name.clone(),
       children: virtual_dir_children,
-      is_virtual_directory: true,
+      ..FileSystemTreeNode::synthetic(virtual_dir_path, virtual_display_name)
     };
 
     // Add the virtual directory as a child of the main tree
     main_tree.children.push(virtual_dir_node);
 
     // Re-sort the main tree's children by size
-    main_tree
-      .children
-      .sort_by(|a, b| b.size_bytes.cmp(&a.size_bytes));
+    main_tree.children.sort_by(by_size_then_path);
 
     // Update percentages for all children
     for child in &mut main_tree.children {
-      if main_tree.size_bytes > 0 {
-        child.percent_of_parent = (child.size_bytes as f64 / main_tree.size_bytes as f64) * 100.0;
-      }
+      child.percent_of_parent = percent_of(child.size_bytes, main_tree.size_bytes);
+      child.percent_of_parent_allocated =
+        percent_of(child.size_allocated_bytes, main_tree.size_allocated_bytes);
     }
   }
 
   Some(main_tree)
 }
 
-// Define a global cache to store scan results
-lazy_static! {
-  static ref GLOBAL_SCAN_CACHE: Mutex<Option<ScanCache>> = Mutex::new(None);
-}
-
-// Structure to hold cached scan data
-struct ScanCache {
-  root_path: PathBuf,
-  entries: Vec<Arc<AnalyticsInfo>>,
-  // Prebuilt indices for faster tree building
-  path_map: HashMap<PathBuf, usize>, // Maps path to index in entries
-  children_map: HashMap<PathBuf, Vec<usize>>, // Maps parent path to indices of children in entries
-}
-
-// New command to scan directory and return complete results at once
-#[tauri::command]
-async fn scan_directory_size(path: String, window: tauri::Window) -> Result<(), String> {
-  // Drop any previous resources before starting a new scan
-  tokio::task::yield_now().await;
-
-  // Clear the global cache first when starting a new scan
-  if let Ok(mut global_cache) = GLOBAL_SCAN_CACHE.lock() {
-    *global_cache = None;
-  }
-
-  let result = scan_directory_complete(path, window.clone()).await;
-
-  // Ensure we emit a complete event even on error to clean up frontend state
-  if result.is_err() {
-    // Try to emit completion event on error to ensure frontend cleans up
-    let _ = window.emit("scan-complete", ());
+// This function hides file children smaller than `min_display_size` from a built tree
+// and rolls them into a single "[others]" summary node, so totals stay unchanged
+// Directories (and virtual directory nodes) are never hidden by this filter
+fn apply_min_display_size(node: &mut FileSystemTreeNode, min_display_size: u64) {
+  if min_display_size == 0 {
+    return;
   }
 
-  match result {
-    Ok(_) => Ok(()),
-    Err(e) => Err(e.to_string()),
+  // Apply the filter to nested levels first (e.g. the virtual directory node's files)
+  for child in &mut node.children {
+    if !child.children.is_empty() {
+      apply_min_display_size(child, min_display_size);
+    }
   }
-}
-
-// Modified scan_directory_complete function to store results in global cache
-async fn scan_directory_complete(path: String, window: tauri::Window) -> std::io::Result<()> {
-  let start_time = std::time::Instant::now();
-
-  let target_dir = Path::new(&path).canonicalize()?;
-  let analytics_map = Arc::new(DashMap::new());
-  let visited_inodes = Arc::new(DashSet::new());
-  let processed_paths = Arc::new(DashSet::new());
 
-  // Run the calculation using tokio's spawn_blocking for CPU-intensive work
-  // This allows the expensive calculation to run without blocking other Tokio tasks
-  let analytics_map_clone = analytics_map.clone();
-  let target_dir_clone = target_dir.clone();
-  let scan_task = tokio::task::spawn_blocking(move || {
-    // Run the synchronous calculation using Rayon's parallel processing
-    calculate_size_sync(
-      target_dir_clone.as_path(),
-      analytics_map_clone.clone(),
-      target_dir_clone.as_path(),
-      visited_inodes,
-      processed_paths,
-    )
+  let mut others_size_bytes = 0;
+  let mut others_size_allocated_bytes = 0;
+  let mut others_count = 0;
+
+  node.children.retain(|child| {
+    let is_file = !child.is_virtual_directory && child.directory_count == 0;
+    if is_file && child.size_bytes < min_display_size {
+      others_size_bytes += child.size_bytes;
+      others_size_allocated_bytes += child.size_allocated_bytes;
+      others_count += 1;
+      false
+    } else {
+      true
+    }
   });
 
-  // Wait for calculation to complete and handle any errors
-  if let Err(e) = scan_task.await? {
-    eprintln!("Error during directory calculation: {}", e);
-    return Err(e);
+  if others_count == 0 {
+    return;
   }
 
-  // Calculate scan time
-  let elapsed_ms = start_time.elapsed().as_millis() as u64;
-
-  // Convert the analytics map to a vector of entries
-  let entries = analytics_map_to_entries(&analytics_map);
+  let others_node = FileSystemTreeNode {
+    size_bytes: others_size_bytes,
+    size_allocated_bytes: others_size_allocated_bytes,
+    entry_count: others_count,
+    file_count: others_count,
+    percent_of_parent: percent_of(others_size_bytes, node.size_bytes),
+    percent_of_parent_allocated: percent_of(others_size_allocated_bytes, node.size_allocated_bytes),
+    last_modified_time: node.last_modified_time,
+    owner_name: node.owner_name.clone(),
+    ..FileSystemTreeNode::synthetic(node.path.join("[others]"), "[others]".to_string())
+  };
 
-  // Build the initial tree from the entries with just a basic approach
-  // This will be quick and allows us to show results to the user without waiting for indexing
-  let tree = build_tree_from_entries_with_depth(&entries, &target_dir, 1, true);
+  node.children.push(others_node);
 
-  // Create the complete result object
-  let result = DirectoryScanResult {
-    root_path: target_dir.clone(),
-    tree: tree.clone(),
-    scan_time_ms: elapsed_ms,
-  };
+  // Re-sort the children by size so the summary node lands in its natural place
+  node
+    .children
+    .sort_by_key(|child| std::cmp::Reverse(child.size_bytes));
+}
 
-  // Send the complete result as a single event immediately
-  if let Err(e) = window.emit("scan-result", &result) {
-    eprintln!("Failed to emit scan result: {}", e);
+// Lists the files a scan with `min_record_size` left out of the cache as one
+// `[small files]` child, from whatever the directory's totals (`entry`) have that its own
+// size and its listed children don't
+fn add_unrecorded_files_node(node: &mut FileSystemTreeNode, entry: &AnalyticsInfo) {
+  let (mut size_bytes, mut size_allocated_bytes) = own_sizes(entry);
+  let (mut entry_count, mut file_count) = (1, 0);
+  for child in &node.children {
+    size_bytes += child.size_bytes;
+    size_allocated_bytes += child.size_allocated_bytes;
+    entry_count += child.entry_count;
+    file_count += child.file_count;
   }
-
-  // Also emit completion event for backward compatibility
-  if let Err(e) = window.emit("scan-complete", ()) {
-    eprintln!("Failed to emit completion event: {}", e);
+  let unrecorded_files = node.file_count.saturating_sub(file_count);
+  let unrecorded_bytes = node.size_bytes.saturating_sub(size_bytes);
+  if unrecorded_files == 0 {
+    return;
   }
+  let unrecorded_allocated_bytes = node
+    .size_allocated_bytes
+    .saturating_sub(size_allocated_bytes);
+
+  let small_files_node = FileSystemTreeNode {
+    size_bytes: unrecorded_bytes,
+    size_allocated_bytes: unrecorded_allocated_bytes,
+    entry_count: node.entry_count.saturating_sub(entry_count),
+    file_count: unrecorded_files,
+    percent_of_parent: percent_of(unrecorded_bytes, node.size_bytes),
+    percent_of_parent_allocated: percent_of(unrecorded_allocated_bytes, node.size_allocated_bytes),
+    last_modified_time: node.last_modified_time,
+    owner_name: node.owner_name.clone(),
+    ..FileSystemTreeNode::synthetic(node.path.join("[small files]"), "[small files]".to_string())
+  };
+  node.children.push(small_files_node);
+  node.children.sort_by(by_size_then_path);
+}
 
-  // Now that the user sees the results, build the indices in the background
-  // Clone what we need for the async task
-  let entries_clone = entries.clone();
-  let target_dir_clone = target_dir.clone();
+// Apparent and allocated size of an entry itself, without its children
+fn own_sizes(entry: &AnalyticsInfo) -> (u64, u64) {
+  entry
+    .path_info
+    .as_ref()
+    .map_or((0, 0), |info| (info.size_bytes, info.size_allocated_bytes))
+}
 
-  // Spawn a new async task to build the indices
-  tokio::spawn(async move {
-    // Clone values before passing to spawn_blocking to avoid ownership issues
-    let target_dir_for_indices = target_dir_clone.clone();
-    let entries_for_indices = entries_clone.clone();
+/// What a listed directory's own bytes (its metadata, not any child's) count towards
+/// - `Unattributed` leaves them out, so the children's percentages sum to a little under 100
+/// - `Node` lists them as a `[directory overhead]` child
+/// - `ChildrenOnly` takes the children's percentages of their own total instead
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Eq)]
+enum RootOverhead {
+  #[default]
+  Unattributed,
+  Node,
+  ChildrenOnly,
+}
 
-    // Use tokio's spawn_blocking to run CPU-intensive parallelized work
-    // This ensures we don't block the async runtime with CPU-bound work
-    let indices_result = tokio::task::spawn_blocking(move || {
-      build_indices(&entries_for_indices, &target_dir_for_indices)
-    })
-    .await;
-
-    // Process the result of the parallel work
-    if let Ok((path_map, children_map)) = indices_result {
-      // Store the results in the global cache
-      let cache = ScanCache {
-        root_path: target_dir_clone,
-        entries: entries_clone,
-        path_map,
-        children_map,
+// Makes a listed directory's children account for all of it, as chosen by `mode`
+fn apply_root_overhead(node: &mut FileSystemTreeNode, mode: RootOverhead) {
+  let children_size_bytes: u64 = node.children.iter().map(|child| child.size_bytes).sum();
+  let children_size_allocated_bytes: u64 = node
+    .children
+    .iter()
+    .map(|child| child.size_allocated_bytes)
+    .sum();
+  match mode {
+    RootOverhead::Unattributed => {}
+    RootOverhead::Node => {
+      let overhead_bytes = node.size_bytes.saturating_sub(children_size_bytes);
+      if overhead_bytes == 0 {
+        return;
+      }
+      let overhead_allocated_bytes = node
+        .size_allocated_bytes
+        .saturating_sub(children_size_allocated_bytes);
+
+      let overhead_node = FileSystemTreeNode {
+        size_bytes: overhead_bytes,
+        size_allocated_bytes: overhead_allocated_bytes,
+        percent_of_parent: percent_of(overhead_bytes, node.size_bytes),
+        percent_of_parent_allocated: percent_of(
+          overhead_allocated_bytes,
+          node.size_allocated_bytes,
+        ),
+        last_modified_time: node.last_modified_time,
+        owner_name: node.owner_name.clone(),
+        ..FileSystemTreeNode::synthetic(
+          node.path.join("[directory overhead]"),
+          "[directory overhead]".to_string(),
+        )
       };
-
-      // Update the global cache
-      if let Ok(mut global_cache) = GLOBAL_SCAN_CACHE.lock() {
-        *global_cache = Some(cache);
-      } else {
-        eprintln!("Failed to acquire lock on global cache");
+      node.children.push(overhead_node);
+      node.children.sort_by(by_size_then_path);
+    }
+    RootOverhead::ChildrenOnly => {
+      for child in &mut node.children {
+        child.percent_of_parent = percent_of(child.size_bytes, children_size_bytes);
+        child.percent_of_parent_allocated =
+          percent_of(child.size_allocated_bytes, children_size_allocated_bytes);
       }
-    } else {
-      eprintln!("Failed to build indices in background task");
     }
-  });
+  }
+}
 
-  Ok(())
+/// How children are ordered when a directory is listed
+/// - `Size` puts the largest apparent size first
+/// - `Slack` puts the most wasted space first (allocated minus apparent size), surfacing
+///   directories full of tiny files. Compressed files allocate less than their size and
+///   have no slack
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Eq)]
+enum SortKey {
+  #[default]
+  Size,
+  Slack,
 }
 
-// Function to build indices for faster tree building
-fn build_indices(
-  entries: &[Arc<AnalyticsInfo>],
-  target_dir: &Path,
-) -> (HashMap<PathBuf, usize>, HashMap<PathBuf, Vec<usize>>) {
-  // First pass: build path_map (map from path to index in entries) - parallelize this
-  let path_map = entries
-    .par_iter()
-    .enumerate()
-    .map(|(i, entry)| (entry.path.clone(), i))
-    .collect::<HashMap<_, _>>();
+// Space allocated on disk beyond the apparent size
+fn slack_bytes(node: &FileSystemTreeNode) -> u64 {
+  node.size_allocated_bytes.saturating_sub(node.size_bytes)
+}
 
-  // Second pass: build children_map
-  // This is harder to fully parallelize so we'll use a concurrent map
-  let children_map = DashMap::new();
+// Orders a built tree's children (and nested virtual directory nodes) by the given key
+// Trees are built sorted by size, and the sort is stable, so equal keys stay largest first
+fn sort_tree_children(node: &mut FileSystemTreeNode, sort_key: SortKey) {
+  for child in &mut node.children {
+    if !child.children.is_empty() {
+      sort_tree_children(child, sort_key);
+    }
+  }
 
-  // Populate the children map in parallel
-  entries.par_iter().enumerate().for_each(|(i, entry)| {
-    if let Some(parent_path) = entry.path.parent().map(|p| p.to_path_buf()) {
-      // Skip entries that are outside our target directory
-      if !parent_path.starts_with(target_dir) && parent_path != *target_dir {
-        return;
+  match sort_key {
+    SortKey::Size => node
+      .children
+      .sort_by_key(|child| std::cmp::Reverse(child.size_bytes)),
+    SortKey::Slack => node
+      .children
+      .sort_by_key(|child| std::cmp::Reverse(slack_bytes(child))),
+  }
+}
+
+// Display name of a file_kind_hint category
+fn kind_category_name(kind: &str) -> &'static str {
+  match kind {
+    "image" => "Images",
+    "video" => "Videos",
+    "audio" => "Audio",
+    "archive" => "Archives",
+    "code" => "Code",
+    "document" => "Documents",
+    "executable" => "Executables",
+    "symlink" => "Symlinks",
+    "folder" => "Folders",
+    _ => "Other",
+  }
+}
+
+// Splits the files of each `[N Files]` node among the tree's children into one virtual
+// node per kind (`[Images]`, `[Videos]`, ...), so long file lists can be drilled into by
+// category. Totals are unchanged, percentages are relative to the new parents
+fn group_virtual_files_by_kind(node: &mut FileSystemTreeNode) {
+  for files_node in node
+    .children
+    .iter_mut()
+    .filter(|child| child.is_virtual_directory && !child.children.is_empty())
+  {
+    let mut categories: Vec<FileSystemTreeNode> = Vec::new();
+    for file in std::mem::take(&mut files_node.children) {
+      let name = format!("[{}]", kind_category_name(&file.file_kind_hint));
+      let position = match categories.iter().position(|category| category.name == name) {
+        Some(position) => position,
+        None => {
+          categories.push(FileSystemTreeNode {
+            max_child_depth: 1,
+            owner_name: files_node.owner_name.clone(),
+            ..FileSystemTreeNode::synthetic(files_node.path.join(&name), name)
+          });
+          categories.len() - 1
+        }
+      };
+      let category = &mut categories[position];
+      category.size_bytes += file.size_bytes;
+      category.size_allocated_bytes += file.size_allocated_bytes;
+      category.entry_count += file.entry_count;
+      category.file_count += file.file_count;
+      category.last_modified_time = category.last_modified_time.max(file.last_modified_time);
+      category.children.push(file);
+    }
+
+    for category in &mut categories {
+      category.percent_of_parent = percent_of(category.size_bytes, files_node.size_bytes);
+      category.percent_of_parent_allocated = percent_of(
+        category.size_allocated_bytes,
+        files_node.size_allocated_bytes,
+      );
+      for file in &mut category.children {
+        file.percent_of_parent = percent_of(file.size_bytes, category.size_bytes);
+        file.percent_of_parent_allocated =
+          percent_of(file.size_allocated_bytes, category.size_allocated_bytes);
       }
+    }
+    categories.sort_by(by_size_then_path);
+    files_node.children = categories;
+  }
+}
 
-      // Use entry API on DashMap to safely add children indices
-      children_map
-        .entry(parent_path)
-        .or_insert_with(Vec::new)
-        .push(i);
+// Moves directories and virtual nodes ahead of the files among a tree's children
+// The sort is stable, so each group keeps its existing order and percentages are untouched
+fn group_directories_before_files(node: &mut FileSystemTreeNode) {
+  node
+    .children
+    .sort_by_key(|child| !child.is_virtual_directory && child.directory_count == 0);
+}
+
+// Define a global cache to store scan results
+// Readers clone the Arc and release the lock right away, so navigation requests never wait on
+// each other. Writers replace the cache, or copy it on write while readers hold a snapshot
+lazy_static! {
+  static ref GLOBAL_SCAN_CACHE: RwLock<Option<Arc<ScanCache>>> = RwLock::new(None);
+  // Root and state of the scan in progress, so commands can read it before it finishes
+  static ref CURRENT_SCAN: RwLock<Option<(PathBuf, Arc<ScanState>)>> = RwLock::new(None);
+  // Lifecycle phase of the latest scan, with the cache generation that set it
+  static ref SCAN_PHASE: Mutex<(u64, ScanPhase)> = Mutex::new((0, ScanPhase::Idle));
+  // Sizes recorded by snapshot_size, kept apart from the cache so they outlive re-scans
+  static ref SIZE_SNAPSHOTS: Mutex<HashMap<PathBuf, SizeSnapshot>> = Mutex::new(HashMap::new());
+  // Totals of each completed scan as (Unix timestamp in seconds, bytes, files) by root,
+  // oldest first, for get_size_trend. Like snapshots, they outlive re-scans and clears
+  static ref SCAN_HISTORY: Mutex<HashMap<PathBuf, Vec<SizeTrendPoint>>> =
+    Mutex::new(HashMap::new());
+  // Background task started by start_space_monitor, if one is running
+  static ref SPACE_MONITOR: Mutex<Option<tokio::task::JoinHandle<()>>> = Mutex::new(None);
+}
+
+/// Where the latest scan is in its lifecycle, returned by `get_scan_state` and sent as
+/// `scan-state-changed` events
+/// - `Idle`: nothing scanned yet, or the cache was cleared
+/// - `Scanning`: the scan is reading the filesystem
+/// - `BuildingIndex`: results are stored and browsable, indices are still being built
+/// - `Ready`: results are stored and indexed
+/// - `Cancelled`: like `Ready`, but the scan stopped at its deadline so results are partial
+/// - `Error`: the scan failed
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize)]
+enum ScanPhase {
+  #[default]
+  Idle,
+  Scanning,
+  BuildingIndex,
+  Ready,
+  Cancelled,
+  Error,
+}
+
+// Moves the lifecycle to `phase` on behalf of the scan or clear that started `generation`
+// Returns false, changing nothing, if a newer scan or clear has taken over since
+fn update_scan_phase(generation: u64, phase: ScanPhase) -> bool {
+  let Ok(mut current) = SCAN_PHASE.lock() else {
+    return false;
+  };
+  if generation < current.0 {
+    return false;
+  }
+  *current = (generation, phase);
+  true
+}
+
+// Same as update_scan_phase, and tells the frontend with a `scan-state-changed` event
+fn set_scan_phase(window: &tauri::Window, generation: u64, phase: ScanPhase) {
+  if !update_scan_phase(generation, phase) {
+    return;
+  }
+  if let Err(e) = window.emit("scan-state-changed", phase) {
+    eprintln!("Failed to emit scan state: {}", e);
+  }
+}
+
+// Bumped (under the cache lock) by every new scan and every clear, so a background index
+// build can tell whether the cache it is about to write has been superseded
+static CACHE_GENERATION: AtomicU64 = AtomicU64::new(0);
+
+// Cancellation token for index builds, tied to the cache generation they were started for
+// Starting a new scan or clearing the cache cancels every build from older generations
+#[derive(Clone, Copy, Debug)]
+struct IndexCancelToken {
+  generation: Option<u64>,
+}
+
+impl IndexCancelToken {
+  // A token for builds whose results only matter while `generation` is current
+  fn for_generation(generation: u64) -> Self {
+    IndexCancelToken {
+      generation: Some(generation),
     }
-  });
+  }
 
-  // Convert DashMap to regular HashMap for storage
-  let mut regular_children_map: HashMap<PathBuf, Vec<usize>> =
-    HashMap::with_capacity(children_map.len());
+  // A token for builds that always run to completion, e.g. for a reader that needs them now
+  fn never() -> Self {
+    IndexCancelToken { generation: None }
+  }
 
-  // Third pass: collect and sort children by size (largest first) for each parent
-  children_map
-    .into_iter()
-    .for_each(|(parent_path, mut indices)| {
-      // Sort indices by size (largest first) - this can be done in parallel for each entry
-      indices.par_sort_unstable_by(|&a, &b| {
-        let size_a = entries[a].size_bytes;
-        let size_b = entries[b].size_bytes;
-        size_b.cmp(&size_a) // Sort largest first
-      });
+  fn is_cancelled(&self) -> bool {
+    self
+      .generation
+      .is_some_and(|generation| CACHE_GENERATION.load(Ordering::SeqCst) != generation)
+  }
+}
 
-      regular_children_map.insert(parent_path, indices);
-    });
+// Error a scan returns when a newer scan or a clear took over while it ran
+const SCAN_SUPERSEDED: &str = "Scan was superseded by a newer scan";
 
-  (path_map, regular_children_map)
+// Whether a newer scan or a clear has started since `generation`
+fn is_superseded(generation: u64) -> bool {
+  CACHE_GENERATION.load(Ordering::SeqCst) != generation
 }
 
-// Updated get_directory_children function to use cached data
-#[tauri::command]
-async fn get_directory_children(path: String) -> Result<FileSystemTreeNode, String> {
-  // Access the global cache
+// Records the scan now in progress, cancelling the one it replaces: that scan's results
+// would be thrown away for its stale generation anyway, so it stops reading early
+fn begin_current_scan(root: PathBuf, state: Arc<ScanState>) {
+  if let Ok(mut current) = CURRENT_SCAN.write() {
+    if let Some((_, previous)) = current.replace((root, state)) {
+      previous.cancelled.store(true, Ordering::Relaxed);
+    }
+  }
+}
+
+// Starts a new cache generation and returns it, clearing the global cache unless a scan
+// is going to merge into it
+fn start_cache_generation(clear_cache: bool) -> Result<u64, String> {
+  let mut global_cache = GLOBAL_SCAN_CACHE
+    .write()
+    .map_err(|_| "Failed to acquire lock on global cache".to_string())?;
+  if clear_cache {
+    *global_cache = None;
+  }
+  Ok(CACHE_GENERATION.fetch_add(1, Ordering::SeqCst) + 1)
+}
+
+// Stores a finished scan in the global cache, unless a newer scan or a clear has happened
+// since `generation` was captured. Returns whether the cache was written
+fn store_scan_cache(mut cache: ScanCache, generation: u64) -> bool {
+  cache.generation = generation;
+  let Ok(mut global_cache) = GLOBAL_SCAN_CACHE.write() else {
+    eprintln!("Failed to acquire lock on global cache");
+    return false;
+  };
+  if CACHE_GENERATION.load(Ordering::SeqCst) != generation {
+    return false;
+  }
+  *global_cache = Some(Arc::new(cache));
+  true
+}
+
+// Keeps a failed scan from leaving cached data that navigation would attribute to it. A
+// merge drops whatever the cache held under the failed root, any other scan leaves no cache
+// at all. Nothing is touched once a newer scan or a clear has moved past `generation`
+fn discard_failed_scan(root: Option<&Path>, merge: bool, generation: u64) {
+  let Ok(mut global_cache) = GLOBAL_SCAN_CACHE.write() else {
+    eprintln!("Failed to acquire lock on global cache");
+    return;
+  };
+  if CACHE_GENERATION.load(Ordering::SeqCst) != generation {
+    return;
+  }
+  match (merge, root, global_cache.as_mut()) {
+    (true, Some(root), Some(cache)) => {
+      let cache = Arc::make_mut(cache);
+      if !cache.indexed {
+        rebuild_cache_indices(cache);
+      }
+      remove_path_from_cache(cache, root);
+    }
+    (true, None, _) | (_, _, None) => {}
+    (false, _, _) => *global_cache = None,
+  }
+}
+
+// Path index (path -> position in entries), children index (parent -> child positions) and
+// every position ordered by size, largest first
+type CacheIndices = (
+  HashMap<PathBuf, usize>,
+  HashMap<PathBuf, Vec<usize>>,
+  Vec<usize>,
+);
+
+// Structure to hold cached scan data
+#[derive(Clone)]
+struct ScanCache {
+  // Value of CACHE_GENERATION when this scan was stored or last merged into
+  generation: u64,
+  root_path: PathBuf,
+  // Every scanned root. A single scan has just `root_path`; merged scans hang their roots
+  // off a synthetic `root_path` entry spanning all of them
+  roots: Vec<PathBuf>,
+  entries: Vec<Arc<AnalyticsInfo>>,
+  // Total size of `root_path`, kept with every change to the entries so summaries don't
+  // need the indices to look it up
+  total_bytes: u64,
+  // Prebuilt indices for faster tree building, empty until `indexed` is set
+  path_map: HashMap<PathBuf, usize>, // Maps path to index in entries
+  children_map: HashMap<PathBuf, Vec<usize>>, // Maps parent path to indices of children in entries
+  // Indices of entries sorted by size, largest first, so top-N queries can take a prefix
+  // instead of sorting every entry. estimate_compression relies on it
+  sorted_by_size: Vec<usize>,
+  indexed: bool,
+  // When the scan finished
+  scanned_at: SystemTime,
+  // How long the scan took in milliseconds
+  scan_time_ms: u64,
+  // Whether the scan stopped at its deadline. Its directories may be missing entries even
+  // though their mtimes are current, so they are never grafted into a later scan
+  partial: bool,
+}
+
+impl ScanCache {
+  // Whether a path lies inside one of the scanned roots (or is the synthetic merged root)
+  fn contains_path(&self, path: &Path) -> bool {
+    path == self.root_path || self.roots.iter().any(|root| path.starts_with(root))
+  }
+
+  // Whether `root_path` is a synthetic parent of merged roots rather than a scanned path
+  fn has_synthetic_root(&self) -> bool {
+    !self.roots.contains(&self.root_path)
+  }
+
+  // Approximate bytes held by the cache: fixed struct sizes plus the strings they own,
+  // for the entries and each index. Allocator overhead and spare capacity aren't counted
+  fn memory_estimate(&self) -> u64 {
+    use std::mem::size_of;
+
+    let path_len = |path: &Path| path.as_os_str().len();
+    let string_len = |s: &Option<String>| s.as_ref().map_or(0, String::len);
+
+    // Each entry is an Arc slot in `entries` pointing at its counts and the AnalyticsInfo
+    let entries: usize = self
+      .entries
+      .iter()
+      .map(|entry| {
+        size_of::<Arc<AnalyticsInfo>>()
+          + 2 * size_of::<usize>()
+          + size_of::<AnalyticsInfo>()
+          + path_len(&entry.path)
+          + string_len(&entry.owner_name)
+          + string_len(&entry.content_type)
+          + string_len(&entry.content_hash)
+          + entry.link_target.as_deref().map_or(0, path_len)
+          + entry
+            .path_info
+            .as_ref()
+            .map_or(0, |info| string_len(&info.owner_name))
+      })
+      .sum();
+    let path_map: usize = self
+      .path_map
+      .keys()
+      .map(|path| size_of::<(PathBuf, usize)>() + path_len(path))
+      .sum();
+    let children_map: usize = self
+      .children_map
+      .iter()
+      .map(|(path, children)| {
+        size_of::<(PathBuf, Vec<usize>)>() + path_len(path) + children.len() * size_of::<usize>()
+      })
+      .sum();
+    let sorted_by_size = self.sorted_by_size.len() * size_of::<usize>();
+
+    (size_of::<ScanCache>() + entries + path_map + children_map + sorted_by_size) as u64
+  }
+}
+
+// Whether two paths are equal ignoring case
+fn paths_eq_ignore_case(a: &Path, b: &Path) -> bool {
+  a.to_string_lossy().to_lowercase() == b.to_string_lossy().to_lowercase()
+}
+
+// Finds the cached spelling of a path that differs from it only in case, walking down from
+// the scanned root one component at a time
+fn find_path_ignoring_case(cache: &ScanCache, path: &Path) -> Option<PathBuf> {
+  let components: Vec<_> = path.components().collect();
+  cache
+    .roots
+    .iter()
+    .chain(std::iter::once(&cache.root_path))
+    .find_map(|root| {
+      let root_len = root.components().count();
+      let path_root: PathBuf = components.get(..root_len)?.iter().collect();
+      if !paths_eq_ignore_case(&path_root, root) {
+        return None;
+      }
+
+      let mut current = root.clone();
+      for component in &components[root_len..] {
+        let name = Path::new(component.as_os_str());
+        current = cache
+          .children_map
+          .get(&current)?
+          .iter()
+          .map(|&index| &cache.entries[index].path)
+          .find(|child| {
+            child
+              .file_name()
+              .is_some_and(|child_name| paths_eq_ignore_case(Path::new(child_name), name))
+          })?
+          .clone();
+      }
+      Some(current)
+    })
+}
+
+// Rewrites forward slashes from the frontend to backslashes on Windows, where the cache
+// stores backslashes and `C:/Users` would neither match `path_map` nor `starts_with` a root
+fn normalize_separators(path: &str) -> std::borrow::Cow<'_, str> {
+  if cfg!(windows) && path.contains('/') {
+    std::borrow::Cow::Owned(path.replace('/', "\\"))
+  } else {
+    std::borrow::Cow::Borrowed(path)
+  }
+}
+
+// Expands a leading `~` to `home` and resolves relative paths against it
+// The app's working directory depends on how it was launched, so it is no base for user input
+fn expand_input_path(path: &str, home: Option<&Path>) -> PathBuf {
+  let path = &*normalize_separators(path);
+  let Some(home) = home else {
+    return PathBuf::from(path);
+  };
+  if path == "~" {
+    return home.to_path_buf();
+  }
+  if let Some(rest) = path
+    .strip_prefix("~/")
+    .or_else(|| path.strip_prefix("~\\").filter(|_| cfg!(windows)))
+  {
+    return home.join(rest);
+  }
+  let path = Path::new(path);
+  if path.is_relative() {
+    home.join(path)
+  } else {
+    path.to_path_buf()
+  }
+}
+
+// Expands and canonicalizes a path typed by the user, with a clear error if it doesn't exist
+fn resolve_input_path(path: &str) -> std::io::Result<PathBuf> {
+  let expanded = expand_input_path(path, dirs::home_dir().as_deref());
+  expanded.canonicalize().map_err(|e| {
+    if e.kind() == std::io::ErrorKind::NotFound {
+      std::io::Error::new(
+        e.kind(),
+        format!("Path does not exist: {}", expanded.display()),
+      )
+    } else {
+      e
+    }
+  })
+}
+
+// Converts a path from the frontend to the form stored in the cache, checking that it lies
+// within the scanned roots
+fn resolve_cached_path(cache: &ScanCache, path: &str) -> Result<PathBuf, String> {
+  let target_dir =
+    resolve_input_path(path).map_err(|e| format!("Failed to canonicalize path: {}", e))?;
+
+  // Canonicalizing doesn't always restore the on-disk case, so on case-insensitive
+  // filesystems fall back to matching the cached paths ignoring case
+  let target_dir = if platform::has_case_insensitive_paths()
+    && cache.indexed
+    && !cache.path_map.contains_key(&target_dir)
+  {
+    find_path_ignoring_case(cache, &target_dir).unwrap_or(target_dir)
+  } else {
+    target_dir
+  };
+
+  if !cache.contains_path(&target_dir) {
+    return Err(format!(
+      "Path {} is not within the scanned directory {}",
+      target_dir.display(),
+      cache.root_path.display()
+    ));
+  }
+  Ok(target_dir)
+}
+
+// Takes a snapshot of the global cache, holding the lock only long enough to clone the Arc
+fn cache_snapshot() -> Result<Option<Arc<ScanCache>>, String> {
   let cache_guard = GLOBAL_SCAN_CACHE
-    .lock()
+    .read()
     .map_err(|e| format!("Failed to acquire cache lock: {}", e))?;
+  Ok(cache_guard.clone())
+}
 
-  // Check if we have cached scan data
-  if let Some(cache) = &*cache_guard {
-    // Convert the path to canonical form
-    let target_dir = match Path::new(&path).canonicalize() {
-      Ok(p) => p,
-      Err(e) => return Err(format!("Failed to canonicalize path: {}", e)),
-    };
+// Takes a snapshot of the global cache, building its indices first if the background build
+// hasn't yet
+fn indexed_cache_snapshot() -> Result<Option<Arc<ScanCache>>, String> {
+  Ok(cache_snapshot()?.and_then(|cache| with_indices(cache, &IndexCancelToken::never())))
+}
 
-    // Check if the requested path is within our cached data (it should be a subpath of the root)
-    if !target_dir.starts_with(&cache.root_path) && target_dir != cache.root_path {
-      return Err(format!(
-        "Path {} is not within the scanned directory {}",
-        target_dir.display(),
-        cache.root_path.display()
-      ));
+// Returns an indexed version of a cache snapshot, building the indices outside the lock
+// The result is installed as the global cache if the snapshot is still current, so the work
+// is shared with later readers. Returns None if the build was cancelled
+fn with_indices(cache: Arc<ScanCache>, cancel: &IndexCancelToken) -> Option<Arc<ScanCache>> {
+  if cache.indexed {
+    return Some(cache);
+  }
+
+  let (path_map, children_map, sorted_by_size) =
+    compute_cache_indices(&cache.entries, &cache.root_path, &cache.roots, cancel)?;
+  let indexed = Arc::new(ScanCache {
+    path_map,
+    children_map,
+    sorted_by_size,
+    indexed: true,
+    ..(*cache).clone()
+  });
+
+  if let Ok(mut global_cache) = GLOBAL_SCAN_CACHE.write() {
+    // Anything else that changed the cache in the meantime wins, including another reader
+    // that indexed the same snapshot first
+    if global_cache
+      .as_ref()
+      .is_some_and(|current| Arc::ptr_eq(current, &cache))
+    {
+      *global_cache = Some(indexed.clone());
     }
+  }
+  Some(indexed)
+}
 
-    // Use the prebuilt indices to build the tree (much faster)
-    if let Some(tree) = build_tree_from_indices(
-      &cache.entries,
-      &cache.path_map,
-      &cache.children_map,
-      &target_dir,
-      1,    // Just show direct children
-      true, // Build virtual directory node
-    ) {
-      return Ok(tree);
+// Builds the indices for the cached scan unless they already exist
+// The lock is released while building, and the indices are dropped if the cache was
+// cleared or replaced in the meantime. Returns whether the cached scan is now indexed
+fn index_cached_scan() -> Result<bool, String> {
+  let Some(cache) = cache_snapshot()? else {
+    return Ok(false);
+  };
+  if cache.indexed {
+    return Ok(true);
+  }
+
+  // Abandon the build as soon as a newer scan or a clear makes it pointless
+  let cancel = IndexCancelToken::for_generation(cache.generation);
+  let Some(indexed) = with_indices(cache, &cancel) else {
+    return Ok(false);
+  };
+  Ok(cache_snapshot()?.is_some_and(|current| Arc::ptr_eq(&current, &indexed) || current.indexed))
+}
+
+/// Summary of the cached scan, for "last scanned" displays
+#[derive(Clone, Debug, Serialize)]
+struct ScanInfo {
+  /// The scanned root directory
+  root_path: PathBuf,
+  /// Number of entries stored in the cache
+  entry_count: u64,
+  /// When the scan finished (Unix timestamp in seconds)
+  scanned_at: u64,
+  /// How long the scan took in milliseconds
+  scan_time_ms: u64,
+}
+
+/// Payload of the `scan-throughput` event, sent every `THROUGHPUT_TICK` while a scan runs
+#[derive(Clone, Debug, Serialize)]
+struct ScanThroughput {
+  /// Bytes scanned per second, averaged over the last `THROUGHPUT_WINDOW` ticks
+  bytes_per_second: f64,
+  /// Entries scanned per second, averaged over the last `THROUGHPUT_WINDOW` ticks
+  entries_per_second: f64,
+  /// Bytes scanned so far
+  bytes_scanned: u64,
+  /// Entries scanned so far
+  entries_scanned: u64,
+}
+
+/// Live totals of the root of the scan in progress
+#[derive(Clone, Debug, Default, Serialize)]
+struct CurrentRootSize {
+  /// Bytes under the root found so far
+  size_bytes: u64,
+  /// Entries under the root found so far, the root included
+  entry_count: u64,
+}
+
+// Interval between `scan-throughput` events
+const THROUGHPUT_TICK: Duration = Duration::from_secs(1);
+// Number of ticks the reported rates are averaged over, so they don't jitter
+const THROUGHPUT_WINDOW: usize = 3;
+
+/// Turns a scan's running totals into rates smoothed over the last few ticks
+struct ThroughputMeter {
+  /// (time, entries, bytes) at each recent tick, oldest first
+  samples: std::collections::VecDeque<(std::time::Instant, u64, u64)>,
+}
+
+impl ThroughputMeter {
+  fn new(start: std::time::Instant) -> Self {
+    ThroughputMeter {
+      samples: std::collections::VecDeque::from([(start, 0, 0)]),
     }
+  }
 
-    // If the optimized method failed (unlikely), fall back to the original method
-    let entry = cache.entries.iter().find(|e| e.path == target_dir);
+  // Records the totals at `now` and returns the rates since the oldest sample in the window
+  fn record(&mut self, now: std::time::Instant, entries: u64, bytes: u64) -> ScanThroughput {
+    self.samples.push_back((now, entries, bytes));
+    while self.samples.len() > THROUGHPUT_WINDOW + 1 {
+      self.samples.pop_front();
+    }
 
-    if let Some(_) = entry {
-      // Build a tree using the original method
-      let tree = build_tree_from_entries_with_depth(&cache.entries, &target_dir, 1, true);
-      return Ok(tree);
-    } else {
-      return Err(format!(
-        "Path {} not found in scan data",
-        target_dir.display()
-      ));
+    let (since, since_entries, since_bytes) = self.samples[0];
+    let elapsed = now.duration_since(since).as_secs_f64();
+    let rate = |delta: u64| {
+      if elapsed > 0.0 {
+        delta as f64 / elapsed
+      } else {
+        0.0
+      }
+    };
+    ScanThroughput {
+      bytes_per_second: rate(bytes.saturating_sub(since_bytes)),
+      entries_per_second: rate(entries.saturating_sub(since_entries)),
+      bytes_scanned: bytes,
+      entries_scanned: entries,
     }
-  } else {
-    // No cached data available, need to perform a fresh scan
-    return Err("No scan data available. Please scan a directory first.".to_string());
   }
 }
 
+/// Payload of the `scan-finished` event, sent exactly once when a scan ends either way
+#[derive(Clone, Debug, Serialize)]
+struct ScanFinished {
+  /// Whether the scan completed and its results were delivered
+  success: bool,
+  /// Why the scan failed, when it did
+  error: Option<String>,
+  /// Number of entries the scan found, 0 on failure
+  entry_count: u64,
+}
+
+// New command to scan directory and return complete results at once
 #[tauri::command]
-fn get_free_space(path: String) -> Result<u64, String> {
-  match platform::get_space_info(&path) {
-    Some((_, available, _)) => Ok(available),
-    None => Err("Failed to get free space".to_string()),
+async fn scan_directory_size(
+  path: String,
+  options: Option<ScanOptions>,
+  merge: Option<bool>,
+  window: tauri::Window,
+) -> Result<(), String> {
+  // Drop any previous resources before starting a new scan
+  tokio::task::yield_now().await;
+
+  // Keep the cached scan around for grafting before it is cleared
+  let options = options.unwrap_or_default();
+  let previous_scan = if options.reuse_cache {
+    indexed_cache_snapshot()?.filter(|cache| !cache.partial)
+  } else {
+    None
+  };
+  // Sized from the cached scan, so it has to be looked up before that is cleared
+  // A path that doesn't resolve is left for the scan to report
+  let parent_context = if options.include_parent_context {
+    let cache = indexed_cache_snapshot()?;
+    resolve_input_path(&path)
+      .ok()
+      .and_then(|root| find_parent_context(&root, cache.as_deref()))
+  } else {
+    None
+  };
+
+  // Clear the global cache first when starting a new scan, unless merging into it
+  let merge = merge.unwrap_or(false);
+  let generation = start_cache_generation(!merge)?;
+  set_scan_phase(&window, generation, ScanPhase::Scanning);
+
+  let path_for_cleanup = path.clone();
+  let result = scan_directory_complete(
+    path,
+    options,
+    previous_scan,
+    parent_context,
+    merge,
+    generation,
+    window.clone(),
+  )
+  .await;
+
+  // A scan that was replaced by a newer one stays quiet, its events would end the newer
+  // scan's progress display. Its cache and phase updates are already ignored
+  if let Err(e) = &result {
+    if is_superseded(generation) {
+      return Err(e.to_string());
+    }
+  }
+
+  // Ensure we emit a complete event even on error to clean up frontend state
+  if result.is_err() {
+    let failed_root = resolve_input_path(&path_for_cleanup).ok();
+    discard_failed_scan(failed_root.as_deref(), merge, generation);
+    // Try to emit completion event on error to ensure frontend cleans up
+    let _ = window.emit("scan-complete", ());
+    set_scan_phase(&window, generation, ScanPhase::Error);
+  }
+
+  // `scan-complete` fires on success and failure alike, `scan-finished` says which it was
+  let finished = match &result {
+    Ok(entry_count) => ScanFinished {
+      success: true,
+      error: None,
+      entry_count: *entry_count,
+    },
+    Err(e) => ScanFinished {
+      success: false,
+      error: Some(e.to_string()),
+      entry_count: 0,
+    },
+  };
+  if let Err(e) = window.emit("scan-finished", finished) {
+    eprintln!("Failed to emit scan finished event: {}", e);
+  }
+
+  match result {
+    Ok(_) => Ok(()),
+    Err(e) => Err(e.to_string()),
   }
 }
 
-#[tauri::command]
-fn get_space_info(path: String) -> Result<(u64, u64, u64), String> {
-  match platform::get_space_info(&path) {
-    Some((total, available, used)) => Ok((total, available, used)),
-    None => Err("Failed to get space information".to_string()),
+// Fails if the scan root is a directory whose contents can't be listed, which would
+// otherwise scan "successfully" as an empty tree. Errors in its children are left to the scan
+fn check_root_readable(path: &Path) -> std::io::Result<()> {
+  if !path.is_dir() {
+    return Ok(());
+  }
+  match std::fs::read_dir(path) {
+    Ok(_) => Ok(()),
+    Err(e) => {
+      let reason = if e.kind() == std::io::ErrorKind::PermissionDenied {
+        PERMISSION_DENIED_REASON.to_string()
+      } else {
+        e.to_string()
+      };
+      Err(std::io::Error::new(
+        e.kind(),
+        format!("Cannot read directory: {}", reason),
+      ))
+    }
+  }
+}
+
+// Microseconds in a duration, saturating instead of wrapping around past u64::MAX
+fn duration_micros(duration: Duration) -> u64 {
+  u64::try_from(duration.as_micros()).unwrap_or(u64::MAX)
+}
+
+// Looks up the size of `root`'s parent without scanning it, from the cached scan or else the
+// volume's used space. The percentage is filled in once the root's own size is known
+fn find_parent_context(root: &Path, cache: Option<&ScanCache>) -> Option<ParentContext> {
+  let parent_path = root.parent()?.to_path_buf();
+  let cached_size = cache.and_then(|cache| {
+    cache
+      .path_map
+      .get(&parent_path)
+      .map(|&index| cache.entries[index].size_bytes)
+  });
+  let (parent_total_bytes, is_volume_total) = match cached_size {
+    Some(size) => (size, false),
+    None => {
+      let (_, _, used) = platform::get_space_info(&parent_path)?;
+      (used, true)
+    }
+  };
+  Some(ParentContext {
+    parent_path,
+    parent_total_bytes,
+    is_volume_total,
+    percent_of_parent: 0.0,
+  })
+}
+
+// Rough number of entries a subdirectory holds, for estimating a scan's size from its
+// root's direct children alone
+const ESTIMATED_ENTRIES_PER_SUBDIRECTORY: u64 = 50;
+
+/// Whether a path can be scanned, checked without scanning it
+#[derive(Clone, Debug, Serialize)]
+struct ScanTargetValidation {
+  /// Whether the path exists
+  exists: bool,
+  /// Whether it is a directory
+  is_dir: bool,
+  /// Whether its contents can be listed (or, for a file, opened)
+  is_readable: bool,
+  /// Whether it is a system directory that moves refuse without `allow_protected`
+  is_protected_root: bool,
+  /// Very rough number of entries a scan would find, from a count of the direct children
+  /// where each subdirectory stands for `ESTIMATED_ENTRIES_PER_SUBDIRECTORY` entries
+  estimated_entry_count: Option<u64>,
+}
+
+// Checks a scan target with at most one read_dir of the path itself
+fn validate_target(path: &str) -> ScanTargetValidation {
+  let Ok(target) = resolve_input_path(path) else {
+    return ScanTargetValidation {
+      exists: false,
+      is_dir: false,
+      is_readable: false,
+      is_protected_root: false,
+      estimated_entry_count: None,
+    };
+  };
+  let is_dir = target.is_dir();
+
+  let (is_readable, estimated_entry_count) = if is_dir {
+    match std::fs::read_dir(&target) {
+      Ok(dir_entries) => {
+        let (mut files, mut directories) = (0u64, 0u64);
+        for entry in dir_entries.flatten() {
+          if entry.file_type().is_ok_and(|file_type| file_type.is_dir()) {
+            directories += 1;
+          } else {
+            files += 1;
+          }
+        }
+        let estimate = 1 + files + directories * ESTIMATED_ENTRIES_PER_SUBDIRECTORY;
+        (true, Some(estimate))
+      }
+      Err(_) => (false, None),
+    }
+  } else {
+    (std::fs::File::open(&target).is_ok(), Some(1))
+  };
+
+  ScanTargetValidation {
+    exists: true,
+    is_dir,
+    is_readable,
+    is_protected_root: is_protected_root(&target),
+    estimated_entry_count,
+  }
+}
+
+// Command to check a path before scanning it, so the UI can warn about missing permissions
+// or a very large tree up front
+#[tauri::command]
+async fn validate_scan_target(path: String) -> Result<ScanTargetValidation, String> {
+  tokio::task::spawn_blocking(move || validate_target(&path))
+    .await
+    .map_err(|e| format!("Validation task failed: {}", e))
+}
+
+// What finish_scan did with a completed scan, for the command to report
+struct FinishedScan {
+  // The root and tree to show: the scanned directory's, or the whole cache's after a merge
+  root_path: PathBuf,
+  tree: FileSystemTreeNode,
+  entry_count: u64,
+  partial: bool,
+  // Whether the scan was merged into the cache, which indexes it right away
+  is_merged: bool,
+  // Whether the global cache holds the scan, false once a newer scan or a clear won
+  stored: bool,
+}
+
+// Records a completed scan's totals and puts its entries in the global cache, merged into
+// what's there with `merge`, unless a newer scan or a clear has superseded it. A scan stored
+// on its own is left for the caller to index
+fn finish_scan(
+  target_dir: &Path,
+  state: &ScanState,
+  generation: u64,
+  merge: bool,
+  scan_time_ms: u64,
+) -> std::io::Result<FinishedScan> {
+  if is_superseded(generation) {
+    return Err(std::io::Error::new(
+      std::io::ErrorKind::Interrupted,
+      SCAN_SUPERSEDED,
+    ));
+  }
+  let scanned_at = SystemTime::now();
+
+  // Convert the analytics map to a vector of entries
+  let entries = analytics_map_to_entries(&state.analytics_map);
+  let partial = state.cancelled.load(Ordering::Relaxed);
+  let entry_count = entries.len() as u64;
+  let root_totals = state
+    .analytics_map
+    .get(target_dir)
+    .map(|root| (root.size_bytes, root.file_count));
+
+  // A cancelled scan's totals would show up as a drop in the size trend
+  if !partial {
+    if let Some((size_bytes, file_count)) = root_totals {
+      record_scan_totals(target_dir, scanned_at, size_bytes, file_count);
+    }
+  }
+
+  // In merge mode, splice the scan into the existing cache and show the combined tree
+  if merge {
+    if let Some((root_path, tree)) = merge_scan_into_global_cache(
+      target_dir,
+      entries.clone(),
+      generation,
+      scan_time_ms,
+      partial,
+    ) {
+      return Ok(FinishedScan {
+        root_path,
+        tree,
+        entry_count,
+        partial,
+        is_merged: true,
+        stored: true,
+      });
+    }
+  }
+
+  // Build the initial tree from the entries with just a basic approach
+  // This will be quick and allows us to show results to the user without waiting for indexing
+  let tree = build_tree_from_entries_with_depth(&entries, target_dir, 1, true);
+
+  // Store the entries right away so navigation works before the indices are ready,
+  // unless the cache was cleared or replaced while scanning
+  let cache = ScanCache {
+    generation,
+    total_bytes: root_totals.map_or(0, |(size_bytes, _)| size_bytes),
+    roots: vec![target_dir.to_path_buf()],
+    root_path: target_dir.to_path_buf(),
+    entries,
+    path_map: HashMap::new(),
+    children_map: HashMap::new(),
+    sorted_by_size: Vec::new(),
+    indexed: false,
+    scanned_at,
+    scan_time_ms,
+    partial,
+  };
+  let stored = store_scan_cache(cache, generation);
+  Ok(FinishedScan {
+    root_path: target_dir.to_path_buf(),
+    tree,
+    entry_count,
+    partial,
+    is_merged: false,
+    stored,
+  })
+}
+
+// Modified scan_directory_complete function to store results in global cache
+// Returns the number of entries found
+async fn scan_directory_complete(
+  path: String,
+  options: ScanOptions,
+  previous_scan: Option<Arc<ScanCache>>,
+  parent_context: Option<ParentContext>,
+  merge: bool,
+  generation: u64,
+  window: tauri::Window,
+) -> std::io::Result<u64> {
+  let start_time = std::time::Instant::now();
+
+  let target_dir = resolve_input_path(&path)?;
+  check_root_readable(&target_dir)?;
+  let state = Arc::new(ScanState {
+    previous_scan,
+    ..Default::default()
+  });
+  begin_current_scan(target_dir.clone(), state.clone());
+
+  // Cancel the scan once its deadline passes, it then winds down with what it has read
+  let deadline_task = options.deadline_secs.map(|deadline_secs| {
+    let deadline_state = state.clone();
+    tokio::spawn(async move {
+      tokio::time::sleep(Duration::from_secs(deadline_secs)).await;
+      deadline_state.cancelled.store(true, Ordering::Relaxed);
+    })
+  });
+
+  // Run the calculation using tokio's spawn_blocking for CPU-intensive work
+  // This allows the expensive calculation to run without blocking other Tokio tasks
+  let state_clone = state.clone();
+  let target_dir_clone = target_dir.clone();
+  let event_window = window.clone();
+  let scan_task = tokio::task::spawn_blocking(move || {
+    // Forward partial results to the frontend as they become available
+    let emit_event = |event: ScanEvent| {
+      let result = match event {
+        ScanEvent::Preview(node) => event_window.emit("scan-preview", node),
+        ScanEvent::PartialNode(node) => event_window.emit("partial-node", node),
+      };
+      if let Err(e) = result {
+        eprintln!("Failed to emit scan event: {}", e);
+      }
+    };
+
+    // Run the synchronous calculation using Rayon's parallel processing
+    calculate_size_sync(
+      target_dir_clone.as_path(),
+      &state_clone,
+      &options,
+      Some(&emit_event),
+    )
+  });
+
+  // Report throughput while the scan runs, for a live MB/s and files/s readout
+  let throughput_state = state.clone();
+  let throughput_window = window.clone();
+  let throughput_task = tokio::spawn(async move {
+    let mut meter = ThroughputMeter::new(std::time::Instant::now());
+    let mut interval = tokio::time::interval(THROUGHPUT_TICK);
+    // The first tick completes immediately
+    interval.tick().await;
+    loop {
+      interval.tick().await;
+      let throughput = meter.record(
+        std::time::Instant::now(),
+        throughput_state.entries_scanned.load(Ordering::Relaxed),
+        throughput_state.bytes_scanned.load(Ordering::Relaxed),
+      );
+      if let Err(e) = throughput_window.emit("scan-throughput", throughput) {
+        eprintln!("Failed to emit scan throughput: {}", e);
+      }
+    }
+  });
+
+  // Wait for calculation to complete and handle any errors
+  let scan_result = scan_task.await;
+  throughput_task.abort();
+  if let Ok(mut current) = CURRENT_SCAN.write() {
+    // A newer scan may have taken over in the meantime
+    if matches!(&*current, Some((_, current_state)) if Arc::ptr_eq(current_state, &state)) {
+      *current = None;
+    }
+  }
+  if let Some(deadline_task) = deadline_task {
+    deadline_task.abort();
+  }
+  if let Err(e) = scan_result? {
+    eprintln!("Error during directory calculation: {}", e);
+    return Err(e);
+  }
+
+  // Calculate scan time
+  let elapsed = start_time.elapsed();
+  let elapsed_ms = elapsed.as_millis() as u64;
+
+  // Merging rebuilds the cache's indices, so keep it off the async runtime
+  let finish_state = state.clone();
+  let finish_dir = target_dir.clone();
+  let finished = tokio::task::spawn_blocking(move || {
+    finish_scan(&finish_dir, &finish_state, generation, merge, elapsed_ms)
+  })
+  .await??;
+
+  // Create the complete result object
+  let result = DirectoryScanResult {
+    root_path: finished.root_path,
+    tree: finished.tree,
+    scan_time_ms: elapsed_ms,
+    scan_time_us: duration_micros(elapsed),
+    symlink_cycles: state
+      .symlink_cycles
+      .iter()
+      .map(|item| (item.key().clone(), item.value().clone()))
+      .collect(),
+    errors: state.errors_sorted(),
+    needs_full_disk_access: state.needs_full_disk_access(),
+    partial: finished.partial,
+    is_protected_root: is_protected_root(&target_dir),
+    // Of the scanned directory, which in merge mode isn't the merged tree's root
+    parent_context: parent_context.map(|context| ParentContext {
+      percent_of_parent: percent_of(
+        state
+          .analytics_map
+          .get(&target_dir)
+          .map_or(0, |root| root.size_bytes),
+        context.parent_total_bytes,
+      ),
+      ..context
+    }),
+  };
+
+  // Send the complete result as a single event immediately
+  if let Err(e) = window.emit("scan-result", &result) {
+    eprintln!("Failed to emit scan result: {}", e);
+  }
+
+  // Also emit completion event for backward compatibility
+  if let Err(e) = window.emit("scan-complete", ()) {
+    eprintln!("Failed to emit completion event: {}", e);
+  }
+
+  // A merged scan has already been indexed into the cache
+  let finished_phase = if finished.partial {
+    ScanPhase::Cancelled
+  } else {
+    ScanPhase::Ready
+  };
+  if finished.is_merged {
+    set_scan_phase(&window, generation, finished_phase);
+    return Ok(finished.entry_count);
+  }
+  if !finished.stored {
+    return Ok(finished.entry_count);
+  }
+  set_scan_phase(&window, generation, ScanPhase::BuildingIndex);
+
+  // Now that the user sees the results, build the indices in the background
+  tokio::spawn(async move {
+    // Use tokio's spawn_blocking to run CPU-intensive parallelized work
+    // This ensures we don't block the async runtime with CPU-bound work
+    match tokio::task::spawn_blocking(index_cached_scan).await {
+      Ok(Ok(_)) => set_scan_phase(&window, generation, finished_phase),
+      Ok(Err(e)) => {
+        eprintln!("Failed to build indices in background task: {}", e);
+        set_scan_phase(&window, generation, ScanPhase::Error);
+      }
+      Err(e) => {
+        eprintln!("Failed to build indices in background task: {}", e);
+        set_scan_phase(&window, generation, ScanPhase::Error);
+      }
+    }
+  });
+
+  Ok(finished.entry_count)
+}
+
+// Totals of `root` as scanned so far. Directories only add up their children once those
+// finish, so until then the running scan counters are the better estimate
+fn current_root_size(root: &Path, state: &ScanState) -> CurrentRootSize {
+  let Some(entry) = state.analytics_map.get(root).map(|e| e.clone()) else {
+    return CurrentRootSize::default();
+  };
+  CurrentRootSize {
+    size_bytes: entry
+      .size_bytes
+      .max(state.bytes_scanned.load(Ordering::Relaxed)),
+    entry_count: entry
+      .entry_count
+      .max(state.entries_scanned.load(Ordering::Relaxed)),
+  }
+}
+
+// Command to read the live size of the scan in progress, zeros when no scan is running
+#[tauri::command]
+async fn get_current_root_size() -> Result<CurrentRootSize, String> {
+  let current = CURRENT_SCAN
+    .read()
+    .map_err(|e| format!("Failed to read current scan: {}", e))?
+    .clone();
+  Ok(match current {
+    Some((root, state)) => current_root_size(&root, &state),
+    None => CurrentRootSize::default(),
+  })
+}
+
+// Command to compute a path's totals without building a tree or populating the cache
+// Lighter-weight than scan_directory_size for scripts that only need the numbers
+#[tauri::command]
+async fn get_directory_size(
+  path: String,
+  options: ScanOptions,
+) -> Result<DirectorySizeSummary, String> {
+  let start_time = std::time::Instant::now();
+
+  let target_dir =
+    resolve_input_path(&path).map_err(|e| format!("Failed to canonicalize path: {}", e))?;
+  let state = Arc::new(ScanState::default());
+
+  let state_clone = state.clone();
+  let target_dir_clone = target_dir.clone();
+  tokio::task::spawn_blocking(move || {
+    calculate_size_sync(target_dir_clone.as_path(), &state_clone, &options, None)
+  })
+  .await
+  .map_err(|e| format!("Scan task failed: {}", e))?
+  .map_err(|e| format!("Error during directory calculation: {}", e))?;
+
+  let root = state
+    .analytics_map
+    .get(&target_dir)
+    .ok_or_else(|| format!("Failed to read {}", target_dir.display()))?;
+
+  Ok(DirectorySizeSummary {
+    size_bytes: root.size_bytes,
+    size_allocated_bytes: root.size_allocated_bytes,
+    unique_bytes: root.unique_bytes,
+    unique_allocated_bytes: root.unique_allocated_bytes,
+    file_count: root.file_count,
+    directory_count: root.directory_count,
+    special_file_count: state.special_file_count.load(Ordering::Relaxed),
+    scan_time_ms: start_time.elapsed().as_millis() as u64,
+  })
+}
+
+// Scans a path down to `max_depth` levels and builds its tree, independently of the cache
+fn build_single_directory_tree(
+  path: &Path,
+  max_depth: usize,
+) -> std::io::Result<FileSystemTreeNode> {
+  let options = ScanOptions {
+    max_depth: Some(max_depth),
+    ..Default::default()
+  };
+  let state = ScanState::default();
+  calculate_size_sync(path, &state, &options, None)?;
+
+  let entries = analytics_map_to_entries(&state.analytics_map);
+  Ok(build_tree_from_entries_with_depth(
+    &entries, path, max_depth, true,
+  ))
+}
+
+// Command to scan any path on its own, without requiring it to be inside the cached scan
+// The scan is bounded by `max_depth` and never touches the global cache
+#[tauri::command]
+async fn scan_single_directory(
+  path: String,
+  max_depth: usize,
+) -> Result<FileSystemTreeNode, String> {
+  let target_dir =
+    resolve_input_path(&path).map_err(|e| format!("Failed to canonicalize path: {}", e))?;
+
+  tokio::task::spawn_blocking(move || build_single_directory_tree(&target_dir, max_depth))
+    .await
+    .map_err(|e| format!("Scan task failed: {}", e))?
+    .map_err(|e| format!("Error during directory calculation: {}", e))
+}
+
+// Function to build indices for faster tree building
+// Returns None if `cancel` fires, which is checked between passes
+fn build_indices(
+  entries: &[Arc<AnalyticsInfo>],
+  target_dir: &Path,
+  cancel: &IndexCancelToken,
+) -> Option<CacheIndices> {
+  // First pass: build path_map (map from path to index in entries) - parallelize this
+  // A path listed more than once (e.g. by a merge that overlapped) keeps the entry with the
+  // larger aggregated size, the others are left out of both maps
+  let keep_larger = |a: usize, b: usize| {
+    let (size_a, size_b) = (entries[a].size_bytes, entries[b].size_bytes);
+    if size_b > size_a || (size_b == size_a && b < a) {
+      b
+    } else {
+      a
+    }
+  };
+  let path_map = entries
+    .par_iter()
+    .enumerate()
+    .fold(
+      HashMap::new,
+      |mut map: HashMap<PathBuf, usize>, (i, entry)| {
+        map
+          .entry(entry.path.clone())
+          .and_modify(|kept| *kept = keep_larger(*kept, i))
+          .or_insert(i);
+        map
+      },
+    )
+    .reduce(HashMap::new, |mut map, other| {
+      for (path, i) in other {
+        map
+          .entry(path)
+          .and_modify(|kept| *kept = keep_larger(*kept, i))
+          .or_insert(i);
+      }
+      map
+    });
+  if cancel.is_cancelled() {
+    return None;
+  }
+
+  // Second pass: build children_map
+  // This is harder to fully parallelize so we'll use a concurrent map
+  let children_map = DashMap::new();
+
+  // Populate the children map in parallel
+  entries.par_iter().enumerate().for_each(|(i, entry)| {
+    // Skip the duplicates that lost out above
+    if path_map.get(&entry.path) != Some(&i) {
+      return;
+    }
+
+    if let Some(parent_path) = entry.path.parent().map(|p| p.to_path_buf()) {
+      // Skip entries that are outside our target directory
+      if !parent_path.starts_with(target_dir) && parent_path != *target_dir {
+        return;
+      }
+
+      // Use entry API on DashMap to safely add children indices
+      children_map
+        .entry(parent_path)
+        .or_insert_with(Vec::new)
+        .push(i);
+    }
+  });
+
+  if cancel.is_cancelled() {
+    return None;
+  }
+
+  // Convert DashMap to regular HashMap for storage
+  let mut regular_children_map: HashMap<PathBuf, Vec<usize>> =
+    HashMap::with_capacity(children_map.len());
+
+  // Third pass: collect and sort children by size (largest first) for each parent
+  children_map
+    .into_iter()
+    .for_each(|(parent_path, mut indices)| {
+      // Sort indices by size (largest first), ties by path - this can be done in parallel for each entry
+      indices.par_sort_unstable_by(|&a, &b| {
+        let size_a = entries[a].size_bytes;
+        let size_b = entries[b].size_bytes;
+        size_b
+          .cmp(&size_a) // Sort largest first
+          .then_with(|| entries[a].path.cmp(&entries[b].path))
+      });
+
+      regular_children_map.insert(parent_path, indices);
+    });
+  if cancel.is_cancelled() {
+    return None;
+  }
+
+  // Fourth pass: order every kept entry by size (largest first) for top-N queries
+  let mut sorted_by_size: Vec<usize> = path_map.values().copied().collect();
+  sorted_by_size.par_sort_unstable_by(|&a, &b| {
+    entries[b]
+      .size_bytes
+      .cmp(&entries[a].size_bytes)
+      .then(a.cmp(&b))
+  });
+  if cancel.is_cancelled() {
+    return None;
+  }
+
+  Some((path_map, regular_children_map, sorted_by_size))
+}
+
+// Updated get_directory_children function to use cached data
+#[tauri::command]
+async fn get_directory_children(
+  path: String,
+  min_display_size: Option<u64>,
+  sort_by: Option<SortKey>,
+  group_directories_first: Option<bool>,
+  root_overhead: Option<RootOverhead>,
+  categorize_virtual_files: Option<bool>,
+) -> Result<FileSystemTreeNode, String> {
+  let categorize_files = categorize_virtual_files.unwrap_or(false);
+  let min_display_size = min_display_size.unwrap_or(0);
+  let sort_key = sort_by.unwrap_or_default();
+  let group_directories = group_directories_first.unwrap_or(false);
+  let root_overhead = root_overhead.unwrap_or_default();
+
+  // Take a snapshot of the global cache, the tree is built without holding the lock
+  let cache_snapshot = cache_snapshot()?;
+
+  // Check if we have cached scan data
+  if let Some(cache) = cache_snapshot.as_deref() {
+    // Convert the path to canonical form and check it is within our cached data
+    let target_dir = resolve_cached_path(cache, &path)?;
+
+    // Use the prebuilt indices to build the tree (much faster)
+    if let Some(mut tree) = build_tree_from_indices(
+      &cache.entries,
+      &cache.path_map,
+      &cache.children_map,
+      &target_dir,
+      1,    // Just show direct children
+      true, // Build virtual directory node
+    ) {
+      add_unrecorded_files_node(&mut tree, &cache.entries[cache.path_map[&target_dir]]);
+      if categorize_files {
+        group_virtual_files_by_kind(&mut tree);
+      }
+      apply_min_display_size(&mut tree, min_display_size);
+      apply_root_overhead(&mut tree, root_overhead);
+      sort_tree_children(&mut tree, sort_key);
+      if group_directories {
+        group_directories_before_files(&mut tree);
+      }
+      return Ok(tree);
+    }
+
+    // If the optimized method failed (unlikely), fall back to the original method
+    let entry = cache.entries.iter().find(|e| e.path == target_dir);
+
+    if let Some(entry) = entry {
+      // Build a tree using the original method
+      let mut tree = build_tree_from_entries_with_depth(&cache.entries, &target_dir, 1, true);
+      add_unrecorded_files_node(&mut tree, entry);
+      if categorize_files {
+        group_virtual_files_by_kind(&mut tree);
+      }
+      apply_min_display_size(&mut tree, min_display_size);
+      apply_root_overhead(&mut tree, root_overhead);
+      sort_tree_children(&mut tree, sort_key);
+      if group_directories {
+        group_directories_before_files(&mut tree);
+      }
+      return Ok(tree);
+    } else {
+      return Err(format!(
+        "Path {} not found in scan data",
+        target_dir.display()
+      ));
+    }
+  } else {
+    // No cached data available, need to perform a fresh scan
+    return Err("No scan data available. Please scan a directory first.".to_string());
+  }
+}
+
+#[tauri::command]
+fn get_free_space(path: String) -> Result<u64, String> {
+  match platform::get_space_info(&path) {
+    Some((_, available, _)) => Ok(available),
+    None => Err("Failed to get free space".to_string()),
+  }
+}
+
+#[tauri::command]
+fn get_space_info(path: String) -> Result<(u64, u64, u64), String> {
+  match platform::get_space_info(&path) {
+    Some((total, available, used)) => Ok((total, available, used)),
+    None => Err("Failed to get space information".to_string()),
+  }
+}
+
+// Command to list the mounted removable drives (USB sticks, SD cards) that can be scanned
+#[tauri::command]
+fn list_removable_drives() -> Vec<platform::RemovableDrive> {
+  platform::list_removable_drives()
+}
+
+// Default interval between free space checks of start_space_monitor
+const SPACE_MONITOR_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Payload of the `low-space` and `space-recovered` events
+#[derive(Clone, Debug, Serialize)]
+struct SpaceStatus {
+  /// The monitored path
+  path: PathBuf,
+  /// Bytes available on its volume
+  available_bytes: u64,
+  /// Total bytes of its volume
+  total_bytes: u64,
+  /// Available space as a percentage (0-100) of the total
+  available_percent: f64,
+}
+
+/// Tracks which side of the threshold free space was last seen on, so each crossing is
+/// reported once rather than on every check
+struct SpaceWatch {
+  threshold_percent: f64,
+  low: bool,
+}
+
+impl SpaceWatch {
+  fn new(threshold_percent: f64) -> Self {
+    SpaceWatch {
+      threshold_percent,
+      low: false,
+    }
+  }
+
+  // Records a check, returning the event to emit if free space crossed the threshold
+  fn record(&mut self, available_bytes: u64, total_bytes: u64) -> Option<&'static str> {
+    let low = percent_of(available_bytes, total_bytes) < self.threshold_percent;
+    if low == self.low {
+      return None;
+    }
+    self.low = low;
+    Some(if low { "low-space" } else { "space-recovered" })
+  }
+}
+
+// Command to keep checking a volume's free space in the background, emitting `low-space`
+// when it drops below `threshold_percent` and `space-recovered` once it is back above
+// Replaces any monitor already running
+#[tauri::command]
+fn start_space_monitor(
+  path: String,
+  threshold_percent: f64,
+  interval_secs: Option<u64>,
+  window: tauri::Window,
+) -> Result<(), String> {
+  if !(0.0..=100.0).contains(&threshold_percent) {
+    return Err(format!(
+      "Threshold must be between 0 and 100, got {}",
+      threshold_percent
+    ));
+  }
+  let path = PathBuf::from(path);
+  if platform::get_space_info(&path).is_none() {
+    return Err("Failed to get space information".to_string());
+  }
+  let interval = interval_secs
+    .map(Duration::from_secs)
+    .unwrap_or(SPACE_MONITOR_INTERVAL)
+    .max(Duration::from_secs(1));
+
+  let task = tokio::spawn(async move {
+    let mut watch = SpaceWatch::new(threshold_percent);
+    let mut interval = tokio::time::interval(interval);
+    loop {
+      interval.tick().await;
+      let Some((total_bytes, available_bytes, _)) = platform::get_space_info(&path) else {
+        continue;
+      };
+      if let Some(event) = watch.record(available_bytes, total_bytes) {
+        let status = SpaceStatus {
+          path: path.clone(),
+          available_bytes,
+          total_bytes,
+          available_percent: percent_of(available_bytes, total_bytes),
+        };
+        if let Err(e) = window.emit(event, status) {
+          eprintln!("Failed to emit {} event: {}", event, e);
+        }
+      }
+    }
+  });
+
+  let mut monitor = SPACE_MONITOR
+    .lock()
+    .map_err(|e| format!("Failed to acquire space monitor lock: {}", e))?;
+  if let Some(previous) = monitor.replace(task) {
+    previous.abort();
+  }
+  Ok(())
+}
+
+// Command to stop the space monitor, if one is running
+#[tauri::command]
+fn stop_space_monitor() -> Result<(), String> {
+  let mut monitor = SPACE_MONITOR
+    .lock()
+    .map_err(|e| format!("Failed to acquire space monitor lock: {}", e))?;
+  if let Some(task) = monitor.take() {
+    task.abort();
+  }
+  Ok(())
+}
+
+// Command to build the cached scan's indices right away, for UIs that want guaranteed
+// fast navigation. Returns once they are ready, and does nothing if they already exist
+#[tauri::command]
+async fn build_index_now() -> Result<(), String> {
+  tokio::task::spawn_blocking(index_cached_scan)
+    .await
+    .map_err(|e| format!("Index task failed: {}", e))?
+    .map(|_| ())
+}
+
+// Clears the scan cache, returning the generation the clear started
+fn reset_scan_cache() -> Result<u64, String> {
+  start_cache_generation(true)
+}
+
+// Command to clear the scan cache
+#[tauri::command]
+async fn clear_scan_cache(window: tauri::Window) -> Result<(), String> {
+  let generation = reset_scan_cache()?;
+  set_scan_phase(&window, generation, ScanPhase::Idle);
+  Ok(())
+}
+
+// Command to read the scan lifecycle phase, so the UI doesn't have to piece it together
+// from the scan events it has seen
+#[tauri::command]
+async fn get_scan_state() -> Result<ScanPhase, String> {
+  SCAN_PHASE
+    .lock()
+    .map(|current| current.1)
+    .map_err(|e| format!("Failed to read scan state: {}", e))
+}
+
+// Command to report when the cached scan ran, how big it is and how long it took
+#[tauri::command]
+async fn get_scan_info() -> Result<Option<ScanInfo>, String> {
+  Ok(cache_snapshot()?.map(|cache| {
+    ScanInfo {
+      root_path: cache.root_path.clone(),
+      entry_count: cache.entries.len() as u64,
+      scanned_at: cache
+        .scanned_at
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0),
+      scan_time_ms: cache.scan_time_ms,
+    }
+  }))
+}
+
+// Hashes the cached scan's summary together with the root's current mtime (in nanoseconds)
+// BLAKE3 rather than the standard library's hasher, whose output may change between Rust
+// releases and would make fingerprints kept by the UI stop matching after an update
+fn scan_fingerprint(cache: &ScanCache, root_mtime: u128) -> String {
+  let root_path = cache.root_path.to_string_lossy();
+  let mut hasher = blake3::Hasher::new();
+  hasher.update(&(root_path.len() as u64).to_le_bytes());
+  hasher.update(root_path.as_bytes());
+  hasher.update(&(cache.entries.len() as u64).to_le_bytes());
+  hasher.update(&cache.total_bytes.to_le_bytes());
+  hasher.update(&root_mtime.to_le_bytes());
+  hasher.finalize().to_hex().to_string()
+}
+
+// Command to fingerprint the cached scan with one stat of its root, so the UI can cheaply
+// tell whether to reuse it. Only changes directly inside the root touch its mtime, deeper
+// changes need a rescan or change detection to show up
+#[tauri::command]
+async fn get_scan_fingerprint() -> Result<String, String> {
+  let cache = cache_snapshot()?
+    .ok_or_else(|| "No scan data available. Please scan a directory first.".to_string())?;
+
+  let root_mtime = std::fs::metadata(&cache.root_path)
+    .and_then(|metadata| metadata.modified())
+    .map_err(|e| format!("Failed to read root metadata: {}", e))?
+    .duration_since(UNIX_EPOCH)
+    .map(|d| d.as_nanos())
+    .unwrap_or(0);
+  Ok(scan_fingerprint(&cache, root_mtime))
+}
+
+// Command to estimate how much memory the cached scan takes, including its indices once built
+#[tauri::command]
+async fn get_cache_memory_estimate() -> Result<u64, String> {
+  let cache = cache_snapshot()?
+    .ok_or_else(|| "No scan data available. Please scan a directory first.".to_string())?;
+  Ok(cache.memory_estimate())
+}
+
+// A completed scan of a root as (Unix timestamp in seconds, total bytes, file count)
+type SizeTrendPoint = (u64, u64, u64);
+
+// Scans kept per root by the size trend, the oldest are dropped beyond this
+const MAX_SCAN_HISTORY: usize = 365;
+
+// Appends a completed scan's totals to its root's history
+fn record_scan_totals(root: &Path, scanned_at: SystemTime, total_bytes: u64, file_count: u64) {
+  let timestamp = scanned_at
+    .duration_since(UNIX_EPOCH)
+    .map(|d| d.as_secs())
+    .unwrap_or(0);
+  let Ok(mut history) = SCAN_HISTORY.lock() else {
+    eprintln!("Failed to acquire scan history lock");
+    return;
+  };
+  let points = history.entry(root.to_path_buf()).or_default();
+  points.push((timestamp, total_bytes, file_count));
+  if points.len() > MAX_SCAN_HISTORY {
+    points.drain(..points.len() - MAX_SCAN_HISTORY);
+  }
+}
+
+// The recorded scans of `root` ordered by when they finished, which the clock may have
+// shuffled relative to the order they were recorded in
+fn size_trend(root: &Path) -> Result<Vec<SizeTrendPoint>, String> {
+  let mut points = SCAN_HISTORY
+    .lock()
+    .map_err(|e| format!("Failed to acquire scan history lock: {}", e))?
+    .get(root)
+    .cloned()
+    .unwrap_or_default();
+  points.sort_by_key(|&(timestamp, _, _)| timestamp);
+  Ok(points)
+}
+
+// Command to get (timestamp, total_bytes, file_count) for every completed scan of a root,
+// oldest first, for drawing its growth over time
+#[tauri::command]
+async fn get_size_trend(root_path: String) -> Result<Vec<SizeTrendPoint>, String> {
+  // A root that no longer exists still has the history of its scans
+  let root = resolve_input_path(&root_path)
+    .unwrap_or_else(|_| expand_input_path(&root_path, dirs::home_dir().as_deref()));
+  size_trend(&root)
+}
+
+/// A subtree size recorded by snapshot_size
+#[derive(Clone, Copy, Debug, Serialize)]
+struct SizeSnapshot {
+  /// Size of the subtree in bytes when the snapshot was taken
+  size_bytes: u64,
+  /// When the snapshot was taken
+  taken_at: SystemTime,
+}
+
+/// How much a subtree has changed since its snapshot
+#[derive(Clone, Debug, Serialize)]
+struct SizeDelta {
+  /// The directory the snapshot was taken of
+  path: PathBuf,
+  /// Size in bytes recorded by the snapshot
+  previous_size_bytes: u64,
+  /// Size in bytes in the current scan
+  current_size_bytes: u64,
+  /// Growth since the snapshot, negative when the subtree shrank
+  delta_bytes: i64,
+  /// Seconds elapsed since the snapshot was taken
+  elapsed_secs: f64,
+}
+
+fn size_delta(
+  path: PathBuf,
+  snapshot: SizeSnapshot,
+  current_size: u64,
+  now: SystemTime,
+) -> SizeDelta {
+  SizeDelta {
+    path,
+    previous_size_bytes: snapshot.size_bytes,
+    current_size_bytes: current_size,
+    delta_bytes: (current_size as i128 - snapshot.size_bytes as i128)
+      .clamp(i64::MIN as i128, i64::MAX as i128) as i64,
+    elapsed_secs: now
+      .duration_since(snapshot.taken_at)
+      .unwrap_or_default()
+      .as_secs_f64(),
+  }
+}
+
+// Looks up a path's size in the cached scan
+fn cached_size(path: &str) -> Result<(PathBuf, u64), String> {
+  let cache = indexed_cache_snapshot()?
+    .ok_or_else(|| "No scan data available. Please scan a directory first.".to_string())?;
+  let target_dir = resolve_cached_path(&cache, path)?;
+  let size = cache
+    .path_map
+    .get(&target_dir)
+    .map(|&index| cache.entries[index].size_bytes)
+    .ok_or_else(|| format!("Path {} was not found in the scan", target_dir.display()))?;
+  Ok((target_dir, size))
+}
+
+// Command to record a directory's current size, to compare against later with
+// size_delta_since_snapshot. Replaces any earlier snapshot of the same path
+#[tauri::command]
+async fn snapshot_size(path: String) -> Result<SizeSnapshot, String> {
+  let (target_dir, size_bytes) = cached_size(&path)?;
+  let snapshot = SizeSnapshot {
+    size_bytes,
+    taken_at: SystemTime::now(),
+  };
+  SIZE_SNAPSHOTS
+    .lock()
+    .map_err(|e| format!("Failed to acquire snapshot lock: {}", e))?
+    .insert(target_dir, snapshot);
+  Ok(snapshot)
+}
+
+// Command to compare a directory's size in the current scan with its last snapshot
+#[tauri::command]
+async fn size_delta_since_snapshot(path: String) -> Result<SizeDelta, String> {
+  let (target_dir, current_size) = cached_size(&path)?;
+  let snapshot = SIZE_SNAPSHOTS
+    .lock()
+    .map_err(|e| format!("Failed to acquire snapshot lock: {}", e))?
+    .get(&target_dir)
+    .copied()
+    .ok_or_else(|| format!("No snapshot was taken of {}", target_dir.display()))?;
+  Ok(size_delta(
+    target_dir,
+    snapshot,
+    current_size,
+    SystemTime::now(),
+  ))
+}
+
+// Writes every scanned directory as a `<size_in_kb>\t<path>` line, children before their
+// parent, matching the output of `du -k` (or `du -k --apparent-size`)
+fn write_du_export<W: std::io::Write>(
+  cache: &ScanCache,
+  writer: &mut W,
+  apparent: bool,
+) -> std::io::Result<()> {
+  let Some(&root_index) = cache.path_map.get(&cache.root_path) else {
+    return Ok(());
+  };
+
+  // Post-order walk with an explicit stack: each directory is pushed once to visit its
+  // children and once more to be written after them
+  let mut stack = vec![(root_index, false)];
+  while let Some((index, children_written)) = stack.pop() {
+    let entry = &cache.entries[index];
+    if children_written {
+      let size = if apparent {
+        entry.size_bytes
+      } else {
+        entry.size_allocated_bytes
+      };
+      // du counts in whole 1K blocks, rounding partial blocks up
+      writeln!(writer, "{}\t{}", size.div_ceil(1024), entry.path.display())?;
+      continue;
+    }
+
+    stack.push((index, true));
+    if let Some(children) = cache.children_map.get(&entry.path) {
+      for &child_index in children.iter().rev() {
+        if is_directory_entry(&cache.entries[child_index]) {
+          stack.push((child_index, false));
+        }
+      }
+    }
+  }
+
+  Ok(())
+}
+
+// Command to export the cached scan in `du -k` format, for diffing against `du` output
+#[tauri::command]
+async fn export_du(output_path: String, apparent: bool) -> Result<(), String> {
+  let cache = indexed_cache_snapshot()?
+    .ok_or_else(|| "No scan data available. Please scan a directory first.".to_string())?;
+
+  let file = std::fs::File::create(&output_path)
+    .map_err(|e| format!("Failed to create {}: {}", output_path, e))?;
+  let mut writer = std::io::BufWriter::new(file);
+  write_du_export(&cache, &mut writer, apparent)
+    .and_then(|_| std::io::Write::flush(&mut writer))
+    .map_err(|e| format!("Failed to write {}: {}", output_path, e))
+}
+
+// Writes the path of every directory below `root`, relative to it, one per line, parents
+// before their children, so the lines can be fed straight to `mkdir -p`
+// Returns the number of directories written
+fn write_structure_export<W: std::io::Write>(
+  cache: &ScanCache,
+  root: &Path,
+  writer: &mut W,
+) -> std::io::Result<u64> {
+  let mut directories: Vec<&Path> = cache
+    .entries
+    .iter()
+    .filter(|entry| is_directory_entry(entry))
+    .filter_map(|entry| entry.path.strip_prefix(root).ok())
+    .filter(|relative| !relative.as_os_str().is_empty())
+    .collect();
+  // Paths compare component by component, so a directory sorts before everything inside it
+  directories.sort();
+
+  for directory in &directories {
+    writeln!(writer, "{}", directory.display())?;
+  }
+  Ok(directories.len() as u64)
+}
+
+// Command to export the directory layout under a path, without files or sizes, e.g. to
+// recreate it elsewhere. Returns the number of directories written
+#[tauri::command]
+async fn export_structure(path: String, output: String) -> Result<u64, String> {
+  let cache = indexed_cache_snapshot()?
+    .ok_or_else(|| "No scan data available. Please scan a directory first.".to_string())?;
+  let root = resolve_cached_path(&cache, &path)?;
+
+  let file =
+    std::fs::File::create(&output).map_err(|e| format!("Failed to create {}: {}", output, e))?;
+  let mut writer = std::io::BufWriter::new(file);
+  write_structure_export(&cache, &root, &mut writer)
+    .and_then(|count| std::io::Write::flush(&mut writer).map(|_| count))
+    .map_err(|e| format!("Failed to write {}: {}", output, e))
+}
+
+// Renders the subtree under `path` as an indented outline like `tree --du`, largest first
+// Directories cut off at `max_depth` get a `...` line where their children would be
+fn write_text_tree(
+  cache: &ScanCache,
+  path: &Path,
+  max_depth: usize,
+  apparent: bool,
+) -> Option<String> {
+  fn size_of(entry: &AnalyticsInfo, apparent: bool) -> u64 {
+    if apparent {
+      entry.size_bytes
+    } else {
+      entry.size_allocated_bytes
+    }
+  }
+
+  fn write_children(
+    cache: &ScanCache,
+    path: &Path,
+    prefix: &str,
+    depth: usize,
+    max_depth: usize,
+    apparent: bool,
+    output: &mut String,
+  ) {
+    let Some(children) = cache.children_map.get(path).filter(|c| !c.is_empty()) else {
+      return;
+    };
+    if depth >= max_depth {
+      output.push_str(&format!("{}└── ...\n", prefix));
+      return;
+    }
+
+    let mut children: Vec<&Arc<AnalyticsInfo>> = children
+      .iter()
+      .map(|&index| &cache.entries[index])
+      .collect();
+    children.sort_by_key(|entry| std::cmp::Reverse(size_of(entry, apparent)));
+    for (i, child) in children.iter().enumerate() {
+      let is_last = i == children.len() - 1;
+      let name = child
+        .path
+        .file_name()
+        .map(|name| name.to_string_lossy())
+        .unwrap_or_else(|| child.path.to_string_lossy());
+      output.push_str(&format!(
+        "{}{}[{}]  {}\n",
+        prefix,
+        if is_last { "└── " } else { "├── " },
+        format_size(size_of(child, apparent)),
+        name
+      ));
+      let child_prefix = format!("{}{}", prefix, if is_last { "    " } else { "│   " });
+      write_children(
+        cache,
+        &child.path,
+        &child_prefix,
+        depth + 1,
+        max_depth,
+        apparent,
+        output,
+      );
+    }
+  }
+
+  let root = &cache.entries[*cache.path_map.get(path)?];
+  let mut output = format!(
+    "[{}]  {}\n",
+    format_size(size_of(root, apparent)),
+    path.display()
+  );
+  write_children(cache, path, "", 0, max_depth, apparent, &mut output);
+  Some(output)
+}
+
+// Command to render part of the cached scan as plain text, for pasting into a bug report
+#[tauri::command]
+async fn export_text_tree(
+  path: String,
+  max_depth: usize,
+  apparent: bool,
+) -> Result<String, String> {
+  let cache = indexed_cache_snapshot()?
+    .ok_or_else(|| "No scan data available. Please scan a directory first.".to_string())?;
+
+  let target_dir = resolve_cached_path(&cache, &path)?;
+  write_text_tree(&cache, &target_dir, max_depth, apparent)
+    .ok_or_else(|| format!("Path {} was not found in the scan", target_dir.display()))
+}
+
+// SQLite integers are signed 64-bit, anything larger is capped
+fn sql_integer(value: u64) -> i64 {
+  i64::try_from(value).unwrap_or(i64::MAX)
+}
+
+// Writes every cached entry into an `entries` table, for ad-hoc SQL queries over the scan
+// Rows are inserted one at a time from a prepared statement inside a single transaction, so
+// memory stays flat however many entries there are
+fn write_sqlite_export(cache: &ScanCache, conn: &mut rusqlite::Connection) -> rusqlite::Result<()> {
+  let transaction = conn.transaction()?;
+  transaction.execute_batch(
+    "CREATE TABLE entries (
+      path TEXT PRIMARY KEY,
+      parent_path TEXT,
+      size_bytes INTEGER NOT NULL,
+      size_allocated_bytes INTEGER NOT NULL,
+      file_count INTEGER NOT NULL,
+      directory_count INTEGER NOT NULL,
+      last_modified_time INTEGER NOT NULL,
+      owner_name TEXT,
+      is_dir INTEGER NOT NULL
+    );",
+  )?;
+
+  {
+    let mut insert = transaction.prepare(
+      "INSERT INTO entries (path, parent_path, size_bytes, size_allocated_bytes, file_count,
+        directory_count, last_modified_time, owner_name, is_dir)
+      VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+    )?;
+    for entry in &cache.entries {
+      // Merged roots hang off the synthetic root rather than their real parent
+      let parent_path = if entry.path == cache.root_path {
+        None
+      } else if cache.has_synthetic_root() && cache.roots.contains(&entry.path) {
+        Some(cache.root_path.as_path())
+      } else {
+        entry.path.parent()
+      };
+      let is_dir = match &entry.path_info {
+        Some(_) => is_directory_entry(entry),
+        None => entry.directory_count > 0,
+      };
+
+      insert.execute(rusqlite::params![
+        entry.path.to_string_lossy(),
+        parent_path.map(|parent| parent.to_string_lossy()),
+        sql_integer(entry.size_bytes),
+        sql_integer(entry.size_allocated_bytes),
+        sql_integer(entry.file_count),
+        sql_integer(entry.directory_count),
+        sql_integer(entry.last_modified_time),
+        entry.owner_name,
+        is_dir,
+      ])?;
+    }
+  }
+
+  // Indexes are cheaper to build once the rows are in
+  transaction.execute_batch(
+    "CREATE INDEX entries_parent_path ON entries (parent_path);
+    CREATE INDEX entries_size_bytes ON entries (size_bytes);",
+  )?;
+  transaction.commit()
+}
+
+// Command to export the cached scan to a SQLite database with an `entries` table
+#[tauri::command]
+async fn export_sqlite(output_path: String) -> Result<(), String> {
+  let cache = indexed_cache_snapshot()?
+    .ok_or_else(|| "No scan data available. Please scan a directory first.".to_string())?;
+
+  tokio::task::spawn_blocking(move || {
+    // Start from an empty database, like the other exports overwrite their file
+    match std::fs::remove_file(&output_path) {
+      Ok(()) => {}
+      Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+      Err(e) => return Err(format!("Failed to replace {}: {}", output_path, e)),
+    }
+    let mut conn = rusqlite::Connection::open(&output_path)
+      .map_err(|e| format!("Failed to create {}: {}", output_path, e))?;
+    write_sqlite_export(&cache, &mut conn)
+      .map_err(|e| format!("Failed to write {}: {}", output_path, e))
+  })
+  .await
+  .map_err(|e| format!("Export task failed: {}", e))?
+}
+
+// Lines written between flushes of an NDJSON export, so readers tailing it see progress
+const NDJSON_FLUSH_LINES: u64 = 10_000;
+
+/// One line of an NDJSON export
+#[derive(Serialize)]
+struct NdjsonRecord<'a> {
+  /// Path to the file or directory, with non-UTF-8 bytes replaced
+  path: std::borrow::Cow<'a, str>,
+  /// Total size in bytes
+  size_bytes: u64,
+  /// Total size in bytes on disk
+  size_allocated_bytes: u64,
+  /// Number of entries, itself included
+  entry_count: u64,
+  /// Number of files
+  file_count: u64,
+  /// Number of directories
+  directory_count: u64,
+  /// Last modified time in seconds since the epoch
+  last_modified_time: u64,
+  /// Owner of the entry
+  owner_name: Option<&'a str>,
+  /// Whether the entry is a directory
+  is_dir: bool,
+}
+
+// Writes every cached entry as one JSON object per line, returning the number of lines
+fn write_ndjson_export<W: std::io::Write>(
+  cache: &ScanCache,
+  writer: &mut W,
+) -> std::io::Result<u64> {
+  let mut lines = 0;
+  for entry in &cache.entries {
+    let record = NdjsonRecord {
+      path: entry.path.to_string_lossy(),
+      size_bytes: entry.size_bytes,
+      size_allocated_bytes: entry.size_allocated_bytes,
+      entry_count: entry.entry_count,
+      file_count: entry.file_count,
+      directory_count: entry.directory_count,
+      last_modified_time: entry.last_modified_time,
+      owner_name: entry.owner_name.as_deref(),
+      is_dir: match &entry.path_info {
+        Some(_) => is_directory_entry(entry),
+        None => entry.directory_count > 0,
+      },
+    };
+    serde_json::to_writer(&mut *writer, &record)?;
+    writer.write_all(b"\n")?;
+
+    lines += 1;
+    if lines % NDJSON_FLUSH_LINES == 0 {
+      writer.flush()?;
+    }
+  }
+  writer.flush()?;
+  Ok(lines)
+}
+
+// Command to export the cached scan as newline-delimited JSON, flat and streamable into
+// tools like jq or DuckDB. Returns the number of lines written
+#[tauri::command]
+async fn export_ndjson(output_path: String) -> Result<u64, String> {
+  let cache = cache_snapshot()?
+    .ok_or_else(|| "No scan data available. Please scan a directory first.".to_string())?;
+
+  tokio::task::spawn_blocking(move || {
+    let file = std::fs::File::create(&output_path)
+      .map_err(|e| format!("Failed to create {}: {}", output_path, e))?;
+    let mut writer = std::io::BufWriter::new(file);
+    write_ndjson_export(&cache, &mut writer)
+      .map_err(|e| format!("Failed to write {}: {}", output_path, e))
+  })
+  .await
+  .map_err(|e| format!("Export task failed: {}", e))?
+}
+
+// Rounds a reported ratio or percentage to two decimals, digits past that only bloat the
+// JSON and make the display flicker
+fn round_to_hundredths(value: f64) -> f64 {
+  (value * 100.0).round() / 100.0
+}
+
+// Percentage (0-100) of `whole` taken up by `part`, 0 when `whole` is empty
+fn percent_of(part: u64, whole: u64) -> f64 {
+  if whole == 0 {
+    return 0.0;
+  }
+  round_to_hundredths(part as f64 / whole as f64 * 100.0)
+}
+
+// Looks up each node in the cache and computes its share of the baseline's size
+fn compute_percentages(
+  cache: &ScanCache,
+  nodes: &[PathBuf],
+  baseline: &Path,
+) -> Result<Vec<(PathBuf, f64)>, String> {
+  let find_entry = |path: &Path| {
+    cache
+      .path_map
+      .get(path)
+      .map(|&index| &cache.entries[index])
+      .ok_or_else(|| format!("Path {} not found in scan data", path.display()))
+  };
+
+  let baseline_size = find_entry(baseline)?.size_bytes;
+  nodes
+    .iter()
+    .map(|node| {
+      let entry = find_entry(node)?;
+      Ok((node.clone(), percent_of(entry.size_bytes, baseline_size)))
+    })
+    .collect()
+}
+
+// Command to recalculate the percentage of each node relative to a baseline path, e.g. the
+// total of a filtered view, keeping percentage math on the Rust side
+#[tauri::command]
+async fn recompute_percentages(
+  nodes: Vec<PathBuf>,
+  baseline: PathBuf,
+) -> Result<Vec<(PathBuf, f64)>, String> {
+  let cache = indexed_cache_snapshot()?
+    .ok_or_else(|| "No scan data available. Please scan a directory first.".to_string())?;
+
+  compute_percentages(&cache, &nodes, &baseline)
+}
+
+/// A node laid out for a flamegraph, in pre-order
+#[derive(Clone, Debug, Serialize)]
+struct FlameNode {
+  /// Path to the file or directory
+  path: PathBuf,
+  /// Depth below the requested path (0 for the path itself)
+  depth: usize,
+  /// Bytes not accounted for by the node's children
+  self_bytes: u64,
+  /// Total size in bytes, including children
+  total_bytes: u64,
+  /// Byte position where the node starts: its parent's start plus the sizes of the
+  /// siblings laid out before it
+  start_offset: u64,
+}
+
+// Lays out the subtree under `path` for a flamegraph, down to `max_depth` levels
+fn build_flame_data(cache: &ScanCache, path: &Path, max_depth: usize) -> Vec<FlameNode> {
+  let Some(&root_index) = cache.path_map.get(path) else {
+    return Vec::new();
+  };
+
+  let mut nodes = Vec::new();
+  let mut stack = vec![(root_index, 0, 0)];
+  while let Some((index, depth, start_offset)) = stack.pop() {
+    let entry = &cache.entries[index];
+    let children = cache
+      .children_map
+      .get(&entry.path)
+      .map(Vec::as_slice)
+      .unwrap_or_default();
+    let children_bytes: u64 = children
+      .iter()
+      .map(|&child| cache.entries[child].size_bytes)
+      .sum();
+
+    nodes.push(FlameNode {
+      path: entry.path.clone(),
+      depth,
+      self_bytes: entry.size_bytes.saturating_sub(children_bytes),
+      total_bytes: entry.size_bytes,
+      start_offset,
+    });
+
+    if depth >= max_depth {
+      continue;
+    }
+
+    // Children are laid out from the parent's start, largest first; pushing them in
+    // reverse keeps the walk in pre-order
+    let mut child_offset = start_offset;
+    let mut child_nodes = Vec::with_capacity(children.len());
+    for &child in children {
+      child_nodes.push((child, depth + 1, child_offset));
+      child_offset += cache.entries[child].size_bytes;
+    }
+    stack.extend(child_nodes.into_iter().rev());
+  }
+
+  nodes
+}
+
+// Command to get flamegraph-ready nodes with precomputed offsets for a directory
+#[tauri::command]
+async fn get_flame_data(path: String, max_depth: usize) -> Result<Vec<FlameNode>, String> {
+  let cache = indexed_cache_snapshot()?
+    .ok_or_else(|| "No scan data available. Please scan a directory first.".to_string())?;
+
+  let target_dir = resolve_cached_path(&cache, &path)?;
+  Ok(build_flame_data(&cache, &target_dir, max_depth))
+}
+
+/// A direct child directory on a different filesystem than its parent
+#[derive(Clone, Debug, Serialize)]
+struct ChildMountPoint {
+  /// Path to the child directory
+  path: PathBuf,
+  /// Device ID of the filesystem mounted there
+  device: u64,
+  /// Total size in bytes of that filesystem, when it could be read
+  total_bytes: Option<u64>,
+  /// Bytes available on that filesystem
+  available_bytes: Option<u64>,
+  /// Bytes used on that filesystem
+  used_bytes: Option<u64>,
+}
+
+// Finds the direct children of `path` whose device differs from its own, going by the
+// device IDs recorded in the scan. Windows doesn't record them, so it never finds any
+fn find_child_mount_points(cache: &ScanCache, path: &Path) -> Vec<ChildMountPoint> {
+  let device_of = |index: usize| {
+    cache.entries[index]
+      .path_info
+      .as_ref()
+      .and_then(|info| info.inode_device)
+      .map(|(_, device)| device)
+  };
+  let Some(parent_device) = cache.path_map.get(path).and_then(|&index| device_of(index)) else {
+    return Vec::new();
+  };
+
+  cache
+    .children_map
+    .get(path)
+    .map(Vec::as_slice)
+    .unwrap_or_default()
+    .iter()
+    .filter(|&&child| is_directory_entry(&cache.entries[child]))
+    .filter_map(|&child| {
+      let device = device_of(child).filter(|&device| device != parent_device)?;
+      let path = cache.entries[child].path.clone();
+      let space = platform::get_space_info(&path);
+      Some(ChildMountPoint {
+        device,
+        total_bytes: space.map(|(total, _, _)| total),
+        available_bytes: space.map(|(_, available, _)| available),
+        used_bytes: space.map(|(_, _, used)| used),
+        path,
+      })
+    })
+    .collect()
+}
+
+// Command to list the direct children of a directory that are separate filesystems, which
+// explains why their sizes can reflect a whole other disk
+#[tauri::command]
+async fn get_child_mount_points(path: String) -> Result<Vec<ChildMountPoint>, String> {
+  let cache = indexed_cache_snapshot()?
+    .ok_or_else(|| "No scan data available. Please scan a directory first.".to_string())?;
+
+  let target_dir = resolve_cached_path(&cache, &path)?;
+  Ok(find_child_mount_points(&cache, &target_dir))
+}
+
+/// A contiguous span of a linear "disk ruler", in pre-order
+#[derive(Clone, Debug, Serialize)]
+struct LinearSpan {
+  /// Path to the file, or to a directory whose contents aren't broken down further
+  path: PathBuf,
+  /// Byte position where the span starts within the requested path's total
+  start_byte: u64,
+  /// Byte position where the span ends, exclusive
+  end_byte: u64,
+  /// Whether the span is a directory: one cut off at the depth limit, or the bytes of a
+  /// directory not accounted for by its children
+  is_directory: bool,
+}
+
+// Flattens the subtree under `path` into spans covering 0..total, so every byte lies in
+// exactly one span. Directories below `max_depth` levels become a single span, and
+// zero-sized entries are left out since they take up no room on the ruler
+fn build_linear_layout(cache: &ScanCache, path: &Path, max_depth: usize) -> Vec<LinearSpan> {
+  let Some(&root_index) = cache.path_map.get(path) else {
+    return Vec::new();
+  };
+
+  let mut spans = Vec::new();
+  let mut push_span = |path: &Path, start_byte: u64, size: u64, is_directory: bool| {
+    if size > 0 {
+      spans.push(LinearSpan {
+        path: path.to_path_buf(),
+        start_byte,
+        end_byte: start_byte + size,
+        is_directory,
+      });
+    }
+  };
+
+  let mut stack = vec![(root_index, 0, 0)];
+  while let Some((index, depth, start_byte)) = stack.pop() {
+    let entry = &cache.entries[index];
+    let children = cache
+      .children_map
+      .get(&entry.path)
+      .map(Vec::as_slice)
+      .unwrap_or_default();
+    if children.is_empty() || depth >= max_depth {
+      push_span(
+        &entry.path,
+        start_byte,
+        entry.size_bytes,
+        is_directory_entry(entry),
+      );
+      continue;
+    }
+
+    // The directory's own bytes come first, then its children in the same order as
+    // build_flame_data
+    let children_bytes: u64 = children
+      .iter()
+      .map(|&child| cache.entries[child].size_bytes)
+      .sum();
+    let self_bytes = entry.size_bytes.saturating_sub(children_bytes);
+    push_span(&entry.path, start_byte, self_bytes, true);
+
+    let mut child_start = start_byte + self_bytes;
+    let mut child_nodes = Vec::with_capacity(children.len());
+    for &child in children {
+      child_nodes.push((child, depth + 1, child_start));
+      child_start += cache.entries[child].size_bytes;
+    }
+    stack.extend(child_nodes.into_iter().rev());
+  }
+
+  spans
+}
+
+// Command to get a directory's files laid out end to end for a linear disk map
+#[tauri::command]
+async fn get_linear_layout(path: String, max_depth: usize) -> Result<Vec<LinearSpan>, String> {
+  let cache = indexed_cache_snapshot()?
+    .ok_or_else(|| "No scan data available. Please scan a directory first.".to_string())?;
+
+  let target_dir = resolve_cached_path(&cache, &path)?;
+  Ok(build_linear_layout(&cache, &target_dir, max_depth))
+}
+
+// Files modified within this many seconds count as recently active (30 days)
+const FRESHNESS_RECENT_SECS: u64 = 30 * 24 * 60 * 60;
+// A directory whose newest file is this old gets no credit for recency (1 year)
+const FRESHNESS_STALE_SECS: u64 = 365 * 24 * 60 * 60;
+
+/// How "alive" a directory is, e.g. for deciding what to back up first
+#[derive(Clone, Debug, Serialize)]
+struct FreshnessScore {
+  /// Path to the directory
+  path: PathBuf,
+  /// Depth below the requested path (0 for the path itself)
+  depth: usize,
+  /// Total size in bytes
+  size_bytes: u64,
+  /// Bytes in files modified within the last `FRESHNESS_RECENT_SECS`
+  recent_bytes: u64,
+  /// Last modified time of the newest file below the directory (Unix timestamp in seconds),
+  /// 0 if it holds no files
+  newest_modified_time: u64,
+  /// Average of the recently modified fraction of file bytes and how recent the newest file is,
+  /// from 0 (untouched for a year) to 1 (everything modified lately)
+  score: f64,
+}
+
+// Combines the recently modified fraction of a directory's file bytes with the age of its
+// newest file into a 0-1 score
+fn freshness_score(file_bytes: u64, recent_bytes: u64, newest_modified_time: u64, now: u64) -> f64 {
+  let recent_fraction = if file_bytes > 0 {
+    (recent_bytes as f64 / file_bytes as f64).min(1.0)
+  } else {
+    0.0
+  };
+  let recency = if newest_modified_time > 0 {
+    let age = now.saturating_sub(newest_modified_time);
+    1.0 - (age as f64 / FRESHNESS_STALE_SECS as f64).min(1.0)
+  } else {
+    0.0
+  };
+  (recent_fraction + recency) / 2.0
+}
+
+// Scores every directory under `path` down to `max_depth` levels, in a single post-order
+// pass that sums recent bytes and tracks the newest file mtime up the tree
+fn find_freshness_scores(
+  cache: &ScanCache,
+  path: &Path,
+  max_depth: usize,
+  now: u64,
+) -> Vec<FreshnessScore> {
+  let Some(&root_index) = cache.path_map.get(path) else {
+    return Vec::new();
+  };
+  let recent_cutoff = now.saturating_sub(FRESHNESS_RECENT_SECS);
+
+  // (file bytes, recent bytes, newest file mtime) of entries whose parent hasn't been
+  // visited yet. Directories' own sizes aren't file bytes and don't dilute the score
+  let mut totals: HashMap<usize, (u64, u64, u64)> = HashMap::new();
+  let mut scores = Vec::new();
+  let mut stack = vec![(root_index, 0, false)];
+  while let Some((index, depth, children_done)) = stack.pop() {
+    let entry = &cache.entries[index];
+    let children = cache.children_map.get(&entry.path);
+    if !children_done {
+      stack.push((index, depth, true));
+      if let Some(children) = children {
+        stack.extend(children.iter().map(|&child| (child, depth + 1, false)));
+      }
+      continue;
+    }
+
+    let (file_bytes, recent_bytes, newest_modified_time) = match children {
+      Some(children) => children
+        .iter()
+        .filter_map(|child| totals.remove(child))
+        .fold((0u64, 0u64, 0u64), |(files, recent, newest), child| {
+          (
+            files.saturating_add(child.0),
+            recent.saturating_add(child.1),
+            newest.max(child.2),
+          )
+        }),
+      None if is_directory_entry(entry) => (0, 0, 0),
+      None if entry.last_modified_time >= recent_cutoff => {
+        (entry.size_bytes, entry.size_bytes, entry.last_modified_time)
+      }
+      None => (entry.size_bytes, 0, entry.last_modified_time),
+    };
+    totals.insert(index, (file_bytes, recent_bytes, newest_modified_time));
+
+    // Only the synthetic root of merged scans has no path info
+    if depth <= max_depth && (is_directory_entry(entry) || entry.path_info.is_none()) {
+      scores.push(FreshnessScore {
+        path: entry.path.clone(),
+        depth,
+        size_bytes: entry.size_bytes,
+        recent_bytes,
+        newest_modified_time,
+        score: freshness_score(file_bytes, recent_bytes, newest_modified_time, now),
+      });
+    }
+  }
+
+  scores.sort_by(|a, b| a.path.cmp(&b.path));
+  scores
+}
+
+// Command to score how actively each directory is being changed, for coloring or sorting
+// the tree by freshness
+#[tauri::command]
+async fn get_freshness_scores(
+  path: String,
+  max_depth: usize,
+) -> Result<Vec<FreshnessScore>, String> {
+  let cache = indexed_cache_snapshot()?
+    .ok_or_else(|| "No scan data available. Please scan a directory first.".to_string())?;
+
+  let target_dir = resolve_cached_path(&cache, &path)?;
+  let now = SystemTime::now()
+    .duration_since(UNIX_EPOCH)
+    .map(|d| d.as_secs())
+    .unwrap_or(0);
+  Ok(find_freshness_scores(&cache, &target_dir, max_depth, now))
+}
+
+/// An entry modified within a requested time window
+#[derive(Clone, Debug, Serialize)]
+struct ModifiedEntry {
+  /// Path to the file or directory
+  path: PathBuf,
+  /// Total size in bytes
+  size_bytes: u64,
+  /// Last modified time (Unix timestamp in seconds)
+  last_modified_time: u64,
+  /// Whether the entry is a directory, whose mtime changes when entries are added, removed
+  /// or renamed directly inside it
+  is_directory: bool,
+}
+
+// Finds entries whose mtime lies in [start, end], newest first, keeping at most `limit`
+fn find_entries_in_window(
+  cache: &ScanCache,
+  start: u64,
+  end: u64,
+  limit: usize,
+) -> Vec<ModifiedEntry> {
+  let mut entries: Vec<ModifiedEntry> = cache
+    .entries
+    .par_iter()
+    // The synthetic root of merged scans has no mtime of its own
+    .filter(|entry| entry.path_info.is_some())
+    .filter(|entry| (start..=end).contains(&entry.last_modified_time))
+    .map(|entry| ModifiedEntry {
+      path: entry.path.clone(),
+      size_bytes: entry.size_bytes,
+      last_modified_time: entry.last_modified_time,
+      is_directory: is_directory_entry(entry),
+    })
+    .collect();
+
+  entries.sort_by(|a, b| {
+    b.last_modified_time
+      .cmp(&a.last_modified_time)
+      .then_with(|| a.path.cmp(&b.path))
+  });
+  entries.truncate(limit);
+  entries
+}
+
+// Command to list what changed between two times (Unix timestamps in seconds), e.g. while
+// investigating an incident. An `end` of 0 means until now
+#[tauri::command]
+async fn get_entries_in_window(
+  start: u64,
+  end: u64,
+  limit: usize,
+) -> Result<Vec<ModifiedEntry>, String> {
+  let cache = indexed_cache_snapshot()?
+    .ok_or_else(|| "No scan data available. Please scan a directory first.".to_string())?;
+
+  let end = if end == 0 {
+    SystemTime::now()
+      .duration_since(UNIX_EPOCH)
+      .map(|d| d.as_secs())
+      .unwrap_or(u64::MAX)
+  } else {
+    end
+  };
+  Ok(find_entries_in_window(&cache, start, end, limit))
+}
+
+/// A file or directory protected by an inode flag
+#[derive(Clone, Debug, Serialize)]
+struct ImmutableFile {
+  /// Path to the file or directory
+  path: PathBuf,
+  /// Total size in bytes
+  size_bytes: u64,
+  /// Can't be modified, renamed or deleted until the flag is cleared with `chattr -i`
+  is_immutable: bool,
+  /// Can only be appended to, and can't be deleted until cleared with `chattr -a`
+  is_append_only: bool,
+}
+
+// Collects every cached file and directory with the immutable or append-only flag set,
+// largest first. The flags are read now with `read_flags`, in parallel, rather than during
+// the scan: reading them takes an open per path, which every scan would pay for otherwise
+fn collect_immutable_files<F>(cache: &ScanCache, read_flags: F) -> Vec<ImmutableFile>
+where
+  F: Fn(&Path) -> (bool, bool) + Sync,
+{
+  cache
+    .sorted_by_size
+    .par_iter()
+    .map(|&index| &cache.entries[index])
+    .filter(|entry| {
+      entry
+        .path_info
+        .as_ref()
+        .is_some_and(|info| (info.is_file || info.is_dir) && !info.is_symlink)
+    })
+    .filter_map(|entry| {
+      let (is_immutable, is_append_only) = read_flags(&entry.path);
+      (is_immutable || is_append_only).then(|| ImmutableFile {
+        path: entry.path.clone(),
+        size_bytes: entry.size_bytes,
+        is_immutable,
+        is_append_only,
+      })
+    })
+    .collect()
+}
+
+// Command to list files that can't be deleted without clearing an attribute first (Linux)
+#[tauri::command]
+async fn find_immutable_files() -> Result<Vec<ImmutableFile>, String> {
+  let cache = indexed_cache_snapshot()?
+    .ok_or_else(|| "No scan data available. Please scan a directory first.".to_string())?;
+
+  tokio::task::spawn_blocking(move || collect_immutable_files(&cache, platform::get_inode_flags))
+    .await
+    .map_err(|e| format!("Failed to read file flags: {}", e))
+}
+
+/// How much deleting a path would actually free on its volume
+#[derive(Clone, Debug, Serialize)]
+struct ProjectedFreeSpace {
+  /// The path that would be deleted
+  path: PathBuf,
+  /// Total size on disk shown for the path
+  size_allocated_bytes: u64,
+  /// Bytes on disk deleting the path would give back
+  reclaimable_bytes: u64,
+  /// Bytes of files that are also hard-linked from outside the path, which stay allocated
+  hard_linked_elsewhere_bytes: u64,
+  /// Bytes of files on other volumes mounted below the path, freed there rather than on
+  /// the path's own volume
+  other_volume_bytes: u64,
+  /// Explanations of why the reclaimable figure is lower than the size, for the UI
+  caveats: Vec<String>,
+}
+
+// Formats a byte count for a message, e.g. "1.5 GB"
+fn format_size(bytes: u64) -> String {
+  const UNITS: [&str; 5] = ["bytes", "KB", "MB", "GB", "TB"];
+  let mut size = bytes as f64;
+  let mut unit = 0;
+  while size >= 1024.0 && unit < UNITS.len() - 1 {
+    size /= 1024.0;
+    unit += 1;
+  }
+  if unit == 0 {
+    format!("{} {}", bytes, UNITS[0])
+  } else {
+    format!("{:.1} {}", size, UNITS[unit])
+  }
+}
+
+// Works out how much deleting `path` would free: each physical file once, leaving out
+// files still hard-linked from elsewhere in the scan and files on other volumes. Reflinked
+// (copy-on-write) blocks and hard links from outside the scan can't be seen in the cache
+fn project_free_space(cache: &ScanCache, path: &Path) -> Option<ProjectedFreeSpace> {
+  let root_index = *cache.path_map.get(path)?;
+  let root = &cache.entries[root_index];
+  let root_device = root
+    .path_info
+    .as_ref()
+    .and_then(|info| info.inode_device)
+    .map(|(_, device)| device);
+
+  // Every entry in the subtree, with the allocated size of each physical file kept once
+  let mut subtree_inodes: HashMap<(u64, u64), u64> = HashMap::new();
+  let mut reclaimable_bytes = 0u64;
+  let mut other_volume_bytes = 0u64;
+  let mut stack = vec![root_index];
+  while let Some(index) = stack.pop() {
+    let entry = &cache.entries[index];
+    if let Some(children) = cache.children_map.get(&entry.path) {
+      stack.extend(children.iter().copied());
+    }
+    let Some(info) = &entry.path_info else {
+      continue;
+    };
+
+    // A directory's own allocation, or a file's (which has no children)
+    let own_allocated = info.size_allocated_bytes;
+    match info.inode_device {
+      Some((_, device)) if root_device.is_some_and(|root_device| root_device != device) => {
+        other_volume_bytes = other_volume_bytes.saturating_add(own_allocated);
+      }
+      Some(inode_pair) if info.is_file => {
+        subtree_inodes.entry(inode_pair).or_insert(own_allocated);
+      }
+      _ => reclaimable_bytes = reclaimable_bytes.saturating_add(own_allocated),
+    }
+  }
+
+  // Files with another link outside the subtree stay allocated after the delete
+  let mut linked_elsewhere: std::collections::HashSet<(u64, u64)> =
+    std::collections::HashSet::new();
+  for entry in &cache.entries {
+    let Some(inode_pair) = entry.path_info.as_ref().and_then(|info| info.inode_device) else {
+      continue;
+    };
+    if subtree_inodes.contains_key(&inode_pair) && !entry.path.starts_with(path) {
+      linked_elsewhere.insert(inode_pair);
+    }
+  }
+  let mut hard_linked_elsewhere_bytes = 0u64;
+  for (inode_pair, allocated) in subtree_inodes {
+    if linked_elsewhere.contains(&inode_pair) {
+      hard_linked_elsewhere_bytes = hard_linked_elsewhere_bytes.saturating_add(allocated);
+    } else {
+      reclaimable_bytes = reclaimable_bytes.saturating_add(allocated);
+    }
+  }
+
+  let mut caveats = Vec::new();
+  let duplicate_link_bytes = root
+    .size_allocated_bytes
+    .saturating_sub(root.unique_allocated_bytes);
+  if duplicate_link_bytes > 0 {
+    caveats.push(format!(
+      "{} is extra hard links to the same files and is only stored once",
+      format_size(duplicate_link_bytes)
+    ));
+  }
+  if hard_linked_elsewhere_bytes > 0 {
+    caveats.push(format!(
+      "{} is hard-linked elsewhere and won't be freed",
+      format_size(hard_linked_elsewhere_bytes)
+    ));
+  }
+  if other_volume_bytes > 0 {
+    caveats.push(format!(
+      "{} is on other drives mounted inside this folder and is freed there",
+      format_size(other_volume_bytes)
+    ));
+  }
+
+  Some(ProjectedFreeSpace {
+    path: path.to_path_buf(),
+    size_allocated_bytes: root.size_allocated_bytes,
+    reclaimable_bytes,
+    hard_linked_elsewhere_bytes,
+    other_volume_bytes,
+    caveats,
+  })
+}
+
+// Command to estimate the free space deleting a path would really give back
+#[tauri::command]
+async fn projected_free_space(path: String) -> Result<ProjectedFreeSpace, String> {
+  let cache = indexed_cache_snapshot()?
+    .ok_or_else(|| "No scan data available. Please scan a directory first.".to_string())?;
+
+  let target = resolve_cached_path(&cache, &path)?;
+  project_free_space(&cache, &target)
+    .ok_or_else(|| format!("Path {} was not found in the scan", target.display()))
+}
+
+/// How well a directory's subtree uses the space allocated to it
+#[derive(Clone, Debug, Serialize)]
+struct AllocationEfficiency {
+  /// Path to the directory
+  path: PathBuf,
+  /// Depth below the requested path (0 for the path itself)
+  depth: usize,
+  /// Total apparent size of the subtree in bytes
+  size_bytes: u64,
+  /// Total size of the subtree on disk in bytes
+  size_allocated_bytes: u64,
+  /// Apparent bytes per allocated byte, None when nothing is allocated
+  /// Below 0.5 means lots of slack from tiny files or preallocated space, above 1.0 means
+  /// compressed or sparse files
+  efficiency: Option<f64>,
+}
+
+// Lists the directories under `path`, down to `max_depth` levels, with their allocation
+// efficiency, in pre-order with larger directories first
+fn find_allocation_efficiency(
+  cache: &ScanCache,
+  path: &Path,
+  max_depth: usize,
+) -> Vec<AllocationEfficiency> {
+  let Some(&root_index) = cache.path_map.get(path) else {
+    return Vec::new();
+  };
+
+  let mut results = Vec::new();
+  let mut stack = vec![(root_index, 0)];
+  while let Some((index, depth)) = stack.pop() {
+    let entry = &cache.entries[index];
+    results.push(AllocationEfficiency {
+      path: entry.path.clone(),
+      depth,
+      size_bytes: entry.size_bytes,
+      size_allocated_bytes: entry.size_allocated_bytes,
+      efficiency: (entry.size_allocated_bytes > 0)
+        .then(|| round_to_hundredths(entry.size_bytes as f64 / entry.size_allocated_bytes as f64)),
+    });
+
+    if depth >= max_depth {
+      continue;
+    }
+    if let Some(children) = cache.children_map.get(&entry.path) {
+      // Reversed so the largest child is popped first
+      stack.extend(
+        children
+          .iter()
+          .rev()
+          .filter(|&&child| is_directory_entry(&cache.entries[child]))
+          .map(|&child| (child, depth + 1)),
+      );
+    }
+  }
+
+  results
+}
+
+// Command to get the allocation efficiency of each directory under a path
+#[tauri::command]
+async fn get_allocation_efficiency(
+  path: String,
+  max_depth: usize,
+) -> Result<Vec<AllocationEfficiency>, String> {
+  let cache = indexed_cache_snapshot()?
+    .ok_or_else(|| "No scan data available. Please scan a directory first.".to_string())?;
+
+  let target_dir = resolve_cached_path(&cache, &path)?;
+  Ok(find_allocation_efficiency(&cache, &target_dir, max_depth))
+}
+
+/// The file type taking up the most bytes in a subtree
+#[derive(Clone, Debug, Serialize)]
+struct DominantType {
+  /// Detected MIME type, or else the lowercase extension without the dot, "none" for files
+  /// without one, or "directory" for the space used by directory entries themselves
+  extension: String,
+  /// Total size in bytes of that type in the subtree
+  size_bytes: u64,
+  /// Percentage of the subtree's total size (0-100)
+  percent: f64,
+}
+
+// Groups a path under its lowercase extension, or "none" if it has none
+fn extension_key(path: &Path) -> String {
+  path
+    .extension()
+    .and_then(|ext| ext.to_str())
+    .map(|ext| ext.to_lowercase())
+    .unwrap_or_else(|| "none".to_string())
+}
+
+// Categorizes a node for the frontend's icons, using its type and lowercased extension
+fn file_kind_hint(path: &Path, is_dir: bool, is_symlink: bool) -> String {
+  if is_symlink {
+    return "symlink".to_string();
+  }
+  if is_dir {
+    return "folder".to_string();
+  }
+
+  let kind = match extension_key(path).as_str() {
+    "jpg" | "jpeg" | "png" | "gif" | "bmp" | "webp" | "svg" | "ico" | "tif" | "tiff" | "heic"
+    | "heif" | "raw" | "psd" => "image",
+    "mp4" | "mkv" | "avi" | "mov" | "wmv" | "flv" | "webm" | "m4v" | "mpg" | "mpeg" | "3gp" => {
+      "video"
+    }
+    "mp3" | "wav" | "flac" | "aac" | "ogg" | "m4a" | "wma" | "opus" | "aiff" => "audio",
+    "zip" | "rar" | "7z" | "tar" | "gz" | "tgz" | "bz2" | "xz" | "zst" | "iso" | "dmg" | "cab" => {
+      "archive"
+    }
+    "rs" | "js" | "ts" | "jsx" | "tsx" | "py" | "c" | "h" | "cpp" | "hpp" | "cc" | "java"
+    | "kt" | "go" | "rb" | "php" | "cs" | "swift" | "sh" | "ps1" | "html" | "css" | "scss"
+    | "json" | "toml" | "yaml" | "yml" | "xml" | "sql" | "lua" => "code",
+    "pdf" | "doc" | "docx" | "xls" | "xlsx" | "ppt" | "pptx" | "odt" | "ods" | "odp" | "txt"
+    | "md" | "rtf" | "csv" | "epub" => "document",
+    "exe" | "dll" | "so" | "dylib" | "msi" | "app" | "bin" | "com" | "bat" | "cmd" | "appimage"
+    | "deb" | "rpm" | "apk" => "executable",
+    _ => "other",
+  };
+  kind.to_string()
+}
+
+// Categorizes a cached entry, see file_kind_hint
+fn entry_kind_hint(entry: &AnalyticsInfo) -> String {
+  match &entry.path_info {
+    Some(info) => file_kind_hint(&entry.path, info.is_dir, info.is_symlink),
+    // Only the synthetic root of merged scans has no path info
+    None => file_kind_hint(&entry.path, entry.directory_count > 0, false),
+  }
+}
+
+// Whether a cached entry is anything but a directory, e.g. a single file dropped on the app
+// as the scan root
+fn is_file_entry(entry: &AnalyticsInfo) -> bool {
+  entry.path_info.as_ref().is_some_and(|info| !info.is_dir)
+}
+
+// Whether a cached entry is a symlink, followed or not
+fn is_symlink_entry(entry: &AnalyticsInfo) -> bool {
+  entry.path_info.as_ref().is_some_and(|info| info.is_symlink)
+}
+
+// Where a cached symlink points, None for anything else
+fn entry_symlink_target(entry: &AnalyticsInfo) -> Option<PathBuf> {
+  entry
+    .link_target
+    .clone()
+    .filter(|_| is_symlink_entry(entry))
+}
+
+// Whether a cached entry is a real directory (not a symlink to one)
+fn is_directory_entry(entry: &AnalyticsInfo) -> bool {
+  entry
+    .path_info
+    .as_ref()
+    .is_some_and(|info| info.is_dir && !info.is_symlink)
+}
+
+/// A well-known directory of caches, build output or trash that could be deleted
+#[derive(Clone, Debug, Serialize)]
+struct JunkLocation {
+  /// Path to the directory
+  path: PathBuf,
+  /// What the directory holds: dependencies, build-artifacts, cache, browser-cache, trash
+  /// or temp
+  category: &'static str,
+  /// Total size in bytes
+  size_bytes: u64,
+}
+
+// Directory names of well-known reclaimable locations and their category, matched ignoring
+// case. Browser caches are found by the names Chromium and Firefox give their cache folders
+const JUNK_DIRECTORY_NAMES: &[(&str, &str)] = &[
+  ("node_modules", "dependencies"),
+  ("bower_components", "dependencies"),
+  ("target", "build-artifacts"),
+  ("__pycache__", "build-artifacts"),
+  (".pytest_cache", "build-artifacts"),
+  (".mypy_cache", "build-artifacts"),
+  (".gradle", "build-artifacts"),
+  (".next", "build-artifacts"),
+  (".parcel-cache", "build-artifacts"),
+  (".cache", "cache"),
+  ("Caches", "cache"),
+  ("Cache", "browser-cache"),
+  ("Code Cache", "browser-cache"),
+  ("GPUCache", "browser-cache"),
+  ("cache2", "browser-cache"),
+  (".Trash", "trash"),
+  ("Trash", "trash"),
+  ("$Recycle.Bin", "trash"),
+  ("tmp", "temp"),
+  ("temp", "temp"),
+];
+
+// The junk category of a cached directory, if its name is a well-known one
+// `target` is a common name, so it only counts next to a Cargo.toml
+fn junk_category(cache: &ScanCache, entry: &AnalyticsInfo) -> Option<&'static str> {
+  let name = entry.path.file_name()?.to_str()?;
+  let &(matched, category) = JUNK_DIRECTORY_NAMES
+    .iter()
+    .find(|(junk_name, _)| junk_name.eq_ignore_ascii_case(name))?;
+  if matched == "target" {
+    let manifest = entry.path.parent()?.join("Cargo.toml");
+    if !cache.path_map.contains_key(&manifest) {
+      return None;
+    }
+  }
+  Some(category)
+}
+
+// Finds the well-known junk directories under `path`, largest first. A match isn't searched
+// any further, so junk nested inside junk isn't counted twice
+fn find_junk_locations(cache: &ScanCache, path: &Path) -> Vec<JunkLocation> {
+  let mut locations = Vec::new();
+  let Some(&root_index) = cache.path_map.get(path) else {
+    return locations;
+  };
+  let mut stack = vec![root_index];
+  while let Some(index) = stack.pop() {
+    let entry = &cache.entries[index];
+    if index != root_index && entry.directory_count > 0 {
+      if let Some(category) = junk_category(cache, entry) {
+        locations.push(JunkLocation {
+          path: entry.path.clone(),
+          category,
+          size_bytes: entry.size_bytes,
+        });
+        continue;
+      }
+    }
+
+    if let Some(children) = cache.children_map.get(&entry.path) {
+      stack.extend(children.iter().copied());
+    }
+  }
+  locations.sort_by(|a, b| {
+    b.size_bytes
+      .cmp(&a.size_bytes)
+      .then_with(|| a.path.cmp(&b.path))
+  });
+  locations
+}
+
+// Command to list the caches, build output and trash under a directory that could be
+// cleaned up, with their sizes
+#[tauri::command]
+async fn analyze_junk(path: String) -> Result<Vec<JunkLocation>, String> {
+  let cache = indexed_cache_snapshot()?
+    .ok_or_else(|| "No scan data available. Please scan a directory first.".to_string())?;
+
+  let target_dir = resolve_cached_path(&cache, &path)?;
+  Ok(find_junk_locations(&cache, &target_dir))
+}
+
+// Walks the subtree under `path` and finds the type accounting for the most bytes
+fn find_dominant_type(cache: &ScanCache, path: &Path) -> Option<DominantType> {
+  let mut type_sizes: HashMap<String, u64> = HashMap::new();
+  let mut stack = vec![*cache.path_map.get(path)?];
+  while let Some(index) = stack.pop() {
+    let entry = &cache.entries[index];
+    if is_directory_entry(entry) {
+      // Only the directory's own size, its contents are counted as they are visited
+      let own_size = entry.path_info.as_ref().map_or(0, |info| info.size_bytes);
+      *type_sizes.entry("directory".to_string()).or_insert(0) += own_size;
+    } else if entry.path_info.is_some() {
+      let key = entry
+        .content_type
+        .clone()
+        .unwrap_or_else(|| extension_key(&entry.path));
+      *type_sizes.entry(key).or_insert(0) += entry.size_bytes;
+    }
+
+    if let Some(children) = cache.children_map.get(&entry.path) {
+      stack.extend(children.iter().copied());
+    }
+  }
+
+  let total: u64 = type_sizes.values().sum();
+  let (extension, size_bytes) = type_sizes
+    .into_iter()
+    .filter(|(_, size)| *size > 0)
+    .max_by(|a, b| a.1.cmp(&b.1).then_with(|| b.0.cmp(&a.0)))?;
+  Some(DominantType {
+    extension,
+    size_bytes,
+    percent: percent_of(size_bytes, total),
+  })
+}
+
+// Command to find which file type dominates a directory, e.g. "mostly .mp4, 78%"
+#[tauri::command]
+async fn get_directory_dominant_type(path: String) -> Result<Option<DominantType>, String> {
+  let cache = indexed_cache_snapshot()?
+    .ok_or_else(|| "No scan data available. Please scan a directory first.".to_string())?;
+
+  let target_dir = resolve_cached_path(&cache, &path)?;
+  Ok(find_dominant_type(&cache, &target_dir))
+}
+
+/// Size statistics of the files sharing an extension
+#[derive(Clone, Debug, Serialize)]
+struct ExtensionStats {
+  /// Lowercase extension without the dot, or "none" for files without one
+  extension: String,
+  /// Number of files with the extension
+  file_count: u64,
+  /// Total size in bytes of those files
+  total_bytes: u64,
+  /// Mean file size in bytes
+  average_bytes: u64,
+  /// Median file size in bytes
+  median_bytes: u64,
+  /// Size in bytes of the largest file
+  max_bytes: u64,
+  /// Path to the largest file
+  max_path: PathBuf,
+}
+
+// Median of unsorted sizes, the mean of the two middle values for an even count
+// Partially orders `sizes` in place instead of sorting them
+fn median_size(sizes: &mut [u64]) -> u64 {
+  if sizes.is_empty() {
+    return 0;
+  }
+  let is_odd = sizes.len() % 2 == 1;
+  let (lower, &mut upper_middle, _) = sizes.select_nth_unstable(sizes.len() / 2);
+  if is_odd {
+    return upper_middle;
+  }
+  // Everything left of the middle is no larger than it, so the other middle value is their max
+  let lower_middle = lower.iter().copied().max().unwrap_or(upper_middle);
+  lower_middle + (upper_middle - lower_middle) / 2
+}
+
+// Collects the sizes of every cached file by extension in one pass, then summarizes each
+// extension, largest total first
+fn compute_extension_stats(cache: &ScanCache) -> Vec<ExtensionStats> {
+  // Sizes of each extension's files, with the largest one and its path
+  let mut by_extension: HashMap<String, (Vec<u64>, u64, &Path)> = HashMap::new();
+  for entry in &cache.entries {
+    // Regular files: one file and no directory (symlinks and special files count neither)
+    if entry.file_count != 1 || entry.directory_count != 0 {
+      continue;
+    }
+    let (sizes, max_bytes, max_path) = by_extension
+      .entry(extension_key(&entry.path))
+      .or_insert_with(|| (Vec::new(), entry.size_bytes, &entry.path));
+    if entry.size_bytes > *max_bytes {
+      *max_bytes = entry.size_bytes;
+      *max_path = &entry.path;
+    }
+    sizes.push(entry.size_bytes);
+  }
+
+  let mut stats: Vec<ExtensionStats> = by_extension
+    .into_iter()
+    .map(|(extension, (mut sizes, max_bytes, max_path))| {
+      let file_count = sizes.len() as u64;
+      let total_bytes: u64 = sizes.iter().sum();
+      ExtensionStats {
+        extension,
+        file_count,
+        total_bytes,
+        average_bytes: total_bytes / file_count,
+        max_bytes,
+        median_bytes: median_size(&mut sizes),
+        max_path: max_path.to_path_buf(),
+      }
+    })
+    .collect();
+  stats.sort_by(|a, b| {
+    b.total_bytes
+      .cmp(&a.total_bytes)
+      .then_with(|| a.extension.cmp(&b.extension))
+  });
+  stats
+}
+
+// Command to get per-extension file size statistics for the whole scan, e.g. to spot
+// unusually large .txt files
+#[tauri::command]
+async fn get_extension_stats() -> Result<Vec<ExtensionStats>, String> {
+  let cache = cache_snapshot()?
+    .ok_or_else(|| "No scan data available. Please scan a directory first.".to_string())?;
+  Ok(compute_extension_stats(&cache))
+}
+
+// Totals the files with `extension` under each directory directly inside the scan root,
+// as (directory, bytes, file count), largest first. Directories without any are left out
+fn extension_by_toplevel(cache: &ScanCache, extension: &str) -> Vec<(PathBuf, u64, u64)> {
+  // Accept ".ISO" as well as "iso", matching how extension_key normalizes
+  let extension = extension.trim_start_matches('.').to_lowercase();
+  let extension = if extension.is_empty() {
+    "none".to_string()
+  } else {
+    extension
+  };
+
+  let mut totals = Vec::new();
+  for &child_index in cache
+    .children_map
+    .get(&cache.root_path)
+    .into_iter()
+    .flatten()
+  {
+    let child = &cache.entries[child_index];
+    if child.directory_count == 0 {
+      continue;
+    }
+    let (mut bytes, mut file_count) = (0, 0);
+    let mut stack = vec![child_index];
+    while let Some(index) = stack.pop() {
+      let entry = &cache.entries[index];
+      if entry.file_count == 1 && entry.directory_count == 0 {
+        if extension_key(&entry.path) == extension {
+          bytes += entry.size_bytes;
+          file_count += 1;
+        }
+      } else if let Some(children) = cache.children_map.get(&entry.path) {
+        stack.extend(children.iter().copied());
+      }
+    }
+    if file_count > 0 {
+      totals.push((child.path.clone(), bytes, file_count));
+    }
+  }
+  totals.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+  totals
+}
+
+// Command to get how much of one extension each top-level directory holds, e.g. to find
+// which project has all the .iso files
+#[tauri::command]
+async fn get_extension_by_toplevel(extension: String) -> Result<Vec<(PathBuf, u64, u64)>, String> {
+  let cache = indexed_cache_snapshot()?
+    .ok_or_else(|| "No scan data available. Please scan a directory first.".to_string())?;
+  Ok(extension_by_toplevel(&cache, &extension))
+}
+
+/// How mixed the file types directly inside a directory are
+#[derive(Clone, Debug, Serialize)]
+struct DirectoryEntropy {
+  /// Path to the directory
+  path: PathBuf,
+  /// Shannon entropy in bits of the extensions of the directory's direct files
+  /// 0 when every file has the same extension, higher the more types are mixed
+  entropy: f64,
+  /// Number of direct files
+  file_count: u64,
+  /// Number of distinct extensions among them
+  extension_count: usize,
+}
+
+// Shannon entropy in bits of a distribution given as counts
+fn shannon_entropy<I: IntoIterator<Item = u64>>(counts: I) -> f64 {
+  let counts: Vec<u64> = counts.into_iter().filter(|&count| count > 0).collect();
+  let total: u64 = counts.iter().sum();
+  if total == 0 {
+    return 0.0;
+  }
+  counts
+    .iter()
+    .map(|&count| {
+      let p = count as f64 / total as f64;
+      -p * p.log2()
+    })
+    .sum()
+}
+
+// Computes the extension entropy of each immediate subdirectory of `path`, highest first
+fn find_directory_entropy(cache: &ScanCache, path: &Path) -> Vec<DirectoryEntropy> {
+  let children_of = |dir: &Path| {
+    cache
+      .children_map
+      .get(dir)
+      .map(Vec::as_slice)
+      .unwrap_or_default()
+  };
+
+  let mut results: Vec<DirectoryEntropy> = children_of(path)
+    .iter()
+    .map(|&index| &cache.entries[index])
+    .filter(|entry| is_directory_entry(entry))
+    .map(|dir| {
+      let mut extension_counts: HashMap<String, u64> = HashMap::new();
+      for &child in children_of(&dir.path) {
+        let child = &cache.entries[child];
+        if child.path_info.is_some() && !is_directory_entry(child) {
+          *extension_counts
+            .entry(extension_key(&child.path))
+            .or_insert(0) += 1;
+        }
+      }
+      DirectoryEntropy {
+        path: dir.path.clone(),
+        entropy: shannon_entropy(extension_counts.values().copied()),
+        file_count: extension_counts.values().sum(),
+        extension_count: extension_counts.len(),
+      }
+    })
+    .collect();
+
+  results.sort_by(|a, b| {
+    b.entropy
+      .total_cmp(&a.entropy)
+      .then_with(|| a.path.cmp(&b.path))
+  });
+  results
+}
+
+// Command to rank a directory's subdirectories by how many file types they mix, to find
+// "junk drawer" folders worth reorganizing
+#[tauri::command]
+async fn get_directory_entropy(path: String) -> Result<Vec<DirectoryEntropy>, String> {
+  let cache = indexed_cache_snapshot()?
+    .ok_or_else(|| "No scan data available. Please scan a directory first.".to_string())?;
+
+  let target_dir = resolve_cached_path(&cache, &path)?;
+  Ok(find_directory_entropy(&cache, &target_dir))
+}
+
+/// A file whose apparent size exceeds what it actually occupies on disk
+#[derive(Clone, Debug, Serialize)]
+struct SparseFile {
+  /// Path to the file
+  path: PathBuf,
+  /// Apparent size in bytes
+  size_bytes: u64,
+  /// Size allocated on disk in bytes
+  size_allocated_bytes: u64,
+  /// Bytes that are not backed by disk (apparent minus allocated)
+  hole_bytes: u64,
+}
+
+// Gaps smaller than this are normal block rounding or compression, not a sparse file
+const SPARSE_MIN_HOLE_BYTES: u64 = 1024 * 1024;
+
+// Finds files with the largest holes, largest first
+// Unix reports allocation as blocks x 512 and Windows reads it for sparse files via the
+// expensive metadata path, so the gap means the same thing on both
+fn find_sparse_files(cache: &ScanCache, count: usize) -> Vec<SparseFile> {
+  let mut sparse_files: Vec<SparseFile> = cache
+    .entries
+    .par_iter()
+    .filter(|entry| entry.path_info.as_ref().is_some_and(|info| info.is_file))
+    .filter_map(|entry| {
+      let hole_bytes = entry.size_bytes.saturating_sub(entry.size_allocated_bytes);
+      (hole_bytes >= SPARSE_MIN_HOLE_BYTES).then(|| SparseFile {
+        path: entry.path.clone(),
+        size_bytes: entry.size_bytes,
+        size_allocated_bytes: entry.size_allocated_bytes,
+        hole_bytes,
+      })
+    })
+    .collect();
+
+  // Equal holes are ordered by path, so the same files survive the cut on every run
+  sparse_files.sort_by(|a, b| {
+    b.hole_bytes
+      .cmp(&a.hole_bytes)
+      .then_with(|| a.path.cmp(&b.path))
+  });
+  sparse_files.truncate(count);
+  sparse_files
+}
+
+// Command to list the files that look largest compared to the disk space they use
+#[tauri::command]
+async fn get_sparse_files(count: usize) -> Result<Vec<SparseFile>, String> {
+  let cache = indexed_cache_snapshot()?
+    .ok_or_else(|| "No scan data available. Please scan a directory first.".to_string())?;
+
+  Ok(find_sparse_files(&cache, count))
+}
+
+/// A file present in both compared subtrees with a different size
+#[derive(Clone, Debug, Serialize)]
+struct SizeDifference {
+  /// Path relative to each subtree's root
+  path: PathBuf,
+  /// Size in bytes under the first subtree
+  size_a: u64,
+  /// Size in bytes under the second subtree
+  size_b: u64,
+}
+
+/// Differences between the files of two subtrees of the cached scan
+#[derive(Clone, Debug, Default, Serialize)]
+struct SubtreeDiff {
+  /// Files only in the first subtree, relative to its root
+  only_in_a: Vec<PathBuf>,
+  /// Files only in the second subtree, relative to its root
+  only_in_b: Vec<PathBuf>,
+  /// Files in both whose sizes differ
+  size_differs: Vec<SizeDifference>,
+}
+
+// Collects every file under `root`, keyed by its path relative to `root`
+fn collect_subtree_files(cache: &ScanCache, root: &Path) -> HashMap<PathBuf, u64> {
+  let mut files = HashMap::new();
+  let Some(&root_index) = cache.path_map.get(root) else {
+    return files;
+  };
+  let mut stack = vec![root_index];
+  while let Some(index) = stack.pop() {
+    let entry = &cache.entries[index];
+    if is_file_entry(entry) {
+      if let Ok(relative) = entry.path.strip_prefix(root) {
+        files.insert(relative.to_path_buf(), entry.size_bytes);
+      }
+    }
+
+    if let Some(children) = cache.children_map.get(&entry.path) {
+      stack.extend(children.iter().copied());
+    }
+  }
+  files
+}
+
+// Compares the files of two subtrees by their paths relative to each root
+fn diff_cached_subtrees(cache: &ScanCache, path_a: &Path, path_b: &Path) -> SubtreeDiff {
+  let files_a = collect_subtree_files(cache, path_a);
+  let files_b = collect_subtree_files(cache, path_b);
+
+  let mut diff = SubtreeDiff::default();
+  for (path, &size_a) in &files_a {
+    match files_b.get(path) {
+      Some(&size_b) if size_a != size_b => diff.size_differs.push(SizeDifference {
+        path: path.clone(),
+        size_a,
+        size_b,
+      }),
+      Some(_) => {}
+      None => diff.only_in_a.push(path.clone()),
+    }
+  }
+  diff.only_in_b = files_b
+    .into_keys()
+    .filter(|path| !files_a.contains_key(path))
+    .collect();
+
+  diff.only_in_a.sort();
+  diff.only_in_b.sort();
+  diff.size_differs.sort_by(|a, b| a.path.cmp(&b.path));
+  diff
+}
+
+// Flags the node at `target` in the tree, returning whether it was found
+fn highlight_node(node: &mut FileSystemTreeNode, target: &Path) -> bool {
+  if node.path == target && !node.is_virtual_directory {
+    node.highlight = true;
+    return true;
+  }
+  node
+    .children
+    .iter_mut()
+    .any(|child| highlight_node(child, target))
+}
+
+// Builds the tree of `target`'s parent directory down to `max_depth`, with `target` flagged
+fn locate_in_cache(
+  cache: &ScanCache,
+  target: &Path,
+  max_depth: usize,
+) -> Result<FileSystemTreeNode, String> {
+  if !cache.path_map.contains_key(target) {
+    return Err(format!(
+      "Path {} was not found in the scan",
+      target.display()
+    ));
+  }
+  let parent = target
+    .parent()
+    .filter(|parent| cache.path_map.contains_key(*parent))
+    .ok_or_else(|| format!("Path {} has no parent in the scan", target.display()))?;
+
+  let mut tree = build_tree_from_indices(
+    &cache.entries,
+    &cache.path_map,
+    &cache.children_map,
+    parent,
+    max_depth.max(1),
+    true,
+  )
+  .ok_or_else(|| format!("Failed to build tree for {}", parent.display()))?;
+  highlight_node(&mut tree, target);
+  Ok(tree)
+}
+
+// Command to open a search result: returns its parent directory's tree with the result
+// highlighted, saving the frontend a separate navigation request
+#[tauri::command]
+async fn locate_and_expand(
+  file_path: String,
+  max_depth: usize,
+) -> Result<FileSystemTreeNode, String> {
+  let cache = indexed_cache_snapshot()?
+    .ok_or_else(|| "No scan data available. Please scan a directory first.".to_string())?;
+
+  let target = resolve_cached_path(&cache, &file_path)?;
+  locate_in_cache(&cache, &target, max_depth)
+}
+
+// Command to compare two directories of the same scan, e.g. to check two backups match
+#[tauri::command]
+async fn diff_subtrees(path_a: String, path_b: String) -> Result<SubtreeDiff, String> {
+  let cache = indexed_cache_snapshot()?
+    .ok_or_else(|| "No scan data available. Please scan a directory first.".to_string())?;
+
+  let dir_a = resolve_cached_path(&cache, &path_a)?;
+  let dir_b = resolve_cached_path(&cache, &path_b)?;
+  Ok(diff_cached_subtrees(&cache, &dir_a, &dir_b))
+}
+
+/// A directory holding a few large files rather than many small ones
+#[derive(Clone, Debug, Serialize)]
+struct HeavyDirectory {
+  /// Path to the directory
+  path: PathBuf,
+  /// Total size in bytes
+  size_bytes: u64,
+  /// Total number of entries, including the directory itself
+  entry_count: u64,
+  /// Number of files
+  file_count: u64,
+  /// Average bytes per entry (size_bytes / entry_count)
+  bytes_per_entry: f64,
+}
+
+// Finds the `count` directories with the most bytes per entry, heaviest first
+fn find_heavy_directories(cache: &ScanCache, count: usize) -> Vec<HeavyDirectory> {
+  let mut directories: Vec<HeavyDirectory> = cache
+    .entries
+    .par_iter()
+    .filter(|entry| is_directory_entry(entry) && entry.entry_count > 0)
+    .map(|entry| HeavyDirectory {
+      path: entry.path.clone(),
+      size_bytes: entry.size_bytes,
+      entry_count: entry.entry_count,
+      file_count: entry.file_count,
+      bytes_per_entry: entry.size_bytes as f64 / entry.entry_count as f64,
+    })
+    .collect();
+
+  directories.sort_by(|a, b| {
+    b.bytes_per_entry
+      .total_cmp(&a.bytes_per_entry)
+      .then_with(|| a.path.cmp(&b.path))
+  });
+  directories.truncate(count);
+  directories
+}
+
+// Command to list the directories whose size comes from a few big files, e.g. for archiving
+#[tauri::command]
+async fn get_heavy_directories(count: usize) -> Result<Vec<HeavyDirectory>, String> {
+  let cache = indexed_cache_snapshot()?
+    .ok_or_else(|| "No scan data available. Please scan a directory first.".to_string())?;
+
+  Ok(find_heavy_directories(&cache, count))
+}
+
+/// One step along the heaviest path from the scan root
+#[derive(Clone, Debug, Serialize)]
+struct HeaviestPathStep {
+  /// Path to the file or directory
+  path: PathBuf,
+  /// Total size in bytes
+  size_bytes: u64,
+}
+
+// Follows the largest child from the root down, at most `depth_limit` levels, stopping at
+// a file or empty directory. Children are already sorted largest first, so this is O(depth)
+fn find_heaviest_path(cache: &ScanCache, depth_limit: usize) -> Vec<HeaviestPathStep> {
+  let Some(&root_index) = cache.path_map.get(&cache.root_path) else {
+    return Vec::new();
+  };
+
+  let mut steps = Vec::new();
+  let mut index = root_index;
+  loop {
+    let entry = &cache.entries[index];
+    steps.push(HeaviestPathStep {
+      path: entry.path.clone(),
+      size_bytes: entry.size_bytes,
+    });
+    if steps.len() > depth_limit {
+      break;
+    }
+    match cache
+      .children_map
+      .get(&entry.path)
+      .and_then(|children| children.first())
+    {
+      Some(&largest) => index = largest,
+      None => break,
+    }
+  }
+  steps
+}
+
+// Command to find where the space actually is, so a treemap can focus on it straight away
+#[tauri::command]
+async fn get_heaviest_path(depth_limit: usize) -> Result<Vec<HeaviestPathStep>, String> {
+  let cache = indexed_cache_snapshot()?
+    .ok_or_else(|| "No scan data available. Please scan a directory first.".to_string())?;
+
+  Ok(find_heaviest_path(&cache, depth_limit))
+}
+
+// Files smaller than this aren't worth compressing
+const COMPRESSION_MIN_FILE_BYTES: u64 = 64 * 1024;
+
+// Upper bound on the bytes read for one estimate, largest files are sampled first
+const COMPRESSION_MAX_SAMPLED_BYTES: u64 = 256 * 1024 * 1024;
+
+// How many of the best candidates are returned
+const COMPRESSION_TOP_CANDIDATES: usize = 50;
+
+// zstd level used for sampling, fast rather than thorough
+const COMPRESSION_SAMPLE_LEVEL: i32 = 1;
+
+/// A file that would likely shrink if compressed
+#[derive(Clone, Debug, Serialize)]
+struct CompressionCandidate {
+  /// Path to the file
+  path: PathBuf,
+  /// Size of the file in bytes
+  size_bytes: u64,
+  /// Compressed size divided by original size for the sampled start of the file
+  sample_ratio: f64,
+  /// Bytes the whole file would likely save, extrapolated from the sample
+  estimated_savings_bytes: u64,
+}
+
+/// A rough estimate of what compressing a subtree's files would save, for guidance only
+#[derive(Clone, Debug, Serialize)]
+struct CompressionEstimate {
+  /// Estimated savings summed over every sampled file
+  total_estimated_savings_bytes: u64,
+  /// Number of files sampled
+  sampled_files: u64,
+  /// Bytes read to produce the estimate
+  sampled_bytes: u64,
+  /// The files with the largest estimated savings, largest first
+  candidates: Vec<CompressionCandidate>,
+}
+
+// Compresses the first `sample_bytes` of a file, returning (bytes read, compressed size)
+fn sample_compression(path: &Path, sample_bytes: u64) -> Option<(u64, u64)> {
+  use std::io::Read;
+
+  let mut sample = Vec::new();
+  std::fs::File::open(path)
+    .ok()?
+    .take(sample_bytes)
+    .read_to_end(&mut sample)
+    .ok()?;
+  if sample.is_empty() {
+    return None;
+  }
+  let compressed = zstd::bulk::compress(&sample, COMPRESSION_SAMPLE_LEVEL).ok()?;
+  Some((sample.len() as u64, compressed.len() as u64))
+}
+
+// Samples the larger files under `path` and extrapolates how much compressing them would save
+fn estimate_compression(cache: &ScanCache, path: &Path, sample_bytes: u64) -> CompressionEstimate {
+  // Taken from the size index, so the files are already largest first
+  let mut files: Vec<&Arc<AnalyticsInfo>> = cache
+    .sorted_by_size
+    .iter()
+    .map(|&index| &cache.entries[index])
+    .filter(|entry| entry.path.starts_with(path))
+    .filter(|entry| {
+      entry
+        .path_info
+        .as_ref()
+        .is_some_and(|info| info.is_file && !info.is_symlink)
+    })
+    .filter(|entry| entry.size_bytes >= COMPRESSION_MIN_FILE_BYTES)
+    .collect();
+
+  // Keep the total read bounded however many files there are
+  let mut planned_bytes = 0u64;
+  files.retain(|entry| {
+    let read = entry.size_bytes.min(sample_bytes);
+    if planned_bytes.saturating_add(read) > COMPRESSION_MAX_SAMPLED_BYTES {
+      return false;
+    }
+    planned_bytes += read;
+    true
+  });
+
+  let sampled: Vec<(u64, CompressionCandidate)> = files
+    .par_iter()
+    .filter_map(|entry| {
+      let (read, compressed) = sample_compression(&entry.path, sample_bytes)?;
+      let sample_ratio = compressed as f64 / read as f64;
+      let estimated_savings_bytes = if sample_ratio < 1.0 {
+        (entry.size_bytes as f64 * (1.0 - sample_ratio)) as u64
+      } else {
+        0
+      };
+      Some((
+        read,
+        CompressionCandidate {
+          path: entry.path.clone(),
+          size_bytes: entry.size_bytes,
+          sample_ratio: round_to_hundredths(sample_ratio),
+          estimated_savings_bytes,
+        },
+      ))
+    })
+    .collect();
+
+  let sampled_bytes = sampled.iter().map(|(read, _)| read).sum();
+  let mut candidates: Vec<CompressionCandidate> = sampled
+    .into_iter()
+    .map(|(_, candidate)| candidate)
+    .collect();
+  let total_estimated_savings_bytes = candidates
+    .iter()
+    .map(|candidate| candidate.estimated_savings_bytes)
+    .sum();
+  let sampled_files = candidates.len() as u64;
+
+  candidates.retain(|candidate| candidate.estimated_savings_bytes > 0);
+  candidates.sort_by_key(|candidate| std::cmp::Reverse(candidate.estimated_savings_bytes));
+  candidates.truncate(COMPRESSION_TOP_CANDIDATES);
+
+  CompressionEstimate {
+    total_estimated_savings_bytes,
+    sampled_files,
+    sampled_bytes,
+    candidates,
+  }
+}
+
+// Command to estimate how much space compressing the files under a path would free up
+#[tauri::command]
+async fn estimate_compression_savings(
+  path: String,
+  sample_bytes: u64,
+) -> Result<CompressionEstimate, String> {
+  let cache = indexed_cache_snapshot()?
+    .ok_or_else(|| "No scan data available. Please scan a directory first.".to_string())?;
+  let target_dir = resolve_cached_path(&cache, &path)?;
+
+  // Reading and compressing samples is blocking work
+  tokio::task::spawn_blocking(move || estimate_compression(&cache, &target_dir, sample_bytes))
+    .await
+    .map_err(|e| format!("Compression estimate failed: {}", e))
+}
+
+/// Bytes owned by one user among a directory's direct children
+#[derive(Clone, Debug, Serialize)]
+struct OwnerUsage {
+  /// Owner name
+  owner_name: String,
+  /// Total size in bytes of the children owned by this user
+  size_bytes: u64,
+}
+
+/// A directory whose direct children belong to several owners
+#[derive(Clone, Debug, Serialize)]
+struct MixedOwnershipDir {
+  /// Path to the directory
+  path: PathBuf,
+  /// Owner of the directory itself
+  owner_name: Option<String>,
+  /// Owners of the direct children, largest first
+  owners: Vec<OwnerUsage>,
+}
+
+// Finds directories whose direct children have at least `min_owners` distinct owners
+fn find_mixed_ownership_dirs(cache: &ScanCache, min_owners: usize) -> Vec<MixedOwnershipDir> {
+  let mut results: Vec<MixedOwnershipDir> = cache
+    .children_map
+    .par_iter()
+    .filter_map(|(parent_path, children)| {
+      // Children without a known owner are left out of the count
+      let mut owner_sizes: HashMap<&str, u64> = HashMap::new();
+      for &child_index in children {
+        let child = &cache.entries[child_index];
+        if let Some(owner_name) = child.owner_name.as_deref() {
+          *owner_sizes.entry(owner_name).or_insert(0) += child.size_bytes;
+        }
+      }
+      if owner_sizes.len() < min_owners.max(1) {
+        return None;
+      }
+
+      let mut owners: Vec<OwnerUsage> = owner_sizes
+        .into_iter()
+        .map(|(owner_name, size_bytes)| OwnerUsage {
+          owner_name: owner_name.to_string(),
+          size_bytes,
+        })
+        .collect();
+      owners.sort_by(|a, b| {
+        b.size_bytes
+          .cmp(&a.size_bytes)
+          .then_with(|| a.owner_name.cmp(&b.owner_name))
+      });
+
+      let owner_name = cache
+        .path_map
+        .get(parent_path)
+        .and_then(|&index| cache.entries[index].owner_name.clone());
+      Some(MixedOwnershipDir {
+        path: parent_path.clone(),
+        owner_name,
+        owners,
+      })
+    })
+    .collect();
+
+  // Most diverse directories first
+  results.sort_by(|a, b| {
+    b.owners
+      .len()
+      .cmp(&a.owners.len())
+      .then_with(|| a.path.cmp(&b.path))
+  });
+  results
+}
+
+// Command to list directories whose direct children belong to many different owners
+#[tauri::command]
+async fn get_mixed_ownership_dirs(min_owners: usize) -> Result<Vec<MixedOwnershipDir>, String> {
+  let cache = indexed_cache_snapshot()?
+    .ok_or_else(|| "No scan data available. Please scan a directory first.".to_string())?;
+
+  Ok(find_mixed_ownership_dirs(&cache, min_owners))
+}
+
+// A node for `entry` with nothing counted yet, for totals of just `owner`'s entries
+fn empty_owner_node(entry: &AnalyticsInfo, owner: &str) -> FileSystemTreeNode {
+  FileSystemTreeNode {
+    path: entry.path.clone(),
+    name: node_name(&entry.path),
+    percent_of_parent: 100.0,
+    percent_of_parent_allocated: 100.0,
+    owner_name: Some(owner.to_string()),
+    file_kind_hint: entry_kind_hint(entry),
+    is_symlink: is_symlink_entry(entry),
+    symlink_target: entry_symlink_target(entry),
+    ..Default::default()
+  }
+}
+
+// Totals of what `owner` contributes to the subtree at `index`: their files, and the own
+// size of their directories. Directories count when they hold any of it. None if nothing
+// in the subtree is theirs
+fn owner_subtree_node(cache: &ScanCache, index: usize, owner: &str) -> Option<FileSystemTreeNode> {
+  let root = &cache.entries[index];
+  let mut node = empty_owner_node(root, owner);
+  let mut directories: std::collections::HashSet<&Path> = std::collections::HashSet::new();
+  let mut owned_leaves = 0;
+
+  let mut stack = vec![index];
+  while let Some(index) = stack.pop() {
+    let entry = &cache.entries[index];
+    let is_dir = is_directory_entry(entry);
+    if is_dir {
+      if let Some(children) = cache.children_map.get(&entry.path) {
+        stack.extend(children.iter().copied());
+      }
+    }
+    if entry.owner_name.as_deref() != Some(owner) {
+      continue;
+    }
+
+    let (size_bytes, size_allocated_bytes) = if is_dir {
+      own_sizes(entry)
+    } else {
+      (entry.size_bytes, entry.size_allocated_bytes)
+    };
+    node.size_bytes += size_bytes;
+    node.size_allocated_bytes += size_allocated_bytes;
+    node.last_modified_time = node.last_modified_time.max(entry.last_modified_time);
+    if !is_dir {
+      node.file_count += entry.file_count;
+      owned_leaves += 1;
+    }
+
+    // Every directory from here up to the subtree root now holds some of the owner's bytes
+    let mut directory = if is_dir {
+      Some(entry.path.as_path())
+    } else {
+      entry.path.parent()
+    };
+    while let Some(dir) = directory.filter(|dir| dir.starts_with(&root.path)) {
+      if !directories.insert(dir) {
+        break;
+      }
+      directory = dir.parent();
+    }
+  }
+
+  if owned_leaves == 0 && directories.is_empty() {
+    return None;
+  }
+  node.directory_count = directories.len() as u64;
+  node.entry_count = owned_leaves + node.directory_count;
+  Some(node)
+}
+
+// Lists the children of `path` holding any of `owner`'s bytes, with every size counting only
+// what is theirs
+fn build_owner_tree(cache: &ScanCache, path: &Path, owner: &str) -> Option<FileSystemTreeNode> {
+  let &index = cache.path_map.get(path)?;
+  let entry = &cache.entries[index];
+  if !is_directory_entry(entry) {
+    return Some(
+      owner_subtree_node(cache, index, owner).unwrap_or_else(|| empty_owner_node(entry, owner)),
+    );
+  }
+
+  let mut children: Vec<FileSystemTreeNode> = cache
+    .children_map
+    .get(path)
+    .into_iter()
+    .flatten()
+    .filter_map(|&child_index| owner_subtree_node(cache, child_index, owner))
+    .collect();
+
+  let mut tree = empty_owner_node(entry, owner);
+  if entry.owner_name.as_deref() == Some(owner) {
+    (tree.size_bytes, tree.size_allocated_bytes) = own_sizes(entry);
+    tree.last_modified_time = entry.last_modified_time;
+  }
+  for child in &children {
+    tree.size_bytes += child.size_bytes;
+    tree.size_allocated_bytes += child.size_allocated_bytes;
+    tree.entry_count += child.entry_count;
+    tree.file_count += child.file_count;
+    tree.directory_count += child.directory_count;
+    tree.last_modified_time = tree.last_modified_time.max(child.last_modified_time);
+  }
+  if tree.size_bytes > 0 || !children.is_empty() {
+    tree.entry_count += 1;
+    tree.directory_count += 1;
+  }
+
+  for child in &mut children {
+    child.percent_of_parent = percent_of(child.size_bytes, tree.size_bytes);
+    child.percent_of_parent_allocated =
+      percent_of(child.size_allocated_bytes, tree.size_allocated_bytes);
+  }
+  children.sort_by(by_size_then_path);
+  tree.children = children;
+  Some(tree)
+}
+
+// Command to drill into one owner's share of a directory, e.g. from an owner breakdown
+#[tauri::command]
+async fn get_children_by_owner(path: String, owner: String) -> Result<FileSystemTreeNode, String> {
+  let cache = indexed_cache_snapshot()?
+    .ok_or_else(|| "No scan data available. Please scan a directory first.".to_string())?;
+  let target_dir = resolve_cached_path(&cache, &path)?;
+
+  build_owner_tree(&cache, &target_dir, &owner)
+    .ok_or_else(|| format!("Path {} not found in scan data", target_dir.display()))
+}
+
+/// Result of moving a single path with `move_paths`
+#[derive(Clone, Debug, Serialize)]
+struct MovePathResult {
+  /// The path that was requested to be moved
+  source: PathBuf,
+  /// Where the path ended up (only set on success)
+  destination: Option<PathBuf>,
+  /// Whether the move succeeded
+  success: bool,
+  /// Error message if the move failed
+  error: Option<String>,
+}
+
+/// Progress of a cross-device move, emitted as `move-progress` events
+#[derive(Clone, Debug, Serialize)]
+struct MoveProgress {
+  /// The path being moved
+  source: PathBuf,
+  /// Where the path is being copied to
+  destination: PathBuf,
+  /// Bytes copied so far
+  bytes_copied: u64,
+  /// Total bytes to copy
+  total_bytes: u64,
+}
+
+// Size of the buffer used when streaming a copy across filesystems
+const MOVE_COPY_BUFFER_SIZE: usize = 1024 * 1024;
+// Minimum number of copied bytes between two `move-progress` events
+const MOVE_PROGRESS_INTERVAL_BYTES: u64 = 8 * 1024 * 1024;
+
+// Removes a file, symlink or whole directory tree
+fn remove_path(path: &Path) -> std::io::Result<()> {
+  if std::fs::symlink_metadata(path)?.is_dir() {
+    std::fs::remove_dir_all(path)
+  } else {
+    std::fs::remove_file(path)
+  }
+}
+
+// Sums the apparent size of every file under a path without following symlinks
+fn total_copy_size(path: &Path) -> u64 {
+  let mut total = 0;
+  let mut stack = vec![path.to_path_buf()];
+
+  while let Some(current) = stack.pop() {
+    let Ok(metadata) = std::fs::symlink_metadata(&current) else {
+      continue;
+    };
+
+    if metadata.is_dir() {
+      if let Ok(dir_entries) = std::fs::read_dir(&current) {
+        stack.extend(dir_entries.flatten().map(|entry| entry.path()));
+      }
+    } else if metadata.is_file() {
+      total += metadata.len();
+    }
+  }
+
+  total
+}
+
+// Copies a path (file, symlink or directory tree) to `destination`, streaming file
+// contents and reporting progress at most every `MOVE_PROGRESS_INTERVAL_BYTES`
+fn copy_path_with_progress(
+  source: &Path,
+  destination: &Path,
+  progress: &mut MoveProgress,
+  on_progress: &mut dyn FnMut(&MoveProgress),
+) -> std::io::Result<()> {
+  use std::io::{Read, Write};
+
+  let mut buffer = vec![0u8; MOVE_COPY_BUFFER_SIZE];
+  let mut last_emitted = progress.bytes_copied;
+  // Use an explicit stack so deep trees don't overflow the call stack
+  let mut stack = vec![(source.to_path_buf(), destination.to_path_buf())];
+
+  while let Some((src, dst)) = stack.pop() {
+    let metadata = std::fs::symlink_metadata(&src)?;
+
+    if metadata.file_type().is_symlink() {
+      platform::copy_symlink(&src, &dst)?;
+    } else if metadata.is_dir() {
+      std::fs::create_dir(&dst)?;
+      for entry in std::fs::read_dir(&src)? {
+        let entry = entry?;
+        stack.push((entry.path(), dst.join(entry.file_name())));
+      }
+    } else if metadata.is_file() {
+      let mut reader = std::fs::File::open(&src)?;
+      let mut writer = std::fs::File::create(&dst)?;
+
+      loop {
+        let read = reader.read(&mut buffer)?;
+        if read == 0 {
+          break;
+        }
+        writer.write_all(&buffer[..read])?;
+
+        progress.bytes_copied += read as u64;
+        if progress.bytes_copied - last_emitted >= MOVE_PROGRESS_INTERVAL_BYTES {
+          on_progress(progress);
+          last_emitted = progress.bytes_copied;
+        }
+      }
+
+      // Keep the original permissions and modification time like a rename would
+      writer.set_permissions(metadata.permissions())?;
+      if let Ok(modified) = metadata.modified() {
+        let _ = writer.set_modified(modified);
+      }
+    } else {
+      return Err(std::io::Error::new(
+        std::io::ErrorKind::Unsupported,
+        format!("Cannot copy special file {}", src.display()),
+      ));
+    }
+  }
+
+  Ok(())
+}
+
+// Whether a path is one of the platform's protected system directories, as given or once
+// resolved (e.g. `/usr/../etc`). Scanned paths are canonical, which on Windows means
+// verbatim (`\\?\C:\Windows`), so both sides are compared without that prefix
+fn is_protected_root(path: &Path) -> bool {
+  let candidates: Vec<PathBuf> = std::iter::once(path.to_path_buf())
+    .chain(path.canonicalize().ok())
+    .map(|candidate| platform::strip_verbatim_prefix(&candidate))
+    .collect();
+  platform::protected_roots().iter().any(|root| {
+    let root = platform::strip_verbatim_prefix(root);
+    candidates.iter().any(|candidate| {
+      *candidate == root
+        || (platform::has_case_insensitive_paths() && paths_eq_ignore_case(candidate, &root))
+    })
+  })
+}
+
+// Refuses mutations on protected system directories unless they were explicitly allowed
+fn check_protected_paths(paths: &[PathBuf], allow_protected: bool) -> Result<(), String> {
+  if allow_protected {
+    return Ok(());
+  }
+  match paths.iter().find(|path| is_protected_root(path)) {
+    Some(path) => Err(format!(
+      "{} is a protected system directory, pass allow_protected to modify it anyway",
+      path.display()
+    )),
+    None => Ok(()),
+  }
+}
+
+// Converts a path picked from the tree to the form the cache stores, so it finds its entry
+// Only the parent is canonicalized, so a symlink stays the link rather than its target.
+// Paths whose parent doesn't exist are left as given, for the operation to report
+fn resolve_source_path(cache: Option<&ScanCache>, path: &str) -> PathBuf {
+  let expanded = expand_input_path(path, dirs::home_dir().as_deref());
+  let canonical = match (expanded.parent(), expanded.file_name()) {
+    (Some(parent), Some(name)) => parent.canonicalize().ok().map(|parent| parent.join(name)),
+    // A root like `/` or `C:\`, or a path ending in `..`
+    _ => expanded.canonicalize().ok(),
+  };
+  let resolved = canonical.unwrap_or(expanded);
+
+  // Canonicalizing doesn't always restore the on-disk case, see resolve_cached_path
+  match cache {
+    Some(cache)
+      if platform::has_case_insensitive_paths()
+        && cache.indexed
+        && !cache.path_map.contains_key(&resolved) =>
+    {
+      find_path_ignoring_case(cache, &resolved).unwrap_or(resolved)
+    }
+    _ => resolved,
+  }
+}
+
+// Refuses to act on a path that no longer is what the cached scan saw, e.g. a file that was
+// replaced or turned into a symlink since, or that the scan never saw inside its roots.
+// Paths outside the scanned roots can't be checked
+fn check_unchanged_since_scan(cache: &ScanCache, path: &Path) -> Result<(), String> {
+  let Some(&index) = cache.path_map.get(path) else {
+    if cache.contains_path(path) {
+      return Err(format!(
+        "{} is not in the scan, rescan or pass force to proceed anyway",
+        path.display()
+      ));
+    }
+    return Ok(());
+  };
+  let Some(cached) = cache.entries[index].path_info.as_ref() else {
+    return Ok(());
+  };
+  // A missing path is left for the operation itself to report
+  let Some(current) = platform::get_path_info_with_allocation(path, false, false) else {
+    return Ok(());
+  };
+
+  let changed = cached.is_symlink != current.is_symlink
+    || cached.is_dir != current.is_dir
+    // Followed links were scanned with their target's identity, so only compare the rest
+    || (!cached.is_symlink
+      && matches!(
+        (cached.inode_device, current.inode_device),
+        (Some(cached_id), Some(current_id)) if cached_id != current_id
+      ))
+    // Directory sizes are subtree totals, only a file's own size can be compared
+    || (cached.is_file && !cached.is_symlink && cached.size_bytes != current.size_bytes);
+  if changed {
+    return Err(format!(
+      "{} changed since the scan, rescan or pass force to proceed anyway",
+      path.display()
+    ));
+  }
+  Ok(())
+}
+
+// Moves a single path into `destination_dir`, preserving its file name
+// Falls back to a streaming copy followed by a delete when the rename crosses filesystems
+fn move_path(
+  source: &Path,
+  destination_dir: &Path,
+  overwrite: bool,
+  on_progress: &mut dyn FnMut(&MoveProgress),
+) -> std::io::Result<PathBuf> {
+  use std::io::{Error, ErrorKind};
+
+  let file_name = source
+    .file_name()
+    .ok_or_else(|| Error::new(ErrorKind::InvalidInput, "Path has no file name"))?;
+  let destination = destination_dir.join(file_name);
+
+  // Don't follow symlinks, the link itself is what gets moved
+  let source_metadata = std::fs::symlink_metadata(source)?;
+
+  // Refuse to move a directory into itself or one of its descendants
+  if source_metadata.is_dir() {
+    if let (Ok(src), Ok(dst)) = (source.canonicalize(), destination_dir.canonicalize()) {
+      if dst.starts_with(&src) {
+        return Err(Error::new(
+          ErrorKind::InvalidInput,
+          "Cannot move a directory into itself",
+        ));
+      }
+    }
+  }
+
+  // Moving a path into the folder it is already in would make it its own destination, and
+  // overwriting would delete it. The parents are compared so a symlink isn't resolved
+  if let (Some(Ok(source_parent)), Ok(destination_dir)) = (
+    source.parent().map(Path::canonicalize),
+    destination_dir.canonicalize(),
+  ) {
+    if source_parent == destination_dir {
+      return Err(Error::new(
+        ErrorKind::InvalidInput,
+        format!(
+          "{} is already in {}",
+          source.display(),
+          destination_dir.display()
+        ),
+      ));
+    }
+  }
+
+  let destination_exists = std::fs::symlink_metadata(&destination).is_ok();
+  if destination_exists && !overwrite {
+    return Err(Error::new(
+      ErrorKind::AlreadyExists,
+      format!("{} already exists", destination.display()),
+    ));
+  }
+
+  // What gets replaced is only removed once the source is next to it, so a failed move
+  // leaves it in place
+  let renamed_to = if destination_exists {
+    staging_path(&destination)
+  } else {
+    destination.clone()
+  };
+  match std::fs::rename(source, &renamed_to) {
+    Ok(()) => {
+      if destination_exists {
+        if let Err(e) = replace_path(&renamed_to, &destination) {
+          // Put the source back where it was
+          let _ = std::fs::rename(&renamed_to, source);
+          return Err(e);
+        }
+      }
+      Ok(destination)
+    }
+    Err(e) if e.kind() == ErrorKind::CrossesDevices => {
+      copy_into_place(source, &destination, on_progress)?;
+      remove_path(source)?;
+      Ok(destination)
+    }
+    Err(e) => Err(e),
+  }
+}
+
+// Temporary name next to `destination` for a move that isn't complete yet
+fn staging_path(destination: &Path) -> PathBuf {
+  let mut name = std::ffi::OsString::from(".");
+  name.push(destination.file_name().unwrap_or_default());
+  name.push(format!(".moving-{}", std::process::id()));
+  destination.with_file_name(name)
+}
+
+// Moves `staged` to `destination`, removing whatever is there. Files are renamed over it in
+// one step, directories can't be and have to be removed first
+fn replace_path(staged: &Path, destination: &Path) -> std::io::Result<()> {
+  let is_dir = |path: &Path| std::fs::symlink_metadata(path).is_ok_and(|m| m.is_dir());
+  if is_dir(staged) || is_dir(destination) {
+    remove_path(destination)?;
+  }
+  std::fs::rename(staged, destination)
+}
+
+// Copies `source` to `destination` for a move across filesystems. The copy is made under a
+// temporary name and only replaces `destination` once complete, so a failed copy leaves
+// whatever was there untouched
+fn copy_into_place(
+  source: &Path,
+  destination: &Path,
+  on_progress: &mut dyn FnMut(&MoveProgress),
+) -> std::io::Result<()> {
+  let staged = staging_path(destination);
+  let mut progress = MoveProgress {
+    source: source.to_path_buf(),
+    destination: destination.to_path_buf(),
+    bytes_copied: 0,
+    total_bytes: total_copy_size(source),
+  };
+
+  let copied = copy_path_with_progress(source, &staged, &mut progress, on_progress)
+    .and_then(|()| replace_path(&staged, destination));
+  if copied.is_err() {
+    // Don't leave a half-copied tree behind
+    let _ = remove_path(&staged);
+    return copied;
+  }
+  on_progress(&progress);
+  Ok(())
+}
+
+// This function removes a path and its descendants from the cache, subtracting their
+// totals from every cached ancestor, then rebuilds the indices
+fn remove_path_from_cache(cache: &mut ScanCache, path: &Path) -> bool {
+  remove_paths_from_cache(cache, &[path.to_path_buf()]) > 0
+}
+
+// Removes several paths and their descendants at once, with a single pass over the entries
+// and a single rebuild of the indices however many paths there are. Returns how many of the
+// paths were cached
+fn remove_paths_from_cache(cache: &mut ScanCache, paths: &[PathBuf]) -> usize {
+  let removed = detach_paths_from_cache(cache, paths);
+  if removed.is_empty() {
+    return 0;
+  }
+  rebuild_cache_indices(cache);
+  for path in &removed {
+    refresh_ancestor_depths(cache, path);
+  }
+  removed.len()
+}
+
+// Subtracts the cached subtrees at `paths` from their ancestors' totals and drops them from
+// the entries and roots. Needs up-to-date indices, and leaves them for the caller to rebuild
+// once it's done changing entries. Returns the paths that were cached
+fn detach_paths_from_cache(cache: &mut ScanCache, paths: &[PathBuf]) -> Vec<PathBuf> {
+  let cached: std::collections::HashSet<&Path> = paths
+    .iter()
+    .map(PathBuf::as_path)
+    .filter(|path| cache.path_map.contains_key(*path))
+    .collect();
+  // A path inside another removed one goes with it, and mustn't be subtracted twice
+  let removed: Vec<PathBuf> = cached
+    .iter()
+    .filter(|path| {
+      !path
+        .ancestors()
+        .skip(1)
+        .any(|ancestor| cached.contains(ancestor))
+    })
+    .map(|path| path.to_path_buf())
+    .collect();
+  if removed.is_empty() {
+    return removed;
+  }
+
+  for path in &removed {
+    let subtree = cache.entries[cache.path_map[path]].clone();
+    adjust_ancestor_totals(cache, path, &subtree, false);
+  }
+
+  let removed_set: std::collections::HashSet<&Path> =
+    removed.iter().map(PathBuf::as_path).collect();
+  let is_removed = |path: &Path| {
+    path
+      .ancestors()
+      .any(|ancestor| removed_set.contains(ancestor))
+  };
+  cache.entries.retain(|entry| !is_removed(&entry.path));
+  cache.roots.retain(|root| !is_removed(root));
+  cache.indexed = false;
+
+  removed
+}
+
+// Recomputes the depth of every cached ancestor of `path` from its children, after the
+// subtree at `path` was removed or replaced. Needs up-to-date indices
+fn refresh_ancestor_depths(cache: &mut ScanCache, path: &Path) {
+  let mut ancestor = path.parent();
+  while let Some(ancestor_path) = ancestor {
+    if let Some(&idx) = cache.path_map.get(ancestor_path) {
+      let depth = cache
+        .children_map
+        .get(ancestor_path)
+        .into_iter()
+        .flatten()
+        .map(|&child| {
+          let child = &cache.entries[child];
+          if is_symlink_entry(child) {
+            1
+          } else {
+            child.max_child_depth + 1
+          }
+        })
+        .max()
+        .unwrap_or(0);
+      Arc::make_mut(&mut cache.entries[idx]).max_child_depth = depth;
+    }
+
+    if ancestor_path == cache.root_path {
+      break;
+    }
+    ancestor = ancestor_path.parent();
+  }
+}
+
+// Adds (or subtracts) a subtree's totals to every cached ancestor of `path` up to the root
+fn adjust_ancestor_totals(cache: &mut ScanCache, path: &Path, subtree: &AnalyticsInfo, add: bool) {
+  let apply = |total: u64, amount: u64| {
+    if add {
+      total.saturating_add(amount)
+    } else {
+      total.saturating_sub(amount)
+    }
+  };
+
+  let mut ancestor = path.parent();
+  while let Some(ancestor_path) = ancestor {
+    if let Some(&idx) = cache.path_map.get(ancestor_path) {
+      let analytics = Arc::make_mut(&mut cache.entries[idx]);
+      analytics.size_bytes = apply(analytics.size_bytes, subtree.size_bytes);
+      analytics.size_allocated_bytes =
+        apply(analytics.size_allocated_bytes, subtree.size_allocated_bytes);
+      analytics.unique_bytes = apply(analytics.unique_bytes, subtree.unique_bytes);
+      analytics.unique_allocated_bytes = apply(
+        analytics.unique_allocated_bytes,
+        subtree.unique_allocated_bytes,
+      );
+      analytics.entry_count = apply(analytics.entry_count, subtree.entry_count);
+      analytics.file_count = apply(analytics.file_count, subtree.file_count);
+      analytics.directory_count = apply(analytics.directory_count, subtree.directory_count);
+    }
+
+    if ancestor_path == cache.root_path {
+      break;
+    }
+    ancestor = ancestor_path.parent();
+  }
+}
+
+// Rebuilds the path and children indices after the cache's entries changed
+fn rebuild_cache_indices(cache: &mut ScanCache) {
+  let Some((path_map, children_map, sorted_by_size)) = compute_cache_indices(
+    &cache.entries,
+    &cache.root_path,
+    &cache.roots,
+    &IndexCancelToken::never(),
+  ) else {
+    return;
+  };
+  cache.total_bytes = path_map
+    .get(&cache.root_path)
+    .map_or(0, |&index| cache.entries[index].size_bytes);
+  cache.path_map = path_map;
+  cache.children_map = children_map;
+  cache.sorted_by_size = sorted_by_size;
+  cache.indexed = true;
+}
+
+// Builds the indices for a cache's entries, including merged roots
+fn compute_cache_indices(
+  entries: &[Arc<AnalyticsInfo>],
+  root_path: &Path,
+  roots: &[PathBuf],
+  cancel: &IndexCancelToken,
+) -> Option<CacheIndices> {
+  let (path_map, mut children_map, sorted_by_size) = build_indices(entries, root_path, cancel)?;
+
+  // Merged roots are listed directly under the synthetic root, however deep they really are
+  if !roots.iter().any(|root| root == root_path) {
+    let mut root_indices: Vec<usize> = roots
+      .iter()
+      .filter_map(|root| path_map.get(root).copied())
+      .collect();
+    root_indices.sort_by(|&a, &b| {
+      entries[b]
+        .size_bytes
+        .cmp(&entries[a].size_bytes)
+        .then_with(|| entries[a].path.cmp(&entries[b].path))
+    });
+    children_map.insert(root_path.to_path_buf(), root_indices);
+  }
+
+  Some((path_map, children_map, sorted_by_size))
+}
+
+// Longest path that every root lies under, empty when they share nothing (e.g. two drives)
+fn common_ancestor(roots: &[PathBuf]) -> PathBuf {
+  let Some((first, rest)) = roots.split_first() else {
+    return PathBuf::new();
+  };
+  let mut ancestor = first.clone();
+  for root in rest {
+    while !root.starts_with(&ancestor) {
+      if !ancestor.pop() {
+        return PathBuf::new();
+      }
+    }
+  }
+  ancestor
+}
+
+// Splices a finished scan of `root` into an existing cache
+// A root inside an already-scanned root replaces that subtree; any other root is added next
+// to the existing ones, under a synthetic parent spanning all of them
+fn merge_into_cache(cache: &mut ScanCache, root: &Path, entries: Vec<Arc<AnalyticsInfo>>) {
+  let Some(new_root) = entries.iter().find(|entry| entry.path == root).cloned() else {
+    return;
+  };
+  if !cache.indexed {
+    rebuild_cache_indices(cache);
+  }
+
+  if cache
+    .roots
+    .iter()
+    .any(|existing| root.starts_with(existing))
+  {
+    // Swap the old subtree's totals for the new ones, while the indices still match the
+    // entries, then rebuild them once
+    let is_root = cache.roots.iter().any(|existing| existing == root);
+    adjust_ancestor_totals(cache, root, &new_root, true);
+    detach_paths_from_cache(cache, &[root.to_path_buf()]);
+    if is_root {
+      cache.roots.push(root.to_path_buf());
+    }
+    cache.entries.extend(entries);
+    rebuild_cache_indices(cache);
+    refresh_ancestor_depths(cache, root);
+    return;
+  }
+
+  // The synthetic parent is recomputed from scratch below
+  if cache.has_synthetic_root() {
+    let synthetic_root = cache.root_path.clone();
+    cache.entries.retain(|entry| entry.path != synthetic_root);
+  }
+
+  // Roots inside the new one are superseded by it
+  cache.roots.retain(|existing| !existing.starts_with(root));
+  cache.entries.retain(|entry| !entry.path.starts_with(root));
+  cache.roots.push(root.to_path_buf());
+  cache.entries.extend(entries);
+
+  if cache.roots.len() == 1 {
+    cache.root_path = root.to_path_buf();
+  } else {
+    cache.root_path = common_ancestor(&cache.roots);
+    let root_entries: Vec<Arc<AnalyticsInfo>> = cache
+      .entries
+      .iter()
+      .filter(|entry| cache.roots.contains(&entry.path))
+      .cloned()
+      .collect();
+    cache.entries.push(Arc::new(AnalyticsInfo {
+      path: cache.root_path.clone(),
+      size_bytes: root_entries.iter().map(|e| e.size_bytes).sum(),
+      size_allocated_bytes: root_entries.iter().map(|e| e.size_allocated_bytes).sum(),
+      unique_bytes: root_entries.iter().map(|e| e.unique_bytes).sum(),
+      unique_allocated_bytes: root_entries.iter().map(|e| e.unique_allocated_bytes).sum(),
+      entry_count: root_entries.iter().map(|e| e.entry_count).sum(),
+      file_count: root_entries.iter().map(|e| e.file_count).sum(),
+      directory_count: root_entries.iter().map(|e| e.directory_count).sum(),
+      max_child_depth: root_entries
+        .iter()
+        .map(|e| e.max_child_depth + 1)
+        .max()
+        .unwrap_or(0),
+      skipped_children: 0,
+      last_modified_time: root_entries
+        .iter()
+        .map(|e| e.last_modified_time)
+        .max()
+        .unwrap_or(0),
+      owner_name: None,
+      path_info: None,
+      content_type: None,
+      link_target: None,
+      content_hash: None,
+    }));
+  }
+
+  rebuild_cache_indices(cache);
+}
+
+// Merges a finished scan into the global cache, returning the merged root and its tree
+// Returns None when there is no cache to merge into, or a newer scan or clear superseded
+// this one, in which case the scan is handled like a normal one
+fn merge_scan_into_global_cache(
+  root: &Path,
+  entries: Vec<Arc<AnalyticsInfo>>,
+  generation: u64,
+  scan_time_ms: u64,
+  partial: bool,
+) -> Option<(PathBuf, FileSystemTreeNode)> {
+  let mut global_cache = GLOBAL_SCAN_CACHE.write().ok()?;
+  if CACHE_GENERATION.load(Ordering::SeqCst) != generation {
+    return None;
+  }
+  // Readers holding a snapshot keep the cache as it was before the merge
+  let cache = Arc::make_mut(global_cache.as_mut()?);
+
+  merge_into_cache(cache, root, entries);
+  cache.generation = generation;
+  cache.scan_time_ms += scan_time_ms;
+  cache.partial |= partial;
+
+  let tree = build_tree_from_indices(
+    &cache.entries,
+    &cache.path_map,
+    &cache.children_map,
+    &cache.root_path,
+    1,
+    true,
+  )?;
+  Some((cache.root_path.clone(), tree))
+}
+
+// Command to move files or directories into another folder
+#[tauri::command]
+async fn move_paths(
+  paths: Vec<String>,
+  destination: String,
+  overwrite: Option<bool>,
+  allow_protected: Option<bool>,
+  force: Option<bool>,
+  window: tauri::Window,
+) -> Result<Vec<MovePathResult>, String> {
+  // Sources are picked from the cached tree, which may be stale by now. Resolving them to
+  // the cached form lets them be checked against and removed from the scan
+  let cache = indexed_cache_snapshot()?;
+  let sources: Vec<PathBuf> = paths
+    .iter()
+    .map(|path| resolve_source_path(cache.as_deref(), path))
+    .collect();
+  check_protected_paths(&sources, allow_protected.unwrap_or(false))?;
+  let cache = cache.filter(|_| !force.unwrap_or(false));
+
+  let destination_dir = PathBuf::from(&destination);
+  if !destination_dir.is_dir() {
+    return Err(format!(
+      "Destination {} is not a directory",
+      destination_dir.display()
+    ));
+  }
+  let overwrite = overwrite.unwrap_or(false);
+
+  // Moving can mean copying gigabytes across devices, so keep it off the async runtime
+  let results = tokio::task::spawn_blocking(move || {
+    let mut on_progress = |progress: &MoveProgress| {
+      if let Err(e) = window.emit("move-progress", progress) {
+        eprintln!("Failed to emit move progress: {}", e);
+      }
+    };
+
+    sources
+      .into_iter()
+      .map(|source| {
+        let moved = cache
+          .as_ref()
+          .map_or(Ok(()), |cache| check_unchanged_since_scan(cache, &source))
+          .and_then(|()| {
+            move_path(&source, &destination_dir, overwrite, &mut on_progress)
+              .map_err(|e| e.to_string())
+          });
+        match moved {
+          Ok(moved_to) => MovePathResult {
+            source,
+            destination: Some(moved_to),
+            success: true,
+            error: None,
+          },
+          Err(e) => MovePathResult {
+            source,
+            destination: None,
+            success: false,
+            error: Some(e),
+          },
+        }
+      })
+      .collect::<Vec<_>>()
+  })
+  .await
+  .map_err(|e| format!("Move task failed: {}", e))?;
+
+  // Remove the moved entries from the cached scan so the tree stays accurate
+  if let Ok(mut global_cache) = GLOBAL_SCAN_CACHE.write() {
+    if let Some(cache) = global_cache.as_mut().map(Arc::make_mut) {
+      if !cache.indexed {
+        rebuild_cache_indices(cache);
+      }
+      let moved: Vec<PathBuf> = results
+        .iter()
+        .filter(|r| r.success)
+        .map(|r| r.source.clone())
+        .collect();
+      remove_paths_from_cache(cache, &moved);
+    }
+  }
+
+  Ok(results)
+}
+
+#[cfg_attr(mobile, tauri::mobile_entry_point)]
+pub fn run() {
+  // Create a multi-threaded Tokio runtime
+  let runtime = tokio::runtime::Runtime::new().expect("Failed to create Tokio runtime");
+
+  // Set the runtime as the default for this thread
+  let _guard = runtime.enter();
+
+  tauri::Builder::default()
+    .plugin(tauri_plugin_opener::init())
+    .plugin(tauri_plugin_dialog::init())
+    .plugin(tauri_plugin_fs::init())
+    .plugin(tauri_plugin_shell::init())
+    .invoke_handler(tauri::generate_handler![
+      scan_directory_size,
+      get_free_space,
+      get_space_info,
+      get_directory_children, // Add the new command
+      clear_scan_cache,       // Add cache clearing command
+      move_paths,
+      get_scan_info,
+      get_directory_size,
+      export_du,
+      get_mixed_ownership_dirs,
+      recompute_percentages,
+      scan_single_directory,
+      build_index_now,
+      get_sparse_files,
+      get_directory_dominant_type,
+      get_flame_data,
+      get_directory_entropy,
+      export_sqlite,
+      get_allocation_efficiency,
+      estimate_compression_savings,
+      get_heavy_directories,
+      diff_subtrees,
+      get_scan_fingerprint,
+      locate_and_expand,
+      get_freshness_scores,
+      projected_free_space,
+      find_immutable_files,
+      export_text_tree,
+      get_entries_in_window,
+      get_current_root_size,
+      get_cache_memory_estimate,
+      export_ndjson,
+      get_heaviest_path,
+      get_scan_state,
+      get_children_by_owner,
+      start_space_monitor,
+      stop_space_monitor,
+      snapshot_size,
+      size_delta_since_snapshot,
+      get_linear_layout,
+      get_extension_stats,
+      export_structure,
+      get_child_mount_points,
+      validate_scan_target,
+      get_extension_by_toplevel,
+      get_size_trend,
+      list_removable_drives,
+      analyze_junk
+    ])
+    .run(tauri::generate_context!())
+    .expect("error while running tauri application");
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::fs::{self, File};
+  use std::io::Write;
+  use std::sync::Mutex;
+  use tempfile::tempdir;
+
+  // Scans a directory and builds a fully indexed cache for it, like a finished scan would
+  fn scan_into_cache(path: &Path) -> std::io::Result<ScanCache> {
+    scan_into_cache_with(path, &ScanOptions::default())
+  }
+
+  // Same as scan_into_cache, with non-default scan options
+  fn scan_into_cache_with(path: &Path, options: &ScanOptions) -> std::io::Result<ScanCache> {
+    let state = ScanState::default();
+    calculate_size_sync(path, &state, options, None)?;
+    let analytics_map = state.analytics_map;
+
+    let entries = analytics_map_to_entries(&analytics_map);
+    let (path_map, children_map, sorted_by_size) =
+      build_indices(&entries, path, &IndexCancelToken::never()).unwrap();
+    Ok(ScanCache {
+      generation: 0,
+      total_bytes: analytics_map.get(path).map_or(0, |root| root.size_bytes),
+      root_path: path.to_path_buf(),
+      roots: vec![path.to_path_buf()],
+      entries,
+      path_map,
+      children_map,
+      sorted_by_size,
+      indexed: true,
+      scanned_at: SystemTime::now(),
+      scan_time_ms: 0,
+      partial: false,
+    })
+  }
+
+  // Serializes tests that read or write GLOBAL_SCAN_CACHE
+  static GLOBAL_CACHE_TEST_LOCK: tokio::sync::Mutex<()> = tokio::sync::Mutex::const_new(());
+
+  // Builds a synthetic entry, for tests that need data a real scan can't easily produce
+  fn test_entry(path: &str, size_bytes: u64, owner_name: Option<&str>) -> Arc<AnalyticsInfo> {
+    Arc::new(AnalyticsInfo {
+      path: PathBuf::from(path),
+      size_bytes,
+      size_allocated_bytes: size_bytes,
+      unique_bytes: size_bytes,
+      unique_allocated_bytes: size_bytes,
+      entry_count: 1,
+      file_count: 1,
+      directory_count: 0,
+      max_child_depth: 0,
+      skipped_children: 0,
+      last_modified_time: 0,
+      owner_name: owner_name.map(str::to_string),
+      path_info: None,
+      content_type: None,
+      link_target: None,
+      content_hash: None,
+    })
+  }
+
+  // Builds an indexed cache from synthetic entries
+  fn test_cache(root_path: &str, entries: Vec<Arc<AnalyticsInfo>>) -> ScanCache {
+    let root_path = PathBuf::from(root_path);
+    let (path_map, children_map, sorted_by_size) =
+      build_indices(&entries, &root_path, &IndexCancelToken::never()).unwrap();
+    ScanCache {
+      generation: 0,
+      total_bytes: path_map
+        .get(&root_path)
+        .map_or(0, |&index| entries[index].size_bytes),
+      roots: vec![root_path.clone()],
+      root_path,
+      entries,
+      path_map,
+      children_map,
+      sorted_by_size,
+      indexed: true,
+      scanned_at: SystemTime::now(),
+      scan_time_ms: 0,
+      partial: false,
+    }
+  }
+
+  #[tokio::test]
+  async fn test_calculate_size_empty_directory() -> std::io::Result<()> {
+    // Create a temporary directory for testing
+    let temp_dir = tempdir()?;
+    let path = temp_dir.path().to_path_buf();
+
+    // Create analytics map and visited inodes
+    let state = ScanState::default();
+
+    // Run the function
+    calculate_size_sync(path.as_path(), &state, &ScanOptions::default(), None)?;
+    let analytics_map = state.analytics_map;
+
+    // Verify the results
+    assert!(
+      analytics_map.contains_key(&path),
+      "Path should be in the analytics map"
+    );
+
+    let analytics = analytics_map.get(&path).unwrap();
+    // Directories have different size behaviors on different platforms
+    #[cfg(target_family = "unix")]
+    assert!(
+      analytics.size_bytes > 0,
+      "Directory should have a size on Unix filesystems"
+    );
+
+    // On Windows, empty directories might report a size of 0
+    #[cfg(target_os = "windows")]
+    assert!(
+      analytics.size_bytes >= 0,
+      "Directory size might be 0 on Windows"
+    );
+
+    assert_eq!(analytics.entry_count, 1, "Should count itself as one entry");
+    assert_eq!(analytics.file_count, 0, "Should have 0 files");
+    assert_eq!(
+      analytics.directory_count, 1,
+      "Should count itself as one directory"
+    );
+
+    Ok(())
+  }
+
+  #[tokio::test]
+  async fn test_calculate_size_with_files() -> std::io::Result<()> {
+    // Create a temporary directory with files
+    let temp_dir = tempdir()?;
+    let path = temp_dir.path().to_path_buf();
+
+    // Create a file with known content
+    let file_path = path.join("test_file.txt");
+    let mut file = File::create(&file_path)?;
+    let test_data = "Hello, world!";
+    file.write_all(test_data.as_bytes())?;
+
+    // Create analytics map and visited inodes
+    let state = ScanState::default();
+
+    // Run the function
+    calculate_size_sync(path.as_path(), &state, &ScanOptions::default(), None)?;
+    let analytics_map = state.analytics_map;
+
+    // Verify the results
+    assert!(
+      analytics_map.contains_key(&path),
+      "Path should be in the analytics map"
+    );
+
+    let analytics = analytics_map.get(&path).unwrap();
+    assert!(
+      analytics.size_bytes >= test_data.len() as u64,
+      "Directory size should include the file size"
+    );
+    assert_eq!(analytics.entry_count, 2, "Should count itself and one file");
+    assert_eq!(analytics.file_count, 1, "Should have 1 file");
+    assert_eq!(
+      analytics.directory_count, 1,
+      "Should count itself as one directory"
+    );
+
+    Ok(())
+  }
+
+  #[tokio::test]
+  async fn test_calculate_size_with_subdirectories() -> std::io::Result<()> {
+    // Create a temporary directory with subdirectories and files
+    let temp_dir = tempdir()?;
+    let path = temp_dir.path().to_path_buf();
+
+    // Create a subdirectory
+    let subdir_path = path.join("subdir");
+    fs::create_dir(&subdir_path)?;
+
+    // Create a file in the subdirectory
+    let file_path = subdir_path.join("test_file.txt");
+    let mut file = File::create(&file_path)?;
+    let test_data = "Hello, nested world!";
+    file.write_all(test_data.as_bytes())?;
+
+    // Create another file in the root directory
+    let root_file_path = path.join("root_file.txt");
+    let mut root_file = File::create(&root_file_path)?;
+    let root_test_data = "Root data";
+    root_file.write_all(root_test_data.as_bytes())?;
+
+    // Create analytics map and visited inodes
+    let state = ScanState::default();
+
+    // Run the function
+    calculate_size_sync(path.as_path(), &state, &ScanOptions::default(), None)?;
+    let analytics_map = state.analytics_map;
+
+    // Verify the results for the root directory
+    assert!(
+      analytics_map.contains_key(&path),
+      "Root path should be in the analytics map"
+    );
+
+    let root_analytics = analytics_map.get(&path).unwrap();
+    let expected_size = (test_data.len() + root_test_data.len()) as u64;
+    assert!(
+      root_analytics.size_bytes >= expected_size,
+      "Directory size should include all files and subdirectories"
+    );
+    assert_eq!(
+      root_analytics.entry_count, 4,
+      "Should count root dir, subdir, and 2 files"
+    );
+    assert_eq!(root_analytics.file_count, 2, "Should have 2 files");
+    assert_eq!(
+      root_analytics.directory_count, 2,
+      "Should count root and subdirectory"
+    );
+
+    // Verify the results for the subdirectory
+    assert!(
+      analytics_map.contains_key(&subdir_path),
+      "Subdir path should be in the analytics map"
+    );
+
+    let subdir_analytics = analytics_map.get(&subdir_path).unwrap();
+    assert!(
+      subdir_analytics.size_bytes >= test_data.len() as u64,
+      "Subdirectory size should include its file size"
+    );
+    assert_eq!(
+      subdir_analytics.entry_count, 2,
+      "Should count subdir and its file"
+    );
+    assert_eq!(subdir_analytics.file_count, 1, "Should have 1 file");
+    assert_eq!(
+      subdir_analytics.directory_count, 1,
+      "Should count itself as one directory"
+    );
+
+    Ok(())
+  }
+
+  #[tokio::test]
+  #[cfg(not(target_os = "windows"))]
+  async fn test_calculate_size_with_symlink() -> std::io::Result<()> {
+    // Create a temporary directory with a symlink
+    let temp_dir = tempdir()?;
+    let path = temp_dir.path().to_path_buf();
+
+    // Create a file with known content
+    let file_path = path.join("test_file.txt");
+    let mut file = File::create(&file_path)?;
+    let test_data = "Hello, world!";
+    file.write_all(test_data.as_bytes())?;
+
+    // Create a symlink to the file
+    let symlink_path = path.join("test_symlink");
+    std::os::unix::fs::symlink(&file_path, &symlink_path)?;
+
+    // Create analytics map and visited inodes
+    let state = ScanState::default();
+
+    // Run the function
+    calculate_size_sync(path.as_path(), &state, &ScanOptions::default(), None)?;
+    let analytics_map = state.analytics_map;
+
+    // Verify the results
+    assert!(
+      analytics_map.contains_key(&path),
+      "Path should be in the analytics map"
+    );
+
+    let analytics = analytics_map.get(&path).unwrap();
+    // The symlink is counted in the entry count, but not as a file
+    // In some implementations, symlinks might be treated differently
+    assert!(
+      analytics.entry_count >= 2,
+      "Should count at least the directory and file"
+    );
+    assert_eq!(
+      analytics.file_count, 1,
+      "Should count only the actual file, not the symlink as a file"
+    );
+
+    // Let's also check that the symlink exists
+    assert!(symlink_path.exists(), "Symlink should exist");
+
+    Ok(())
+  }
+
+  // A manual benchmark that can be run with cargo test -- --ignored
+  #[tokio::test]
+  #[ignore]
+  async fn benchmark_rayon_parallel_vs_sequential() -> std::io::Result<()> {
+    // Choose a directory to scan - use home directory for a more realistic test
+    let test_dir = dirs::home_dir()
+      .unwrap_or_else(|| std::env::current_dir().unwrap())
+      .to_path_buf();
+
+    println!("Benchmarking directory: {:?}", test_dir);
+
+    // First measure with parallelism
+    let start = std::time::Instant::now();
+    {
+      let state = ScanState::default();
+
+      // Use Rayon's default thread pool (parallel)
+      calculate_size_sync(test_dir.as_path(), &state, &ScanOptions::default(), None)?;
+      let analytics_map = state.analytics_map;
+
+      println!("Parallel scan found {} entries", analytics_map.len());
+    }
+    let parallel_duration = start.elapsed();
+    println!("Parallel processing took: {:?}", parallel_duration);
+
+    // Then measure with single thread
+    let start = std::time::Instant::now();
+    {
+      let state = ScanState::default();
+
+      // Create a single-threaded pool to simulate sequential processing
+      let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(1)
+        .build()
+        .unwrap();
+
+      pool.install(|| {
+        let _ = calculate_size_sync(test_dir.as_path(), &state, &ScanOptions::default(), None);
+      });
+
+      println!(
+        "Sequential scan found {} entries",
+        state.analytics_map.len()
+      );
+    }
+    let sequential_duration = start.elapsed();
+    println!("Sequential processing took: {:?}", sequential_duration);
+
+    // Calculate and print the speedup
+    let speedup = sequential_duration.as_secs_f64() / parallel_duration.as_secs_f64();
+    println!("Speedup from parallelism: {:.2}x", speedup);
+
+    Ok(())
+  }
+
+  // A manual benchmark of reading each child's info from what read_dir returned against
+  // asking the filesystem again, which is what slow storage pays for. Point
+  // TREE_SIZE_BENCH_DIR at a network share to see the difference there
+  #[test]
+  #[ignore]
+  fn benchmark_prefetched_metadata_vs_stat() {
+    fn list_entries(dir: &Path, entries: &mut Vec<(PathBuf, PrefetchedEntry)>) {
+      let Ok(read_dir) = fs::read_dir(dir) else {
+        return;
+      };
+      for entry in read_dir.flatten() {
+        let prefetched = PrefetchedEntry::from_dir_entry(&entry);
+        let is_dir = prefetched.file_type.is_some_and(|t| t.is_dir());
+        entries.push((entry.path(), prefetched));
+        if is_dir {
+          list_entries(&entry.path(), entries);
+        }
+      }
+    }
+
+    let test_dir = std::env::var_os("TREE_SIZE_BENCH_DIR")
+      .map(PathBuf::from)
+      .or_else(dirs::home_dir)
+      .unwrap_or_else(|| std::env::current_dir().unwrap());
+    println!("Benchmarking directory: {:?}", test_dir);
+
+    let mut entries = Vec::new();
+    list_entries(&test_dir, &mut entries);
+
+    // Every child checked for being a link, then stat'ed
+    let start = std::time::Instant::now();
+    let stat_count = entries
+      .par_iter()
+      .filter(|(path, _)| {
+        platform::get_path_info_with_allocation(path, path.is_symlink(), true).is_some()
+      })
+      .count();
+    let stat_duration = start.elapsed();
+    println!(
+      "Stat'ing read {} of {} entries in {:?}",
+      stat_count,
+      entries.len(),
+      stat_duration
+    );
+
+    // The type, and on Windows the metadata, taken from the listing
+    let start = std::time::Instant::now();
+    let prefetched_count = entries
+      .par_iter()
+      .filter(|(path, prefetched)| match &prefetched.metadata {
+        Some(metadata) => platform::get_path_info_from_metadata(path, metadata, true).is_some(),
+        None => {
+          let is_symlink = prefetched
+            .file_type
+            .map_or_else(|| path.is_symlink(), |t| t.is_symlink());
+          platform::get_path_info_with_allocation(path, is_symlink, true).is_some()
+        }
+      })
+      .count();
+    let prefetched_duration = start.elapsed();
+    println!(
+      "Prefetching read {} of {} entries in {:?}",
+      prefetched_count,
+      entries.len(),
+      prefetched_duration
+    );
+
+    let speedup = stat_duration.as_secs_f64() / prefetched_duration.as_secs_f64();
+    println!("Speedup from prefetching: {:.2}x", speedup);
+  }
+
+  #[tokio::test]
+  #[cfg(target_family = "unix")]
+  async fn test_owner_name_unix() -> std::io::Result<()> {
+    // Create a temporary directory with a file
+    let temp_dir = tempdir()?;
+    let path = temp_dir.path().to_path_buf();
+    let file_path = path.join("test_owner.txt");
+    let mut file = File::create(&file_path)?;
+    file.write_all(b"Testing owner")?;
+
+    // Get path info directly
+    let path_info = platform::get_path_info(&file_path, false);
+    assert!(path_info.is_some(), "Should get path info");
+
+    let info = path_info.unwrap();
+    assert!(info.owner_name.is_some(), "Should have owner name on Unix");
+
+    // Current user should be the owner of the file we just created
+    let current_user = std::env::var("USER").or_else(|_| std::env::var("LOGNAME"));
+    if let Ok(username) = current_user {
+      assert_eq!(
+        info.owner_name.as_deref(),
+        Some(username.as_str()),
+        "File should be owned by current user"
+      );
+    }
+
+    // Test through the analytics map
+    let state = ScanState::default();
+
+    calculate_size_sync(path.as_path(), &state, &ScanOptions::default(), None)?;
+    let analytics_map = state.analytics_map;
+
+    // Convert to entries and check owner_name is preserved
+    let entries = analytics_map_to_entries(&analytics_map);
+    let file_entry = entries.iter().find(|e| e.path == file_path);
+
+    assert!(file_entry.is_some(), "File entry should exist");
+    if let Some(entry) = file_entry {
+      assert!(
+        entry.owner_name.is_some(),
+        "Owner name should be present in entry"
+      );
+    }
+
+    Ok(())
+  }
+
+  #[tokio::test]
+  #[cfg(target_os = "windows")]
+  async fn test_owner_name_windows() -> std::io::Result<()> {
+    use std::fs::metadata;
+
+    // Create a temporary directory with a file
+    let temp_dir = tempdir()?;
+    let path = temp_dir.path().to_path_buf();
+    let file_path = path.join("test_owner.txt");
+    let mut file = File::create(&file_path)?;
+    file.write_all(b"Testing owner")?;
+
+    // Get metadata directly first to verify file creation
+    let file_metadata = metadata(&file_path)?;
+    assert!(file_metadata.is_file(), "Should be a file");
+
+    // Get path info directly with debug output
+    println!("Attempting to get path info for: {:?}", file_path);
+    let path_info = platform::get_path_info(&file_path, false);
+
+    match &path_info {
+      Some(info) => {
+        println!("Got path info with owner: {:?}", info.owner_name);
+        assert!(
+          info.owner_name.is_some(),
+          "Should have owner name on Windows"
+        );
+      }
+      None => {
+        panic!("Failed to get path info for file");
+      }
+    }
+
+    // Test through the analytics map
+    let state = ScanState::default();
+
+    calculate_size_sync(path.as_path(), &state, &ScanOptions::default(), None)?;
+    let analytics_map = state.analytics_map;
+
+    // Convert to entries and check owner_name is preserved
+    let entries = analytics_map_to_entries(&analytics_map);
+    let file_entry = entries.iter().find(|e| e.path == file_path);
+
+    match file_entry {
+      Some(entry) => {
+        println!("Found file entry with owner: {:?}", entry.owner_name);
+        assert!(
+          entry.owner_name.is_some(),
+          "Owner name should be present in entry"
+        );
+        assert!(
+          !entry.owner_name.as_ref().unwrap().is_empty(),
+          "Owner name should not be empty"
+        );
+      }
+      None => {
+        panic!("File entry not found in analytics map");
+      }
+    }
+
+    Ok(())
+  }
+
+  #[tokio::test]
+  async fn test_min_display_size_rolls_small_files_into_others() -> std::io::Result<()> {
+    // Create a temporary directory with one large file, two tiny files and an empty subdir
+    let temp_dir = tempdir()?;
+    let path = temp_dir.path().to_path_buf();
+    fs::create_dir(path.join("subdir"))?;
+    File::create(path.join("large.bin"))?.write_all(&[0u8; 4096])?;
+    File::create(path.join("tiny_a.txt"))?.write_all(b"a")?;
+    File::create(path.join("tiny_b.txt"))?.write_all(b"bb")?;
+
+    let state = ScanState::default();
+    calculate_size_sync(path.as_path(), &state, &ScanOptions::default(), None)?;
+    let analytics_map = state.analytics_map;
+
+    let entries = analytics_map_to_entries(&analytics_map);
+    let mut tree = build_tree_from_entries_with_depth(&entries, &path, 1, true);
+    let total_before = tree.size_bytes;
+    apply_min_display_size(&mut tree, 1024);
+
+    // The subdirectory is never hidden, even though it is below the threshold
+    assert!(
+      tree.children.iter().any(|c| c.name == "subdir"),
+      "Directories should never be hidden"
+    );
+    assert_eq!(tree.size_bytes, total_before, "Totals should be unchanged");
+
+    // The tiny files are rolled into an [others] node inside the virtual files node
+    let virtual_node = tree
+      .children
+      .iter()
+      .find(|c| c.is_virtual_directory)
+      .expect("Virtual files node should exist");
+    let others = virtual_node
+      .children
+      .iter()
+      .find(|c| c.name == "[others]")
+      .expect("Others node should exist");
+    assert_eq!(others.file_count, 2, "Should summarize both tiny files");
+    assert_eq!(others.size_bytes, 3, "Should sum the tiny files' bytes");
+    assert!(
+      virtual_node.children.iter().any(|c| c.name == "large.bin"),
+      "Large file should still be listed"
+    );
+
+    Ok(())
+  }
+
+  #[test]
+  fn test_replaced_file_is_refused_after_scan() -> std::io::Result<()> {
+    let source_dir = tempdir()?;
+    let destination_dir = tempdir()?;
+    let root = source_dir.path().canonicalize()?;
+    let file = root.join("report.txt");
+    fs::write(&file, b"original contents")?;
+    fs::write(root.join("kept.txt"), b"kept")?;
+
+    let cache = scan_into_cache(&root)?;
+    assert!(check_unchanged_since_scan(&cache, &file).is_ok());
+    assert!(check_unchanged_since_scan(&cache, &root).is_ok());
+
+    // Another file takes the old one's place between the scan and the move
+    fs::remove_file(&file)?;
+    fs::write(&file, b"a different file entirely")?;
+    let err = check_unchanged_since_scan(&cache, &file).unwrap_err();
+    assert!(err.contains("changed since the scan"));
+
+    // A replacement of the same size is told apart by its identity
+    let same_size = root.join("same_size.txt");
+    fs::write(&same_size, b"0123456789")?;
+    let cache = scan_into_cache(&root)?;
+    let replacement = root.join("replacement.txt");
+    fs::write(&replacement, b"abcdefghij")?;
+    fs::rename(&replacement, &same_size)?;
+    assert!(check_unchanged_since_scan(&cache, &same_size).is_err());
+
+    // Untouched entries and paths outside the scan are let through, but not paths inside it
+    // the scan never saw
+    assert!(check_unchanged_since_scan(&cache, &root.join("kept.txt")).is_ok());
+    assert!(check_unchanged_since_scan(&cache, &destination_dir.path().join("x")).is_ok());
+    fs::write(root.join("new.txt"), b"new")?;
+    let err = check_unchanged_since_scan(&cache, &root.join("new.txt")).unwrap_err();
+    assert!(err.contains("not in the scan"), "{}", err);
+
+    // A file swapped for a symlink is refused as well
+    #[cfg(unix)]
+    {
+      fs::remove_file(root.join("kept.txt"))?;
+      std::os::unix::fs::symlink(&file, root.join("kept.txt"))?;
+      assert!(check_unchanged_since_scan(&cache, &root.join("kept.txt")).is_err());
+    }
+    Ok(())
+  }
+
+  #[test]
+  fn test_resolve_source_path_finds_cached_entries() -> std::io::Result<()> {
+    let temp_dir = tempdir()?;
+    let root = temp_dir.path().canonicalize()?;
+    fs::create_dir(root.join("sub"))?;
+    fs::write(root.join("file.txt"), b"file")?;
+    let cache = scan_into_cache(&root)?;
+
+    // A roundabout spelling of a cached path resolves to its entry
+    let roundabout = root.join("sub").join("..").join("file.txt");
+    let resolved = resolve_source_path(Some(&cache), roundabout.to_str().unwrap());
+    assert_eq!(resolved, root.join("file.txt"));
+    assert!(cache.path_map.contains_key(&resolved));
+
+    // A symlink is the link itself, not where it points
+    #[cfg(unix)]
+    {
+      let link = root.join("sub").join("link.txt");
+      std::os::unix::fs::symlink(root.join("file.txt"), &link)?;
+      assert_eq!(resolve_source_path(None, link.to_str().unwrap()), link);
+    }
+
+    // Paths that don't exist are left for the move to report
+    let missing = root.join("missing").join("file.txt");
+    assert_eq!(
+      resolve_source_path(Some(&cache), missing.to_str().unwrap()),
+      missing
+    );
+    Ok(())
+  }
+
+  #[tokio::test]
+  async fn test_move_path_refuses_overwrite_and_updates_cache() -> std::io::Result<()> {
+    let source_dir = tempdir()?;
+    let destination_dir = tempdir()?;
+    let root = source_dir.path().to_path_buf();
+
+    // Source tree: root/sub/a.bin and root/b.bin
+    let sub = root.join("sub");
+    fs::create_dir(&sub)?;
+    File::create(sub.join("a.bin"))?.write_all(&[1u8; 1000])?;
+    File::create(root.join("b.bin"))?.write_all(b"source")?;
+    // The destination already has a b.bin
+    File::create(destination_dir.path().join("b.bin"))?.write_all(b"existing")?;
+
+    let mut cache = scan_into_cache(&root)?;
+    let root_before = cache.entries[cache.path_map[&root]].clone();
+    let sub_before = cache.entries[cache.path_map[&sub]].clone();
+
+    let mut no_progress = |_: &MoveProgress| {};
+
+    // Existing files at the destination are not overwritten by default
+    let err = move_path(
+      &root.join("b.bin"),
+      destination_dir.path(),
+      false,
+      &mut no_progress,
+    )
+    .unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::AlreadyExists);
+    assert_eq!(fs::read(destination_dir.path().join("b.bin"))?, b"existing");
+
+    // ...unless overwrite is requested
+    move_path(
+      &root.join("b.bin"),
+      destination_dir.path(),
+      true,
+      &mut no_progress,
+    )?;
+    assert_eq!(fs::read(destination_dir.path().join("b.bin"))?, b"source");
+
+    // Moving a directory preserves its name and contents
+    let moved_to = move_path(&sub, destination_dir.path(), false, &mut no_progress)?;
+    assert_eq!(moved_to, destination_dir.path().join("sub"));
+    assert!(moved_to.join("a.bin").exists(), "Moved file should exist");
+    assert!(!sub.exists(), "Source directory should be gone");
+
+    // The cache drops the subtree and adjusts the root's totals
+    assert!(remove_path_from_cache(&mut cache, &sub));
+    assert!(!cache.path_map.contains_key(&sub.join("a.bin")));
+    let root_after = &cache.entries[cache.path_map[&root]];
+    assert_eq!(
+      root_after.size_bytes,
+      root_before.size_bytes - sub_before.size_bytes
+    );
+    assert_eq!(
+      root_after.entry_count,
+      root_before.entry_count - sub_before.entry_count
+    );
+    assert_eq!(root_after.directory_count, 1, "Only the root should remain");
+
+    Ok(())
+  }
+
+  #[test]
+  fn test_remove_paths_from_cache_in_one_pass() -> std::io::Result<()> {
+    let temp_dir = tempdir()?;
+    let root = temp_dir.path().canonicalize()?;
+    fs::create_dir_all(root.join("sub").join("deep"))?;
+    fs::write(root.join("sub").join("deep").join("a.bin"), vec![0u8; 1000])?;
+    fs::write(root.join("b.bin"), vec![0u8; 200])?;
+    fs::write(root.join("c.bin"), vec![0u8; 30])?;
+    let mut cache = scan_into_cache(&root)?;
+    let root_before = cache.entries[cache.path_map[&root]].clone();
+    let sub_before = cache.entries[cache.path_map[&root.join("sub")]].clone();
+    let b_before = cache.entries[cache.path_map[&root.join("b.bin")]].clone();
+
+    // A path inside another removed one and a path that was never cached count for nothing
+    let removed = remove_paths_from_cache(
+      &mut cache,
+      &[
+        root.join("sub"),
+        root.join("sub").join("deep"),
+        root.join("b.bin"),
+        root.join("missing.bin"),
+      ],
+    );
+    assert_eq!(removed, 2);
+    assert!(cache.indexed);
+    assert!(!cache.path_map.contains_key(&root.join("sub").join("deep")));
+    assert!(cache.path_map.contains_key(&root.join("c.bin")));
+
+    let root_after = &cache.entries[cache.path_map[&root]];
+    assert_eq!(
+      root_after.size_bytes,
+      root_before.size_bytes - sub_before.size_bytes - b_before.size_bytes
+    );
+    assert_eq!(
+      root_after.entry_count,
+      root_before.entry_count - sub_before.entry_count - b_before.entry_count
+    );
+    assert_eq!(root_after.max_child_depth, 1);
+    Ok(())
+  }
+
+  #[test]
+  fn test_move_path_never_loses_the_destination() -> std::io::Result<()> {
+    let temp_dir = tempdir()?;
+    let folder = temp_dir.path().canonicalize()?;
+    fs::write(folder.join("data.txt"), b"keep me")?;
+    let mut no_progress = |_: &MoveProgress| {};
+
+    // Moving into the folder the path is already in is refused, even with overwrite
+    let err = move_path(&folder.join("data.txt"), &folder, true, &mut no_progress).unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+    assert_eq!(fs::read(folder.join("data.txt"))?, b"keep me");
+
+    // A copy that fails partway leaves the existing destination as it was, and nothing else
+    let destination_dir = tempdir()?;
+    let destination = destination_dir.path().join("tree");
+    fs::create_dir(&destination)?;
+    fs::write(destination.join("old.txt"), b"old")?;
+    let source = folder.join("tree");
+    fs::create_dir(&source)?;
+    fs::write(source.join("new.txt"), b"new")?;
+    #[cfg(unix)]
+    let _socket = std::os::unix::net::UnixListener::bind(source.join("socket"))?;
+    #[cfg(not(unix))]
+    fs::remove_dir_all(&source)?;
+    assert!(copy_into_place(&source, &destination, &mut no_progress).is_err());
+    assert_eq!(fs::read(destination.join("old.txt"))?, b"old");
+    assert_eq!(fs::read_dir(destination_dir.path())?.count(), 1);
+
+    // A complete copy replaces it
+    let _ = fs::remove_dir_all(&source);
+    fs::create_dir(&source)?;
+    fs::write(source.join("new.txt"), b"new")?;
+    copy_into_place(&source, &destination, &mut no_progress)?;
+    assert_eq!(fs::read(destination.join("new.txt"))?, b"new");
+    assert!(!destination.join("old.txt").exists());
+    assert_eq!(fs::read_dir(destination_dir.path())?.count(), 1);
+    Ok(())
+  }
+
+  #[tokio::test]
+  async fn test_copy_path_with_progress_reports_all_bytes() -> std::io::Result<()> {
+    let source_dir = tempdir()?;
+    let destination_dir = tempdir()?;
+    let source = source_dir.path().join("data");
+    fs::create_dir_all(source.join("nested"))?;
+    File::create(source.join("one.bin"))?.write_all(&[7u8; 3000])?;
+    File::create(source.join("nested").join("two.bin"))?.write_all(&[9u8; 500])?;
+
+    let destination = destination_dir.path().join("data");
+    let mut progress = MoveProgress {
+      source: source.clone(),
+      destination: destination.clone(),
+      bytes_copied: 0,
+      total_bytes: total_copy_size(&source),
+    };
+    copy_path_with_progress(&source, &destination, &mut progress, &mut |_| {})?;
+
+    assert_eq!(progress.total_bytes, 3500);
+    assert_eq!(progress.bytes_copied, progress.total_bytes);
+    assert_eq!(
+      fs::read(destination.join("nested").join("two.bin"))?.len(),
+      500
+    );
+
+    Ok(())
+  }
+
+  #[test]
+  fn test_calculate_size_deeply_nested_directories() -> std::io::Result<()> {
+    // Build a very deep chain of nested directories. Absolute paths are capped by the OS
+    // (PATH_MAX is 4096 on Linux), so stop early if the path becomes too long to create
+    let temp_dir = tempdir()?;
+    let path = temp_dir.path().to_path_buf();
+    let mut deepest = path.clone();
+    let mut levels = 0;
+    while levels < 5000 {
+      let next = deepest.join("d");
+      if fs::create_dir(&next).is_err() {
+        break;
+      }
+      deepest = next;
+      levels += 1;
+    }
+    // Leave room for the leaf file at the bottom of the chain
+    while File::create(deepest.join("leaf.txt")).is_err() {
+      fs::remove_dir(&deepest)?;
+      deepest.pop();
+      levels -= 1;
+    }
+    fs::write(deepest.join("leaf.txt"), b"leaf")?;
+    // Deep enough to have overflowed the stack when scanning recursed per level. macOS caps
+    // paths at 1024 bytes, which only leaves room for about 500 levels
+    let min_levels = if cfg!(target_os = "macos") { 400 } else { 1000 };
+    assert!(
+      levels >= min_levels,
+      "Only {} levels could be created, the test would prove nothing",
+      levels
+    );
+
+    let state = ScanState::default();
+    calculate_size_sync(path.as_path(), &state, &ScanOptions::default(), None)?;
+    let analytics_map = state.analytics_map;
+
+    let root = analytics_map.get(&path).unwrap();
+    assert_eq!(
+      root.directory_count,
+      levels + 1,
+      "Every nested directory should be counted"
+    );
+    assert_eq!(root.file_count, 1, "The leaf file should be counted");
+
+    Ok(())
+  }
+
+  #[tokio::test]
+  async fn test_scan_options_max_depth_and_excludes() -> std::io::Result<()> {
+    // root/keep/deep/file.txt, root/skip/file.txt, root/top.txt
+    let temp_dir = tempdir()?;
+    let path = temp_dir.path().to_path_buf();
+    fs::create_dir_all(path.join("keep").join("deep"))?;
+    fs::create_dir(path.join("skip"))?;
+    fs::write(path.join("keep").join("deep").join("file.txt"), b"deep")?;
+    fs::write(path.join("skip").join("file.txt"), b"skipped")?;
+    fs::write(path.join("top.txt"), b"top")?;
+
+    let options = ScanOptions {
+      max_depth: Some(1),
+      exclude_names: vec!["skip".to_string()],
+      ..Default::default()
+    };
+    let state = ScanState::default();
+    calculate_size_sync(path.as_path(), &state, &options, None)?;
+    let analytics_map = state.analytics_map;
+
+    // The excluded directory is skipped entirely
+    assert!(!analytics_map.contains_key(&path.join("skip")));
+    // Directories at the depth limit are recorded but not read
+    assert!(analytics_map.contains_key(&path.join("keep")));
+    assert!(!analytics_map.contains_key(&path.join("keep").join("deep")));
+
+    let root = analytics_map.get(&path).unwrap();
+    assert_eq!(root.file_count, 1, "Only top.txt should be counted");
+    assert_eq!(root.directory_count, 2, "Should count root and keep");
+
+    Ok(())
+  }
+
+  #[tokio::test]
+  #[cfg(target_family = "unix")]
+  async fn test_symlink_cycle_is_reported() -> std::io::Result<()> {
+    // root/dir/loop -> root, a symlink back to an ancestor
+    let temp_dir = tempdir()?;
+    let path = temp_dir.path().canonicalize()?;
+    fs::create_dir(path.join("dir"))?;
+    fs::write(path.join("dir").join("file.txt"), b"data")?;
+    let link_path = path.join("dir").join("loop");
+    std::os::unix::fs::symlink(&path, &link_path)?;
+
+    let state = ScanState::default();
+    calculate_size_sync(path.as_path(), &state, &ScanOptions::default(), None)?;
+
+    let symlink_cycles = &state.symlink_cycles;
+    assert_eq!(symlink_cycles.len(), 1, "The loop should be reported once");
+    assert_eq!(
+      symlink_cycles.get(&link_path).map(|target| target.clone()),
+      Some(path.clone()),
+      "The cycle should point back at the root"
+    );
+
+    // The root's contents are still only counted once
+    let root = state.analytics_map.get(&path).unwrap();
+    assert_eq!(root.file_count, 1);
+
+    Ok(())
+  }
+
+  #[tokio::test]
+  async fn test_preview_events_report_root_children() -> std::io::Result<()> {
+    let temp_dir = tempdir()?;
+    let path = temp_dir.path().to_path_buf();
+    fs::create_dir(path.join("dir"))?;
+    fs::write(path.join("dir").join("nested.txt"), b"nested")?;
+    fs::write(path.join("file.txt"), b"file")?;
+
+    let events = Mutex::new(Vec::new());
+    let observer = |event: ScanEvent| events.lock().unwrap().push(event);
+    let options = ScanOptions {
+      preview: true,
+      ..Default::default()
+    };
+    let state = ScanState::default();
+    calculate_size_sync(path.as_path(), &state, &options, Some(&observer))?;
+
+    let events = events.into_inner().unwrap();
+    // The preview comes first and lists every direct child with placeholder sizes
+    let Some(ScanEvent::Preview(preview)) = events.first() else {
+      panic!("The first event should be the preview");
+    };
+    let mut names: Vec<_> = preview.children.iter().map(|c| c.name.as_str()).collect();
+    names.sort();
+    assert_eq!(names, vec!["dir", "file.txt"]);
+    assert!(preview.children.iter().all(|c| c.size_bytes == 0));
+
+    // Each direct child is then reported once with its final size, nested entries are not
+    let mut partial: Vec<_> = events[1..]
+      .iter()
+      .map(|event| match event {
+        ScanEvent::PartialNode(node) => (node.name.clone(), node.size_bytes),
+        ScanEvent::Preview(_) => panic!("Only one preview should be emitted"),
+      })
+      .collect();
+    partial.sort();
+    let dir_size = state
+      .analytics_map
+      .get(&path.join("dir"))
+      .unwrap()
+      .size_bytes;
+    assert_eq!(
+      partial,
+      vec![("dir".to_string(), dir_size), ("file.txt".to_string(), 4)]
+    );
+
+    // Without the flag nothing is emitted
+    let events = Mutex::new(Vec::new());
+    let observer = |event: ScanEvent| events.lock().unwrap().push(event);
+    calculate_size_sync(
+      path.as_path(),
+      &ScanState::default(),
+      &ScanOptions::default(),
+      Some(&observer),
+    )?;
+    assert!(events.into_inner().unwrap().is_empty());
+
+    Ok(())
+  }
+
+  #[tokio::test]
+  async fn test_du_export_lists_directories_children_first() -> std::io::Result<()> {
+    let temp_dir = tempdir()?;
+    let path = temp_dir.path().canonicalize()?;
+    fs::create_dir_all(path.join("a").join("b"))?;
+    fs::write(path.join("a").join("b").join("file.bin"), vec![0u8; 1500])?;
+    fs::write(path.join("top.txt"), b"top")?;
+
+    let cache = scan_into_cache(&path)?;
+    let mut output = Vec::new();
+    write_du_export(&cache, &mut output, true)?;
+    let output = String::from_utf8(output).unwrap();
+    let lines: Vec<(u64, PathBuf)> = output
+      .lines()
+      .map(|line| {
+        let (size, line_path) = line.split_once('\t').unwrap();
+        (size.parse().unwrap(), PathBuf::from(line_path))
+      })
+      .collect();
+
+    // Only directories are listed, deepest first and the root last
+    let paths: Vec<&PathBuf> = lines.iter().map(|(_, p)| p).collect();
+    assert_eq!(
+      paths,
+      vec![&path.join("a").join("b"), &path.join("a"), &path]
+    );
+
+    // Sizes are rounded up to whole kilobytes
+    for (size, line_path) in &lines {
+      let entry = &cache.entries[cache.path_map[line_path]];
+      assert_eq!(*size, entry.size_bytes.div_ceil(1024));
+    }
+
+    Ok(())
+  }
+
+  #[tokio::test]
+  async fn test_clear_discards_in_flight_index_build() -> std::io::Result<()> {
+    let _guard = GLOBAL_CACHE_TEST_LOCK.lock().await;
+    let temp_dir = tempdir()?;
+    let path = temp_dir.path().canonicalize()?;
+    fs::write(path.join("file.txt"), b"data")?;
+
+    // A scan starts and its index build captures the generation
+    let generation = start_cache_generation(true).unwrap();
+    let cache = scan_into_cache(&path)?;
+
+    // The user clears the cache before the index build finishes
+    reset_scan_cache().unwrap();
+    assert!(!store_scan_cache(cache, generation));
+    assert!(cache_snapshot().unwrap().is_none());
+
+    Ok(())
+  }
+
+  #[test]
+  fn test_mixed_ownership_dirs() {
+    let cache = test_cache(
+      "/shared",
+      vec![
+        test_entry("/shared", 600, Some("root")),
+        test_entry("/shared/a.txt", 100, Some("alice")),
+        test_entry("/shared/b.txt", 200, Some("bob")),
+        test_entry("/shared/c.txt", 50, Some("alice")),
+        test_entry("/shared/home", 250, Some("carol")),
+        test_entry("/shared/home/notes.txt", 250, Some("carol")),
+      ],
+    );
+
+    let dirs = find_mixed_ownership_dirs(&cache, 3);
+    assert_eq!(
+      dirs.len(),
+      1,
+      "Only /shared has three owners among its children"
+    );
+    assert_eq!(dirs[0].path, PathBuf::from("/shared"));
+    assert_eq!(dirs[0].owner_name.as_deref(), Some("root"));
+    let owners: Vec<(&str, u64)> = dirs[0]
+      .owners
+      .iter()
+      .map(|o| (o.owner_name.as_str(), o.size_bytes))
+      .collect();
+    assert_eq!(owners, vec![("carol", 250), ("bob", 200), ("alice", 150)]);
+
+    assert_eq!(find_mixed_ownership_dirs(&cache, 4).len(), 0);
+    // Lower thresholds include directories with fewer owners, like /shared/home
+    assert_eq!(find_mixed_ownership_dirs(&cache, 1).len(), 2);
+  }
+
+  #[test]
+  fn test_compute_percentages_against_baseline() {
+    let cache = test_cache(
+      "/data",
+      vec![
+        test_entry("/data", 1000, None),
+        test_entry("/data/a", 600, None),
+        test_entry("/data/b", 300, None),
+        test_entry("/data/empty", 0, None),
+      ],
+    );
+
+    // Percentages are relative to the baseline, not to each node's parent
+    let nodes = vec![PathBuf::from("/data/a"), PathBuf::from("/data/b")];
+    let percentages = compute_percentages(&cache, &nodes, Path::new("/data/a")).unwrap();
+    assert_eq!(
+      percentages,
+      vec![
+        (PathBuf::from("/data/a"), 100.0),
+        (PathBuf::from("/data/b"), 50.0)
+      ]
+    );
+
+    // An empty baseline gives 0 rather than NaN
+    let percentages = compute_percentages(&cache, &nodes, Path::new("/data/empty")).unwrap();
+    assert!(percentages.iter().all(|(_, percent)| *percent == 0.0));
+
+    // Unknown paths are reported
+    let missing = vec![PathBuf::from("/data/missing")];
+    assert!(compute_percentages(&cache, &missing, Path::new("/data")).is_err());
+  }
+
+  #[test]
+  fn test_run_with_timeout() {
+    assert_eq!(run_with_timeout(Duration::from_secs(5), || 42), Some(42));
+
+    // A call that hangs past the timeout is abandoned
+    let hung = run_with_timeout(Duration::from_millis(10), || {
+      std::thread::sleep(Duration::from_secs(1));
+      42
+    });
+    assert_eq!(hung, None);
+
+    // With more slow calls than helper threads the extra ones wait in the queue, and the
+    // pool serves later calls once the slow ones finish
+    let spawned = |_| {
+      run_with_timeout(Duration::from_millis(1), || {
+        std::thread::sleep(Duration::from_millis(200));
+      })
+    };
+    assert!((0..TIMEOUT_POOL_THREADS * 2)
+      .map(spawned)
+      .all(|result| result.is_none()));
+    assert_eq!(run_with_timeout(Duration::from_secs(5), || 7), Some(7));
+  }
+
+  #[tokio::test]
+  async fn test_per_entry_timeout_scans_responsive_entries() -> std::io::Result<()> {
+    let temp_dir = tempdir()?;
+    let path = temp_dir.path().to_path_buf();
+    fs::create_dir(path.join("dir"))?;
+    fs::write(path.join("dir").join("file.txt"), b"data")?;
+
+    let options = ScanOptions {
+      per_entry_timeout_ms: Some(5000),
+      ..Default::default()
+    };
+    let state = ScanState::default();
+    calculate_size_sync(path.as_path(), &state, &options, None)?;
+
+    assert!(state.errors_sorted().is_empty());
+    let root = state.analytics_map.get(&path).unwrap();
+    assert_eq!(root.file_count, 1);
+    assert_eq!(root.directory_count, 2);
+
+    Ok(())
+  }
+
+  #[tokio::test]
+  async fn test_single_directory_tree_is_bounded() -> std::io::Result<()> {
+    let temp_dir = tempdir()?;
+    let path = temp_dir.path().to_path_buf();
+    fs::create_dir_all(path.join("a").join("b"))?;
+    fs::write(path.join("a").join("b").join("deep.txt"), b"deep")?;
+    fs::write(path.join("a").join("shallow.txt"), b"shallow")?;
+
+    let tree = build_single_directory_tree(&path, 2)?;
+    let a = tree.children.iter().find(|c| c.name == "a").unwrap();
+    assert!(a.size_bytes >= 7, "a should include shallow.txt");
+
+    // b sits at the depth limit, so its contents aren't scanned or shown
+    let b = a.children.iter().find(|c| c.name == "b").unwrap();
+    assert!(b.children.is_empty());
+    assert_eq!(b.file_count, 0);
+
+    Ok(())
+  }
+
+  #[test]
+  fn test_sparse_files_with_equal_holes_are_ordered_by_path() -> std::io::Result<()> {
+    let temp_dir = tempdir()?;
+    let file = temp_dir.path().join("file.bin");
+    fs::write(&file, b"file")?;
+    let file_info = platform::get_path_info(&file, false).unwrap();
+
+    let sparse = |path: &str| {
+      Arc::new(AnalyticsInfo {
+        size_allocated_bytes: 0,
+        path_info: Some(file_info.clone()),
+        ..(*test_entry(path, SPARSE_MIN_HOLE_BYTES, None)).clone()
+      })
+    };
+    let cache = test_cache(
+      "/data",
+      vec![
+        test_entry("/data", 3 * SPARSE_MIN_HOLE_BYTES, None),
+        sparse("/data/c.img"),
+        sparse("/data/a.img"),
+        sparse("/data/b.img"),
+      ],
+    );
+
+    let paths: Vec<PathBuf> = find_sparse_files(&cache, 2)
+      .into_iter()
+      .map(|file| file.path)
+      .collect();
+    assert_eq!(
+      paths,
+      vec![PathBuf::from("/data/a.img"), PathBuf::from("/data/b.img")]
+    );
+    Ok(())
+  }
+
+  #[tokio::test]
+  #[cfg(target_family = "unix")]
+  async fn test_sparse_files_report_hole_bytes() -> std::io::Result<()> {
+    let temp_dir = tempdir()?;
+    let path = temp_dir.path().canonicalize()?;
+
+    // Extending a file without writing leaves a hole on filesystems that support it
+    let sparse_path = path.join("disk.img");
+    File::create(&sparse_path)?.set_len(64 * 1024 * 1024)?;
+    fs::write(path.join("dense.bin"), vec![1u8; 2 * 1024 * 1024])?;
+
+    let cache = scan_into_cache(&path)?;
+    let sparse_files = find_sparse_files(&cache, 10);
+
+    let allocated = platform::get_path_info(&sparse_path, false)
+      .unwrap()
+      .size_allocated_bytes;
+    if 64 * 1024 * 1024 - allocated < SPARSE_MIN_HOLE_BYTES {
+      // The temp filesystem doesn't support sparse files
+      assert!(sparse_files.is_empty());
+      return Ok(());
+    }
+
+    assert_eq!(sparse_files.len(), 1, "The dense file has no hole");
+    assert_eq!(sparse_files[0].path, sparse_path);
+    assert_eq!(sparse_files[0].hole_bytes, 64 * 1024 * 1024 - allocated);
+
+    Ok(())
+  }
+
+  #[tokio::test]
+  async fn test_merge_scans_under_common_root() -> std::io::Result<()> {
+    let temp_dir = tempdir()?;
+    let parent = temp_dir.path().canonicalize()?;
+    let (a, b) = (parent.join("a"), parent.join("b"));
+    fs::create_dir_all(a.join("sub"))?;
+    fs::create_dir(&b)?;
+    fs::write(a.join("sub").join("one.txt"), b"one")?;
+    fs::write(b.join("two.txt"), b"twotwo")?;
+
+    let mut cache = scan_into_cache(&a)?;
+    let a_size = cache.entries[cache.path_map[&a]].size_bytes;
+    let b_cache = scan_into_cache(&b)?;
+    let b_size = b_cache.entries[b_cache.path_map[&b]].size_bytes;
+    merge_into_cache(&mut cache, &b, b_cache.entries);
+
+    // Both roots now hang off a synthetic parent spanning them
+    assert_eq!(cache.root_path, parent);
+    assert_eq!(cache.roots, vec![a.clone(), b.clone()]);
+    let root = &cache.entries[cache.path_map[&parent]];
+    assert_eq!(root.size_bytes, a_size + b_size);
+    let tree = build_tree_from_indices(
+      &cache.entries,
+      &cache.path_map,
+      &cache.children_map,
+      &parent,
+      1,
+      true,
+    )
+    .unwrap();
+    let mut names: Vec<_> = tree.children.iter().map(|c| c.name.as_str()).collect();
+    names.sort();
+    assert_eq!(names, vec!["a", "b"]);
+
+    // Subpaths of either root resolve, other paths under the parent don't
+    assert!(cache.contains_path(&a.join("sub")));
+    assert!(cache.contains_path(&b.join("two.txt")));
+    assert!(!cache.contains_path(&parent.join("c")));
+
+    // Rescanning inside a merged root replaces that subtree and updates the totals
+    fs::write(a.join("sub").join("three.txt"), b"three")?;
+    let sub_cache = scan_into_cache(&a.join("sub"))?;
+    merge_into_cache(&mut cache, &a.join("sub"), sub_cache.entries);
+    assert_eq!(cache.roots.len(), 2);
+    assert!(cache
+      .path_map
+      .contains_key(&a.join("sub").join("three.txt")));
+    let root = &cache.entries[cache.path_map[&parent]];
+    assert_eq!(root.size_bytes, a_size + b_size + 5);
+    assert_eq!(root.file_count, 3);
+
+    // Rescanning a merged root itself keeps it listed under the synthetic parent
+    let b_cache = scan_into_cache(&b)?;
+    merge_into_cache(&mut cache, &b, b_cache.entries);
+    assert_eq!(cache.roots.len(), 2);
+    assert_eq!(cache.children_map[&parent].len(), 2);
+    assert_eq!(cache.entries[cache.path_map[&parent]].file_count, 3);
+
+    Ok(())
+  }
+
+  #[tokio::test]
+  async fn test_dominant_type_by_bytes() -> std::io::Result<()> {
+    let temp_dir = tempdir()?;
+    let path = temp_dir.path().canonicalize()?;
+    fs::create_dir(path.join("videos"))?;
+    fs::write(path.join("videos").join("a.MP4"), vec![0u8; 6000])?;
+    fs::write(path.join("videos").join("b.mp4"), vec![0u8; 3000])?;
+    fs::write(path.join("notes.txt"), vec![0u8; 500])?;
+    fs::write(path.join("Makefile"), vec![0u8; 100])?;
+    fs::write(path.join("x.txt"), vec![0u8; 500])?;
+
+    let cache = scan_into_cache(&path)?;
+    let dominant = find_dominant_type(&cache, &path).unwrap();
+
+    // Extensions are grouped case-insensitively across the whole subtree
+    assert_eq!(dominant.extension, "mp4");
+    assert_eq!(dominant.size_bytes, 9000);
+    let root_size = cache.entries[cache.path_map[&path]].size_bytes;
+    assert!((dominant.percent - percent_of(9000, root_size)).abs() < 1e-9);
+
+    assert_eq!(extension_key(Path::new("Makefile")), "none");
+
+    Ok(())
+  }
+
+  #[tokio::test]
+  #[cfg(target_family = "unix")]
+  async fn test_special_files_are_zero_sized_entries() -> std::io::Result<()> {
+    use std::os::unix::net::UnixListener;
+
+    let temp_dir = tempdir()?;
+    let path = temp_dir.path().to_path_buf();
+    fs::write(path.join("file.txt"), b"data")?;
+    let socket_path = path.join("app.sock");
+    let _listener = UnixListener::bind(&socket_path)?;
+
+    let info = platform::get_path_info(&socket_path, false).unwrap();
+    assert_eq!(info.kind, platform::FileKind::Socket);
+
+    let state = ScanState::default();
+    calculate_size_sync(path.as_path(), &state, &ScanOptions::default(), None)?;
+
+    let socket = state.analytics_map.get(&socket_path).unwrap();
+    assert_eq!(socket.size_bytes, 0);
+    assert_eq!(socket.file_count, 0, "Special files aren't regular files");
+    let root = state.analytics_map.get(&path).unwrap();
+    assert_eq!(root.file_count, 1);
+    assert_eq!(root.entry_count, 3, "Root, file.txt and the socket");
+    assert_eq!(state.special_file_count.load(Ordering::Relaxed), 1);
+
+    Ok(())
+  }
+
+  #[tokio::test]
+  async fn test_build_index_now_is_idempotent() -> std::io::Result<()> {
+    let _guard = GLOBAL_CACHE_TEST_LOCK.lock().await;
+    let temp_dir = tempdir()?;
+    let path = temp_dir.path().canonicalize()?;
+    fs::create_dir(path.join("dir"))?;
+    fs::write(path.join("dir").join("file.txt"), b"data")?;
+
+    // Store a scan the way scan_directory_complete does, before its indices exist
+    let mut cache = scan_into_cache(&path)?;
+    cache.path_map.clear();
+    cache.children_map.clear();
+    cache.indexed = false;
+    let generation = start_cache_generation(true).unwrap();
+    assert!(store_scan_cache(cache, generation));
+
+    build_index_now().await.unwrap();
+    let cache = cache_snapshot().unwrap().unwrap();
+    assert!(cache.indexed);
+    assert_eq!(cache.children_map[&path].len(), 1);
+
+    // A second call finds the indices already built
+    build_index_now().await.unwrap();
+    assert!(index_cached_scan().unwrap());
+
+    reset_scan_cache().unwrap();
+    assert!(
+      !index_cached_scan().unwrap(),
+      "Nothing to index once cleared"
+    );
+
+    Ok(())
+  }
+
+  #[tokio::test]
+  async fn test_file_kind_hints() -> std::io::Result<()> {
+    let temp_dir = tempdir()?;
+    let path = temp_dir.path().canonicalize()?;
+    fs::create_dir(path.join("photos"))?;
+    fs::write(path.join("photos").join("cat.JPG"), b"jpg")?;
+    fs::write(path.join("clip.mkv"), b"mkv")?;
+    fs::write(path.join("notes.unknownext"), b"??")?;
+    // Link to something outside the scan, links to scanned targets are skipped as cycles
+    let outside_dir = tempdir()?;
+    fs::write(outside_dir.path().join("target.txt"), b"target")?;
+    #[cfg(target_family = "unix")]
+    std::os::unix::fs::symlink(outside_dir.path().join("target.txt"), path.join("link"))?;
+
+    let cache = scan_into_cache(&path)?;
+    let tree = build_tree_from_indices(
+      &cache.entries,
+      &cache.path_map,
+      &cache.children_map,
+      &path,
+      2,
+      false,
+    )
+    .unwrap();
+    let hint = |name: &str| {
+      tree
+        .children
+        .iter()
+        .find(|c| c.name == name)
+        .map(|c| c.file_kind_hint.clone())
+        .unwrap()
+    };
+
+    assert_eq!(tree.file_kind_hint, "folder");
+    assert_eq!(hint("photos"), "folder");
+    assert_eq!(hint("clip.mkv"), "video");
+    assert_eq!(hint("notes.unknownext"), "other");
+    #[cfg(target_family = "unix")]
+    assert_eq!(hint("link"), "symlink");
+    assert_eq!(
+      file_kind_hint(Path::new("cat.JPG"), false, false),
+      "image",
+      "Extensions are matched case-insensitively"
+    );
+
+    Ok(())
+  }
+
+  #[tokio::test]
+  async fn test_new_generation_cancels_index_build() {
+    let _guard = GLOBAL_CACHE_TEST_LOCK.lock().await;
+    let entries = vec![
+      test_entry("/data", 10, None),
+      test_entry("/data/a", 10, None),
+    ];
+
+    let generation = start_cache_generation(true).unwrap();
+    let cancel = IndexCancelToken::for_generation(generation);
+    assert!(build_indices(&entries, Path::new("/data"), &cancel).is_some());
+
+    // Starting another scan makes the old build stop instead of finishing
+    start_cache_generation(true).unwrap();
+    assert!(cancel.is_cancelled());
+    assert!(build_indices(&entries, Path::new("/data"), &cancel).is_none());
+    assert!(build_indices(&entries, Path::new("/data"), &IndexCancelToken::never()).is_some());
+  }
+
+  #[test]
+  fn test_flame_data_offsets() {
+    let cache = test_cache(
+      "/data",
+      vec![
+        test_entry("/data", 1000, None),
+        test_entry("/data/big", 600, None),
+        test_entry("/data/big/x", 500, None),
+        test_entry("/data/small", 300, None),
+      ],
+    );
+
+    let nodes = build_flame_data(&cache, Path::new("/data"), 5);
+    let layout: Vec<(&str, usize, u64, u64, u64)> = nodes
+      .iter()
+      .map(|n| {
+        (
+          n.path.to_str().unwrap(),
+          n.depth,
+          n.self_bytes,
+          n.total_bytes,
+          n.start_offset,
+        )
+      })
+      .collect();
+    assert_eq!(
+      layout,
+      vec![
+        ("/data", 0, 100, 1000, 0),
+        ("/data/big", 1, 100, 600, 0),
+        ("/data/big/x", 2, 500, 500, 0),
+        ("/data/small", 1, 300, 300, 600),
+      ]
+    );
+
+    // Nodes below max_depth are left out
+    assert_eq!(build_flame_data(&cache, Path::new("/data"), 1).len(), 3);
+  }
+
+  #[test]
+  fn test_linear_layout_covers_total() {
+    let cache = test_cache(
+      "/data",
+      vec![
+        test_entry("/data", 1000, None),
+        test_entry("/data/big", 600, None),
+        test_entry("/data/big/x", 500, None),
+        test_entry("/data/big/empty", 0, None),
+        test_entry("/data/small", 300, None),
+      ],
+    );
+
+    let spans = |max_depth| {
+      build_linear_layout(&cache, Path::new("/data"), max_depth)
+        .into_iter()
+        .map(|s| {
+          (
+            s.path.to_str().unwrap().to_string(),
+            s.start_byte,
+            s.end_byte,
+          )
+        })
+        .collect::<Vec<_>>()
+    };
+    assert_eq!(
+      spans(5),
+      vec![
+        ("/data".to_string(), 0, 100),
+        ("/data/big".to_string(), 100, 200),
+        ("/data/big/x".to_string(), 200, 700),
+        ("/data/small".to_string(), 700, 1000),
+      ]
+    );
+
+    // Directories at the depth limit become a single span
+    assert_eq!(
+      spans(1),
+      vec![
+        ("/data".to_string(), 0, 100),
+        ("/data/big".to_string(), 100, 700),
+        ("/data/small".to_string(), 700, 1000),
+      ]
+    );
+  }
+
+  #[test]
+  fn test_permission_denied_ratio() -> std::io::Result<()> {
+    let state = ScanState::default();
+    assert_eq!(state.permission_denied_ratio(), 0.0);
+
+    let temp_dir = tempdir()?;
+    let path = temp_dir.path().to_path_buf();
+    for name in ["a", "b", "c"] {
+      fs::create_dir(path.join(name))?;
+    }
+    fs::write(path.join("file.txt"), b"data")?;
+    calculate_size_sync(path.as_path(), &state, &ScanOptions::default(), None)?;
+
+    // One of the four directories denied, plus an error that isn't a permission problem
+    state
+      .errors
+      .insert(path.join("a"), PERMISSION_DENIED_REASON.to_string());
+    state
+      .errors
+      .insert(path.join("file.txt"), "timeout".to_string());
+    assert_eq!(state.permission_denied_ratio(), 0.25);
+
+    Ok(())
+  }
+
+  #[test]
+  fn test_huge_sizes_are_clamped_and_saturate() -> std::io::Result<()> {
+    let temp_dir = tempdir()?;
+    let path = temp_dir.path().to_path_buf();
+    let capacity = 1 << 40;
+
+    assert_eq!(clamp_to_capacity(&path, u64::MAX, Some(capacity)), capacity);
+    assert_eq!(clamp_to_capacity(&path, 100, Some(capacity)), 100);
+    assert_eq!(clamp_to_capacity(&path, u64::MAX, None), u64::MAX);
+
+    // Aggregating children whose sizes add past u64::MAX saturates instead of panicking
+    let state = ScanState::default();
+    let options = ScanOptions::default();
+    let context = ScanContext {
+      root: &path,
+      state: &state,
+      options: &options,
+      observer: None,
+      volumes: DashMap::new(),
+    };
+    let children = vec![path.join("a"), path.join("b")];
+    for child in &children {
+      let mut entry = (*test_entry(child.to_str().unwrap(), u64::MAX - 1, None)).clone();
+      entry.size_allocated_bytes = u64::MAX - 1;
+      state.analytics_map.insert(child.clone(), Arc::new(entry));
+    }
+    state
+      .analytics_map
+      .insert(path.clone(), test_entry(path.to_str().unwrap(), 0, None));
+    let directory = PendingDirectory {
+      path: path.clone(),
+      path_info: platform::get_path_info(&path, false).unwrap(),
+      depth: 0,
+      children,
+      remaining: AtomicUsize::new(0),
+      skipped: AtomicU32::new(0),
+      parent: None,
+    };
+    aggregate_directory(&context, &directory);
+
+    let root = state.analytics_map.get(&path).unwrap();
+    assert_eq!(root.size_bytes, u64::MAX);
+    assert_eq!(root.size_allocated_bytes, u64::MAX);
+
+    Ok(())
+  }
+
+  #[test]
+  fn test_sort_children_by_slack() {
+    let cache = test_cache(
+      "/data",
+      vec![
+        test_entry("/data", 0, None),
+        test_entry("/data/big", 0, None),
+        test_entry("/data/tiny_files", 0, None),
+        test_entry("/data/compressed", 0, None),
+      ],
+    );
+    let mut tree = build_tree_from_indices(
+      &cache.entries,
+      &cache.path_map,
+      &cache.children_map,
+      Path::new("/data"),
+      1,
+      false,
+    )
+    .unwrap();
+    // (apparent, allocated): big has little slack, tiny_files a lot, compressed none
+    for child in &mut tree.children {
+      (child.size_bytes, child.size_allocated_bytes) = match child.name.as_str() {
+        "big" => (10_000, 10_100),
+        "tiny_files" => (100, 4_000),
+        _ => (5_000, 2_000),
+      };
+    }
+
+    sort_tree_children(&mut tree, SortKey::Slack);
+    let names: Vec<&str> = tree.children.iter().map(|c| c.name.as_str()).collect();
+    assert_eq!(names, vec!["tiny_files", "big", "compressed"]);
+
+    sort_tree_children(&mut tree, SortKey::Size);
+    let names: Vec<&str> = tree.children.iter().map(|c| c.name.as_str()).collect();
+    assert_eq!(names, vec!["big", "compressed", "tiny_files"]);
+  }
+
+  #[test]
+  fn test_directory_entropy() -> std::io::Result<()> {
+    assert_eq!(shannon_entropy([5]), 0.0);
+    assert_eq!(shannon_entropy([1, 1]), 1.0);
+    assert_eq!(shannon_entropy([1, 1, 1, 1]), 2.0);
+
+    let temp_dir = tempdir()?;
+    let path = temp_dir.path().canonicalize()?;
+    fs::create_dir_all(path.join("photos/nested"))?;
+    fs::write(path.join("photos/a.jpg"), b"1")?;
+    fs::write(path.join("photos/b.JPG"), b"1")?;
+    fs::create_dir(path.join("downloads"))?;
+    for name in ["a.pdf", "b.zip", "c.exe", "d.mp4"] {
+      fs::write(path.join("downloads").join(name), b"1")?;
+    }
+    fs::create_dir(path.join("empty"))?;
+    fs::write(path.join("top.txt"), b"1")?;
+
+    let cache = scan_into_cache(&path)?;
+    let results = find_directory_entropy(&cache, &path);
+    let summary: Vec<(String, f64, u64, usize)> = results
+      .iter()
+      .map(|r| {
+        (
+          r.path.file_name().unwrap().to_string_lossy().into_owned(),
+          r.entropy,
+          r.file_count,
+          r.extension_count,
+        )
+      })
+      .collect();
+    // Files only: the nested directory doesn't count, and extensions ignore case
+    assert_eq!(
+      summary,
+      vec![
+        ("downloads".to_string(), 2.0, 4, 4),
+        ("empty".to_string(), 0.0, 0, 0),
+        ("photos".to_string(), 0.0, 2, 1),
+      ]
+    );
+
+    Ok(())
+  }
+
+  #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+  async fn test_concurrent_directory_children_share_the_cache() -> std::io::Result<()> {
+    let _guard = GLOBAL_CACHE_TEST_LOCK.lock().await;
+    let temp_dir = tempdir()?;
+    let path = temp_dir.path().canonicalize()?;
+    for i in 0..20 {
+      let dir = path.join(format!("dir{}", i));
+      fs::create_dir(&dir)?;
+      for j in 0..20 {
+        fs::write(dir.join(format!("file{}.txt", j)), b"data")?;
+      }
+    }
+
+    // Stored unindexed, so the first readers also race to build the indices
+    let mut cache = scan_into_cache(&path)?;
+    cache.path_map.clear();
+    cache.children_map.clear();
+    cache.indexed = false;
+    let generation = start_cache_generation(true).unwrap();
+    assert!(store_scan_cache(cache, generation));
+
+    // A reader holding a snapshot doesn't block the others
+    let held_snapshot = cache_snapshot().unwrap().unwrap();
+
+    let requests: Vec<_> = (0..64)
+      .map(|i| {
+        let path = path.join(format!("dir{}", i % 20));
+        tokio::spawn(async move {
+          get_directory_children(
+            path.to_string_lossy().into_owned(),
+            None,
+            None,
+            None,
+            None,
+            None,
+          )
+          .await
+        })
+      })
+      .collect();
+    let all_requests = async {
+      let mut trees = Vec::new();
+      for request in requests {
+        trees.push(request.await.unwrap().unwrap());
+      }
+      trees
+    };
+    let trees = tokio::time::timeout(Duration::from_secs(30), all_requests)
+      .await
+      .expect("Concurrent reads should not stall");
+
+    assert!(trees.iter().all(|tree| tree.file_count == 20));
+    assert!(
+      !held_snapshot.indexed,
+      "Snapshots are never modified in place"
+    );
+    drop(held_snapshot);
+
+    reset_scan_cache().unwrap();
+    Ok(())
+  }
+
+  #[test]
+  fn test_sqlite_export() -> std::io::Result<()> {
+    let temp_dir = tempdir()?;
+    let path = temp_dir.path().canonicalize()?;
+    fs::create_dir(path.join("videos"))?;
+    fs::write(path.join("videos").join("movie.mp4"), vec![0u8; 5000])?;
+    fs::write(path.join("notes.txt"), b"hello")?;
+
+    let cache = scan_into_cache(&path)?;
+    let mut conn = rusqlite::Connection::open_in_memory().unwrap();
+    write_sqlite_export(&cache, &mut conn).unwrap();
+
+    let count: i64 = conn
+      .query_row("SELECT COUNT(*) FROM entries", [], |row| row.get(0))
+      .unwrap();
+    assert_eq!(count, 4);
+
+    let videos = path.join("videos").to_string_lossy().into_owned();
+    let (name, size, is_dir): (String, i64, bool) = conn
+      .query_row(
+        "SELECT path, size_bytes, is_dir FROM entries
+        WHERE parent_path = ?1 AND path LIKE '%.mp4' ORDER BY size_bytes DESC LIMIT 1",
+        [&videos],
+        |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+      )
+      .unwrap();
+    assert!(name.ends_with("movie.mp4"));
+    assert_eq!(size, 5000);
+    assert!(!is_dir);
+
+    let root_parent: Option<String> = conn
+      .query_row(
+        "SELECT parent_path FROM entries WHERE path = ?1",
+        [path.to_string_lossy()],
+        |row| row.get(0),
+      )
+      .unwrap();
+    assert_eq!(root_parent, None);
+
+    Ok(())
+  }
+
+  #[test]
+  fn test_reuse_cache_grafts_unchanged_subtrees() -> std::io::Result<()> {
+    let temp_dir = tempdir()?;
+    let path = temp_dir.path().canonicalize()?;
+    for dir in ["changed", "unchanged", "deep/inner"] {
+      fs::create_dir_all(path.join(dir))?;
+      fs::write(path.join(dir).join("file.txt"), b"data")?;
+    }
+
+    // Doctor the previous scan so grafted sizes are recognizable, and so "changed" and the
+    // directory below "deep" look modified since
+    let mut previous = scan_into_cache(&path)?;
+    let mut doctor = |relative: &str, size_bytes: Option<u64>, modified: Option<u64>| {
+      let index = previous.path_map[&path.join(relative)];
+      let entry = Arc::make_mut(&mut previous.entries[index]);
+      if let Some(size_bytes) = size_bytes {
+        entry.size_bytes = size_bytes;
+      }
+      if let Some(modified) = modified {
+        entry.last_modified_time = modified;
+      }
+    };
+    doctor("unchanged", Some(999_999), None);
+    doctor("changed", Some(999_999), Some(0));
+    doctor("deep", Some(999_999), None);
+    doctor("deep/inner", None, Some(0));
+
+    let state = ScanState {
+      previous_scan: Some(Arc::new(previous)),
+      ..Default::default()
+    };
+    calculate_size_sync(path.as_path(), &state, &ScanOptions::default(), None)?;
+
+    let size_of = |relative: &str| {
+      state
+        .analytics_map
+        .get(&path.join(relative))
+        .unwrap()
+        .size_bytes
+    };
+    assert_eq!(
+      size_of("unchanged"),
+      999_999,
+      "Unchanged subtree is grafted"
+    );
+    assert!(
+      size_of("changed") < 999_999,
+      "Changed directory is read again"
+    );
+    assert!(
+      size_of("deep") < 999_999,
+      "A change deeper down also counts"
+    );
+    assert!(
+      state
+        .analytics_map
+        .contains_key(&path.join("unchanged").join("file.txt")),
+      "Grafted subtrees keep their children"
+    );
+
+    Ok(())
+  }
+
+  #[test]
+  fn test_allocation_efficiency() {
+    let mut cache = test_cache(
+      "/data",
+      vec![
+        test_entry("/data", 0, None),
+        test_entry("/data/tiny_files", 0, None),
+        test_entry("/data/tiny_files/nested", 0, None),
+        test_entry("/data/compressed", 0, None),
+        test_entry("/data/file.bin", 0, None),
+      ],
+    );
+    // test_entry has no path info, give every entry but the file a directory's
+    let dir_info = platform::get_path_info(std::env::temp_dir(), false).unwrap();
+    let sizes = [
+      ("/data", 6_000, 10_000),
+      ("/data/tiny_files", 1_000, 8_000),
+      ("/data/tiny_files/nested", 0, 0),
+      ("/data/compressed", 5_000, 2_000),
+    ];
+    for (path, size_bytes, size_allocated_bytes) in sizes {
+      let index = cache.path_map[Path::new(path)];
+      let entry = Arc::make_mut(&mut cache.entries[index]);
+      entry.size_bytes = size_bytes;
+      entry.size_allocated_bytes = size_allocated_bytes;
+      entry.path_info = Some(dir_info.clone());
+    }
+    rebuild_cache_indices(&mut cache);
+
+    let results = find_allocation_efficiency(&cache, Path::new("/data"), 1);
+    let summary: Vec<(&str, usize, Option<f64>)> = results
+      .iter()
+      .map(|r| (r.path.to_str().unwrap(), r.depth, r.efficiency))
+      .collect();
+    assert_eq!(
+      summary,
+      vec![
+        ("/data", 0, Some(0.6)),
+        ("/data/compressed", 1, Some(2.5)),
+        ("/data/tiny_files", 1, Some(0.13)),
+      ]
+    );
+
+    let results = find_allocation_efficiency(&cache, Path::new("/data"), 2);
+    assert_eq!(results.len(), 4);
+    assert_eq!(results[3].efficiency, None, "Nothing allocated");
+  }
+
+  #[test]
+  fn test_scanning_a_single_file() -> std::io::Result<()> {
+    let temp_dir = tempdir()?;
+    let file_path = temp_dir.path().canonicalize()?.join("movie.mp4");
+    fs::write(&file_path, vec![0u8; 3000])?;
+
+    let cache = scan_into_cache(&file_path)?;
+    assert_eq!(cache.entries.len(), 1);
+
+    let tree = build_tree_from_entries_with_depth(&cache.entries, &file_path, 1, true);
+    assert_eq!(tree.path, file_path);
+    assert_eq!(tree.size_bytes, 3000);
+    assert_eq!(tree.file_count, 1);
+    assert_eq!(tree.percent_of_parent, 100.0);
+    assert!(tree.children.is_empty(), "No virtual directory for a file");
+    assert_eq!(tree.file_kind_hint, "video");
+
+    let tree = build_tree_from_indices(
+      &cache.entries,
+      &cache.path_map,
+      &cache.children_map,
+      &file_path,
+      1,
+      true,
+    )
+    .unwrap();
+    assert!(tree.children.is_empty());
+
+    // Space info resolves the volume through the file's directory
+    assert!(platform::get_space_info(&file_path).is_some());
+
+    Ok(())
+  }
+
+  #[test]
+  #[cfg(target_family = "unix")]
+  fn test_follow_link_options() -> std::io::Result<()> {
+    let outside = tempdir()?;
+    let outside_path = outside.path().canonicalize()?;
+    fs::create_dir(outside_path.join("dir"))?;
+    fs::write(outside_path.join("dir").join("inner.txt"), b"data")?;
+    fs::write(outside_path.join("big.bin"), vec![0u8; 5000])?;
+
+    let temp_dir = tempdir()?;
+    let path = temp_dir.path().canonicalize()?;
+    std::os::unix::fs::symlink(outside_path.join("dir"), path.join("dir_link"))?;
+    std::os::unix::fs::symlink(outside_path.join("big.bin"), path.join("file_link"))?;
+
+    let scan = |options: ScanOptions| {
+      let state = ScanState::default();
+      calculate_size_sync(path.as_path(), &state, &options, None).unwrap();
+      state
+    };
+
+    // By default links count as themselves
+    let state = scan(ScanOptions::default());
+    assert!(!state
+      .analytics_map
+      .contains_key(&path.join("dir_link").join("inner.txt")));
+    assert!(
+      state
+        .analytics_map
+        .get(&path.join("file_link"))
+        .unwrap()
+        .size_bytes
+        < 5000
+    );
+
+    let state = scan(ScanOptions {
+      follow_dir_symlinks: true,
+      follow_file_symlinks: true,
+      ..Default::default()
+    });
+    assert!(state
+      .analytics_map
+      .contains_key(&path.join("dir_link").join("inner.txt")));
+    assert_eq!(
+      state
+        .analytics_map
+        .get(&path.join("file_link"))
+        .unwrap()
+        .size_bytes,
+      5000
+    );
+
+    // Junctions are a separate switch, and plain symlinks are never junctions
+    assert_eq!(
+      platform::get_link_kind(path.join("dir_link"), true),
+      platform::LinkKind::DirSymlink
+    );
+
+    Ok(())
+  }
+
+  #[test]
+  fn test_compression_estimate() -> std::io::Result<()> {
+    let temp_dir = tempdir()?;
+    let path = temp_dir.path().canonicalize()?;
+    let size = COMPRESSION_MIN_FILE_BYTES as usize * 2;
+    fs::write(path.join("log.txt"), "repetitive line\n".repeat(size / 16))?;
+    // An xorshift stream doesn't compress
+    let mut state = 0x2545f4914f6cdd1du64;
+    let noise: Vec<u8> = (0..size)
+      .map(|_| {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        state as u8
+      })
+      .collect();
+    fs::write(path.join("noise.bin"), noise)?;
+    fs::write(path.join("small.txt"), "tiny")?;
+
+    let cache = scan_into_cache(&path)?;
+    let estimate = estimate_compression(&cache, &path, 16 * 1024);
+
+    assert_eq!(estimate.sampled_files, 2, "Small files are skipped");
+    assert_eq!(estimate.sampled_bytes, 2 * 16 * 1024);
+    assert_eq!(estimate.candidates.len(), 1);
+    let log = &estimate.candidates[0];
+    assert_eq!(log.path, path.join("log.txt"));
+    assert!(log.sample_ratio < 0.1);
+    assert!(log.estimated_savings_bytes > size as u64 * 9 / 10);
+    assert_eq!(
+      estimate.total_estimated_savings_bytes,
+      log.estimated_savings_bytes
+    );
+
+    Ok(())
+  }
+
+  #[test]
+  fn test_duplicate_paths_are_coalesced() {
+    let cache = test_cache(
+      "/data",
+      vec![
+        test_entry("/data", 1000, None),
+        test_entry("/data/dir", 10, None),
+        test_entry("/data/file", 300, None),
+        test_entry("/data/dir", 700, None),
+        test_entry("/data/dir", 700, None),
+      ],
+    );
+
+    // The larger entry wins, and the earliest of equal ones
+    assert_eq!(cache.path_map[Path::new("/data/dir")], 3);
+    let children = &cache.children_map[Path::new("/data")];
+    assert_eq!(children, &vec![3, 2], "Each path is listed once");
+    assert_eq!(
+      cache.sorted_by_size,
+      vec![0, 3, 2],
+      "Largest first, duplicates left out"
+    );
+
+    let tree = build_tree_from_indices(
+      &cache.entries,
+      &cache.path_map,
+      &cache.children_map,
+      Path::new("/data"),
+      1,
+      false,
+    )
+    .unwrap();
+    let sizes: Vec<u64> = tree.children.iter().map(|c| c.size_bytes).collect();
+    assert_eq!(sizes, vec![700, 300]);
+  }
+
+  #[test]
+  #[cfg(target_family = "unix")]
+  fn test_hard_links_count_once_in_unique_bytes() -> std::io::Result<()> {
+    let temp_dir = tempdir()?;
+    let path = temp_dir.path().canonicalize()?;
+    fs::create_dir(path.join("a"))?;
+    fs::create_dir(path.join("b"))?;
+    fs::write(path.join("a").join("data.bin"), vec![0u8; 10_000])?;
+    fs::hard_link(
+      path.join("a").join("data.bin"),
+      path.join("b").join("link.bin"),
+    )?;
+    fs::write(path.join("b").join("other.bin"), vec![0u8; 500])?;
+
+    let state = ScanState::default();
+    calculate_size_sync(path.as_path(), &state, &ScanOptions::default(), None)?;
+
+    let entry = |relative: &str| {
+      state
+        .analytics_map
+        .get(&path.join(relative))
+        .unwrap()
+        .clone()
+    };
+    let (a, b, root) = (entry("a"), entry("b"), entry(""));
+
+    // Both paths are listed, but only one of them owns the bytes
+    let own_size = |e: &AnalyticsInfo| e.path_info.as_ref().unwrap().size_bytes;
+    let naive_files = root.size_bytes - own_size(&root) - own_size(&a) - own_size(&b);
+    assert_eq!(naive_files, 20_500);
+    assert_eq!(root.unique_bytes, root.size_bytes - 10_000);
+    assert_eq!(root.file_count, 3);
+    assert_eq!(
+      (a.size_bytes - a.unique_bytes) + (b.size_bytes - b.unique_bytes),
+      10_000,
+      "Exactly one of the two links is the repeat"
+    );
+
+    Ok(())
+  }
+
+  #[test]
+  fn test_group_directories_first() -> std::io::Result<()> {
+    let temp_dir = tempdir()?;
+    let path = temp_dir.path().canonicalize()?;
+    fs::write(path.join("huge.bin"), vec![0u8; 50_000])?;
+    fs::create_dir(path.join("small_dir"))?;
+    fs::write(path.join("small_dir").join("a.txt"), b"a")?;
+    fs::create_dir(path.join("big_dir"))?;
+    fs::write(path.join("big_dir").join("b.bin"), vec![0u8; 20_000])?;
+
+    let cache = scan_into_cache(&path)?;
+    let mut tree = build_tree_from_indices(
+      &cache.entries,
+      &cache.path_map,
+      &cache.children_map,
+      &path,
+      1,
+      false,
+    )
+    .unwrap();
+    let percents_before: HashMap<String, f64> = tree
+      .children
+      .iter()
+      .map(|c| (c.name.clone(), c.percent_of_parent))
+      .collect();
+
+    group_directories_before_files(&mut tree);
+    let names: Vec<&str> = tree.children.iter().map(|c| c.name.as_str()).collect();
+    assert_eq!(names, vec!["big_dir", "small_dir", "huge.bin"]);
+    for child in &tree.children {
+      assert_eq!(child.percent_of_parent, percents_before[&child.name]);
+    }
+
+    Ok(())
+  }
+
+  #[test]
+  fn test_heavy_directories() -> std::io::Result<()> {
+    let temp_dir = tempdir()?;
+    let path = temp_dir.path().canonicalize()?;
+    fs::create_dir(path.join("videos"))?;
+    fs::write(path.join("videos").join("movie.mkv"), vec![0u8; 100_000])?;
+    fs::create_dir(path.join("source"))?;
+    for i in 0..50 {
+      fs::write(
+        path.join("source").join(format!("{}.rs", i)),
+        b"fn main() {}",
+      )?;
+    }
+
+    let cache = scan_into_cache(&path)?;
+    let heavy = find_heavy_directories(&cache, 2);
+    assert_eq!(heavy.len(), 2);
+    assert_eq!(heavy[0].path, path.join("videos"));
+    assert_eq!(heavy[0].file_count, 1);
+    assert_eq!(
+      heavy[0].bytes_per_entry,
+      heavy[0].size_bytes as f64 / 2.0,
+      "The directory and its file"
+    );
+    assert!(heavy[1].bytes_per_entry < heavy[0].bytes_per_entry);
+    assert!(find_heavy_directories(&cache, 10)
+      .last()
+      .is_some_and(|d| d.path == path.join("source")));
+
+    Ok(())
+  }
+
+  #[test]
+  fn test_detect_content_type() -> std::io::Result<()> {
+    let temp_dir = tempdir()?;
+    let path = temp_dir.path().canonicalize()?;
+    // A PNG saved without its extension, and a small one below the detection threshold
+    let mut png = b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR".to_vec();
+    png.resize(10_000, 0);
+    fs::write(path.join("picture"), &png)?;
+    fs::write(path.join("icon.dat"), &png[..100])?;
+
+    let options = ScanOptions {
+      detect_content_type: true,
+      ..Default::default()
+    };
+    let cache = scan_into_cache_with(&path, &options)?;
+    let content_type = |name: &str| {
+      cache.entries[cache.path_map[&path.join(name)]]
+        .content_type
+        .clone()
+    };
+    assert_eq!(content_type("picture").as_deref(), Some("image/png"));
+    assert_eq!(content_type("icon.dat"), None);
+
+    // The detected type wins over the missing extension
+    assert_eq!(
+      find_dominant_type(&cache, &path).unwrap().extension,
+      "image/png"
+    );
+
+    // Off by default
+    let cache = scan_into_cache(&path)?;
+    assert!(cache
+      .entries
+      .iter()
+      .all(|entry| entry.content_type.is_none()));
+
+    Ok(())
+  }
+
+  #[test]
+  fn test_diff_subtrees() -> std::io::Result<()> {
+    let temp_dir = tempdir()?;
+    let path = temp_dir.path().canonicalize()?;
+    for backup in ["backup1", "backup2"] {
+      fs::create_dir_all(path.join(backup).join("photos"))?;
+      fs::write(
+        path.join(backup).join("photos").join("same.jpg"),
+        vec![0u8; 300],
+      )?;
+    }
+    fs::write(path.join("backup1").join("changed.txt"), vec![0u8; 10])?;
+    fs::write(path.join("backup2").join("changed.txt"), vec![0u8; 20])?;
+    fs::write(
+      path.join("backup1").join("photos").join("lost.jpg"),
+      vec![0u8; 5],
+    )?;
+    fs::write(path.join("backup2").join("new.txt"), vec![0u8; 5])?;
+
+    let cache = scan_into_cache(&path)?;
+    let diff = diff_cached_subtrees(&cache, &path.join("backup1"), &path.join("backup2"));
+    assert_eq!(
+      diff.only_in_a,
+      vec![PathBuf::from("photos").join("lost.jpg")]
+    );
+    assert_eq!(diff.only_in_b, vec![PathBuf::from("new.txt")]);
+    assert_eq!(diff.size_differs.len(), 1);
+    assert_eq!(diff.size_differs[0].path, PathBuf::from("changed.txt"));
+    assert_eq!(
+      (diff.size_differs[0].size_a, diff.size_differs[0].size_b),
+      (10, 20)
+    );
+
+    let same = diff_cached_subtrees(&cache, &path.join("backup1"), &path.join("backup1"));
+    assert!(same.only_in_a.is_empty() && same.only_in_b.is_empty());
+    assert!(same.size_differs.is_empty());
+
+    Ok(())
+  }
+
+  #[test]
+  fn test_scan_fingerprint() {
+    let cache = test_cache(
+      "/root",
+      vec![
+        test_entry("/root", 300, None),
+        test_entry("/root/a", 300, None),
+      ],
+    );
+    let fingerprint = scan_fingerprint(&cache, 1000);
+    assert_eq!(fingerprint, scan_fingerprint(&cache, 1000));
+    assert_ne!(fingerprint, scan_fingerprint(&cache, 1001));
+
+    let grown = test_cache(
+      "/root",
+      vec![
+        test_entry("/root", 400, None),
+        test_entry("/root/a", 400, None),
+      ],
+    );
+    assert_ne!(fingerprint, scan_fingerprint(&grown, 1000));
+
+    // The root's size is kept without the indices, which a fingerprint never waits for
+    let unindexed = ScanCache {
+      path_map: HashMap::new(),
+      children_map: HashMap::new(),
+      sorted_by_size: Vec::new(),
+      indexed: false,
+      ..grown.clone()
+    };
+    assert_eq!(grown.total_bytes, 400);
+    assert_eq!(
+      scan_fingerprint(&unindexed, 1000),
+      scan_fingerprint(&grown, 1000)
+    );
+
+    // Moving entries out updates it along with the root's totals
+    let mut moved = grown;
+    assert!(remove_path_from_cache(&mut moved, Path::new("/root/a")));
+    assert_eq!(moved.total_bytes, 0);
+  }
+
+  #[test]
+  fn test_find_path_ignoring_case() {
+    let cache = test_cache(
+      "/Users/me",
+      vec![
+        test_entry("/Users/me", 30, None),
+        test_entry("/Users/me/Photos", 20, None),
+        test_entry("/Users/me/Photos/Trip.JPG", 20, None),
+        test_entry("/Users/me/notes.txt", 10, None),
+      ],
+    );
+    assert_eq!(
+      find_path_ignoring_case(&cache, Path::new("/users/ME/photos/trip.jpg")),
+      Some(PathBuf::from("/Users/me/Photos/Trip.JPG"))
+    );
+    assert_eq!(
+      find_path_ignoring_case(&cache, Path::new("/USERS/me")),
+      Some(PathBuf::from("/Users/me"))
+    );
+    assert_eq!(
+      find_path_ignoring_case(&cache, Path::new("/users/me/missing")),
+      None
+    );
+    assert_eq!(find_path_ignoring_case(&cache, Path::new("/other")), None);
+  }
+
+  #[tokio::test]
+  #[cfg(any(target_os = "windows", target_os = "macos"))]
+  async fn test_directory_children_case_insensitive_query() -> std::io::Result<()> {
+    let _guard = GLOBAL_CACHE_TEST_LOCK.lock().await;
+    let temp_dir = tempdir()?;
+    let path = temp_dir.path().canonicalize()?;
+    fs::create_dir(path.join("Photos"))?;
+    fs::write(path.join("Photos").join("trip.jpg"), vec![0u8; 100])?;
+
+    let generation = start_cache_generation(true).unwrap();
+    assert!(store_scan_cache(scan_into_cache(&path)?, generation));
+
+    let query = path.join("PHOTOS").to_string_lossy().into_owned();
+    let tree = get_directory_children(query, None, None, None, None, None)
+      .await
+      .unwrap();
+    assert_eq!(tree.path, path.join("Photos"));
+    assert_eq!(tree.file_count, 1);
+
+    reset_scan_cache().unwrap();
+    Ok(())
+  }
+
+  #[test]
+  fn test_throughput_meter_smooths_over_window() {
+    let start = std::time::Instant::now();
+    let at = |secs: u64| start + Duration::from_secs(secs);
+    let mut meter = ThroughputMeter::new(start);
+
+    let throughput = meter.record(at(1), 100, 1000);
+    assert_eq!(throughput.entries_per_second, 100.0);
+    assert_eq!(throughput.bytes_per_second, 1000.0);
+
+    // Averaged with the earlier ticks rather than jumping to the latest second's rate
+    let throughput = meter.record(at(2), 300, 3000);
+    assert_eq!(throughput.entries_per_second, 150.0);
+    assert_eq!(throughput.entries_scanned, 300);
+
+    // Ticks older than the window stop counting, so a stall shows up within a few seconds
+    for secs in 3..=(2 + THROUGHPUT_WINDOW as u64) {
+      meter.record(at(secs), 300, 3000);
+    }
+    let throughput = meter.record(at(3 + THROUGHPUT_WINDOW as u64), 300, 3000);
+    assert_eq!(throughput.entries_per_second, 0.0);
+    assert_eq!(throughput.bytes_per_second, 0.0);
+  }
+
+  #[test]
+  fn test_locate_in_cache_highlights_target() -> std::io::Result<()> {
+    let temp_dir = tempdir()?;
+    let path = temp_dir.path().canonicalize()?;
+    fs::create_dir_all(path.join("docs").join("old"))?;
+    fs::write(path.join("docs").join("report.pdf"), vec![0u8; 200])?;
+    fs::write(path.join("docs").join("notes.txt"), vec![0u8; 100])?;
+
+    let cache = scan_into_cache(&path)?;
+    let tree = locate_in_cache(&cache, &path.join("docs").join("report.pdf"), 1).unwrap();
+    assert_eq!(tree.path, path.join("docs"));
+
+    fn highlighted(node: &FileSystemTreeNode) -> Vec<PathBuf> {
+      let mut paths: Vec<PathBuf> = node.children.iter().flat_map(highlighted).collect();
+      if node.highlight {
+        paths.push(node.path.clone());
+      }
+      paths
+    }
+    assert_eq!(
+      highlighted(&tree),
+      vec![path.join("docs").join("report.pdf")]
+    );
+
+    assert!(locate_in_cache(&cache, &path.join("docs").join("missing.pdf"), 1).is_err());
+    assert!(locate_in_cache(&cache, &path, 1).is_err());
+
+    Ok(())
+  }
+
+  #[test]
+  fn test_cancelled_scan_keeps_consistent_totals() -> std::io::Result<()> {
+    let temp_dir = tempdir()?;
+    let path = temp_dir.path().canonicalize()?;
+    fs::create_dir(path.join("sub"))?;
+    fs::write(path.join("sub").join("file.txt"), vec![0u8; 1000])?;
+    fs::write(path.join("top.txt"), vec![0u8; 1000])?;
+
+    // Cancelled before anything below the root is read, as if the deadline passed at once
+    let state = ScanState::default();
+    state.cancelled.store(true, Ordering::Relaxed);
+    calculate_size_sync(&path, &state, &ScanOptions::default(), None)?;
+
+    let root = state.analytics_map.get(&path).unwrap().clone();
+    assert_eq!(state.analytics_map.len(), 1);
+    assert_eq!(root.entry_count, 1);
+    assert_eq!(root.file_count, 0, "Unread files aren't counted");
+    assert_eq!(root.directory_count, 1);
+    assert_eq!(
+      root.size_bytes,
+      root.path_info.as_ref().unwrap().size_bytes,
+      "Only the root's own size"
+    );
+
+    Ok(())
+  }
+
+  #[test]
+  fn test_freshness_scores() -> std::io::Result<()> {
+    let temp_dir = tempdir()?;
+    let path = temp_dir.path().canonicalize()?;
+    fs::create_dir_all(path.join("active"))?;
+    fs::create_dir_all(path.join("archive").join("2019"))?;
+    fs::write(path.join("active").join("draft.txt"), vec![0u8; 1000])?;
+    let old_file = path.join("archive").join("2019").join("taxes.pdf");
+    fs::write(&old_file, vec![0u8; 1000])?;
+    let two_years_ago = SystemTime::now() - Duration::from_secs(2 * FRESHNESS_STALE_SECS);
+    fs::File::options()
+      .write(true)
+      .open(&old_file)?
+      .set_modified(two_years_ago)?;
+
+    let cache = scan_into_cache(&path)?;
+    let now = SystemTime::now()
+      .duration_since(UNIX_EPOCH)
+      .unwrap()
+      .as_secs();
+    let scores = find_freshness_scores(&cache, &path, 1, now);
+    let score = |dir: &Path| scores.iter().find(|s| s.path == dir).unwrap().clone();
+
+    let active = score(&path.join("active"));
+    assert_eq!(active.recent_bytes, 1000);
+    assert!(active.score > 0.9, "{}", active.score);
+
+    // The directory's own mtime is fresh, but only its files count
+    let archive = score(&path.join("archive"));
+    assert_eq!(archive.recent_bytes, 0);
+    assert!(archive.newest_modified_time < now - FRESHNESS_STALE_SECS);
+    assert_eq!(archive.score, 0.0);
+
+    let root = score(&path);
+    assert_eq!(root.recent_bytes, 1000);
+    assert!(root.score > archive.score && root.score < active.score);
+    assert!(scores.iter().all(|s| s.depth <= 1), "Limited to max_depth");
+
+    Ok(())
+  }
+
+  #[test]
+  #[cfg(target_family = "unix")]
+  fn test_links_to_scanned_dir_reference_it() -> std::io::Result<()> {
+    let temp_dir = tempdir()?;
+    let path = temp_dir.path().canonicalize()?;
+    let big = path.join("big");
+    fs::create_dir(&big)?;
+    for i in 0..50 {
+      fs::write(big.join(format!("{}.bin", i)), vec![0u8; 1000])?;
+    }
+    fs::create_dir(path.join("links"))?;
+    for i in 0..1000 {
+      std::os::unix::fs::symlink(&big, path.join("links").join(format!("link{}", i)))?;
+    }
+
+    let state = ScanState::default();
+    let options = ScanOptions {
+      follow_dir_symlinks: true,
+      ..Default::default()
+    };
+    calculate_size_sync(&path, &state, &options, None)?;
+
+    // One copy of the folder at its real path, and one small entry per link, instead of the
+    // folder's contents under whichever link was reached first
+    assert_eq!(state.analytics_map.len(), 1 + 1 + 50 + 1 + 1000);
+    assert!(state.analytics_map.contains_key(&big.join("0.bin")));
+    let link = state
+      .analytics_map
+      .get(&path.join("links").join("link0"))
+      .unwrap()
+      .clone();
+    assert_eq!(link.link_target.as_deref(), Some(big.as_path()));
+    assert!(link.size_bytes < 1000);
+
+    // Links count as entries, the folder's files once
+    let root = state.analytics_map.get(&path).unwrap().clone();
+    assert_eq!(root.file_count, 50);
+    assert_eq!(root.entry_count, 1 + 1 + 50 + 1 + 1000);
+    let links = state
+      .analytics_map
+      .get(&path.join("links"))
+      .unwrap()
+      .clone();
+    assert!(root.size_bytes - links.size_bytes >= 50_000);
+    assert!(links.size_bytes < 50_000);
+
+    Ok(())
+  }
+
+  #[test]
+  #[cfg(target_family = "unix")]
+  fn test_projected_free_space() -> std::io::Result<()> {
+    let temp_dir = tempdir()?;
+    let path = temp_dir.path().canonicalize()?;
+    let old = path.join("old");
+    fs::create_dir(&old)?;
+    fs::write(old.join("own.bin"), vec![1u8; 20_000])?;
+    fs::write(old.join("shared.bin"), vec![1u8; 50_000])?;
+    fs::hard_link(old.join("own.bin"), old.join("own_link.bin"))?;
+    fs::create_dir(path.join("keep"))?;
+    fs::hard_link(old.join("shared.bin"), path.join("keep").join("shared.bin"))?;
+
+    let cache = scan_into_cache(&path)?;
+    let allocated = |p: &Path| {
+      let entry = &cache.entries[cache.path_map[p]];
+      entry.path_info.as_ref().unwrap().size_allocated_bytes
+    };
+    let own = allocated(&old.join("own.bin"));
+    let shared = allocated(&old.join("shared.bin"));
+    let dir = allocated(&old);
+
+    let projection = project_free_space(&cache, &old).unwrap();
+    // The link inside the folder goes too, the one outside keeps shared.bin alive
+    assert_eq!(projection.reclaimable_bytes, own + dir);
+    assert_eq!(projection.hard_linked_elsewhere_bytes, shared);
+    assert_eq!(projection.other_volume_bytes, 0);
+    assert!(projection.size_allocated_bytes > projection.reclaimable_bytes);
+    assert_eq!(projection.caveats.len(), 2);
+    assert!(projection.caveats[1].contains("won't be freed"));
+
+    // Deleting both copies frees everything once
+    let projection = project_free_space(&cache, &path).unwrap();
+    assert_eq!(projection.hard_linked_elsewhere_bytes, 0);
+    assert_eq!(format_size(1536), "1.5 KB");
+    assert_eq!(format_size(10), "10 bytes");
+
+    Ok(())
+  }
+
+  #[test]
+  fn test_immutable_files() -> std::io::Result<()> {
+    let temp_dir = tempdir()?;
+    let path = temp_dir.path().canonicalize()?;
+    fs::write(path.join("locked.log"), vec![0u8; 300])?;
+    fs::write(path.join("big.bin"), vec![0u8; 900])?;
+    fs::write(path.join("plain.txt"), vec![0u8; 100])?;
+
+    let cache = scan_into_cache(&path)?;
+    assert!(collect_immutable_files(&cache, platform::get_inode_flags).is_empty());
+
+    // Setting the flags takes root and a filesystem that supports them, so fake them
+    let fake_flags = |file: &Path| match file.file_name().and_then(|n| n.to_str()) {
+      Some("big.bin") => (true, false),
+      Some("locked.log") => (false, true),
+      _ => (false, false),
+    };
+    let files = collect_immutable_files(&cache, fake_flags);
+    let paths: Vec<&Path> = files.iter().map(|f| f.path.as_path()).collect();
+    assert_eq!(paths, vec![path.join("big.bin"), path.join("locked.log")]);
+    assert!(files[0].is_immutable && !files[0].is_append_only);
+    assert!(files[1].is_append_only);
+
+    Ok(())
+  }
+
+  #[test]
+  fn test_text_tree() {
+    let cache = test_cache(
+      "/data",
+      vec![
+        test_entry("/data", 4096, None),
+        test_entry("/data/small.txt", 1000, None),
+        test_entry("/data/photos", 3072, None),
+        test_entry("/data/photos/a.jpg", 2048, None),
+        test_entry("/data/photos/b.jpg", 1024, None),
+      ],
+    );
+
+    let text = write_text_tree(&cache, Path::new("/data"), 2, true).unwrap();
+    assert_eq!(
+      text,
+      "[4.0 KB]  /data\n\
+       ├── [3.0 KB]  photos\n\
+       │   ├── [2.0 KB]  a.jpg\n\
+       │   └── [1.0 KB]  b.jpg\n\
+       └── [1000 bytes]  small.txt\n"
+    );
+
+    // Cut-off children are marked rather than silently dropped
+    let text = write_text_tree(&cache, Path::new("/data"), 1, true).unwrap();
+    assert!(text.contains("├── [3.0 KB]  photos\n│   └── ...\n"));
+    assert!(write_text_tree(&cache, Path::new("/missing"), 1, true).is_none());
+  }
+
+  #[test]
+  fn test_skip_allocated_sizes() -> std::io::Result<()> {
+    let temp_dir = tempdir()?;
+    let path = temp_dir.path().canonicalize()?;
+    fs::write(path.join("small.txt"), b"hi")?;
+    fs::write(path.join("data.bin"), vec![1u8; 10_000])?;
+
+    assert!(ScanOptions::default().compute_allocated);
+    let options = ScanOptions {
+      compute_allocated: false,
+      rounding: RoundingMode::ClusterUp,
+      ..Default::default()
+    };
+    let cache = scan_into_cache_with(&path, &options)?;
+    for entry in &cache.entries {
+      assert_eq!(
+        entry.size_allocated_bytes,
+        entry.size_bytes,
+        "{}",
+        entry.path.display()
+      );
+    }
+
+    Ok(())
+  }
+
+  #[test]
+  fn test_entries_in_window() -> std::io::Result<()> {
+    let temp_dir = tempdir()?;
+    let path = temp_dir.path().canonicalize()?;
+    let set_mtime = |name: &str, secs: u64| -> std::io::Result<()> {
+      fs::write(path.join(name), b"data")?;
+      fs::File::options()
+        .write(true)
+        .open(path.join(name))?
+        .set_modified(UNIX_EPOCH + Duration::from_secs(secs))
+    };
+    set_mtime("before.txt", 1_000)?;
+    set_mtime("first.txt", 2_000)?;
+    set_mtime("second.txt", 3_000)?;
+    set_mtime("third.txt", 3_000)?;
+
+    let cache = scan_into_cache(&path)?;
+    let names = |entries: Vec<ModifiedEntry>| -> Vec<String> {
+      entries
+        .iter()
+        .map(|e| e.path.file_name().unwrap().to_string_lossy().into_owned())
+        .collect()
+    };
+    assert_eq!(
+      names(find_entries_in_window(&cache, 2_000, 3_000, 10)),
+      vec!["second.txt", "third.txt", "first.txt"]
+    );
+    assert_eq!(
+      names(find_entries_in_window(&cache, 1_500, 3_000, 1)),
+      vec!["second.txt"]
+    );
+
+    // Reaching up to now includes the freshly created directory
+    let now = SystemTime::now()
+      .duration_since(UNIX_EPOCH)
+      .unwrap()
+      .as_secs();
+    let open_ended = find_entries_in_window(&cache, 2_500, now, 10);
+    assert_eq!(open_ended.len(), 3);
+    assert!(open_ended[0].is_directory);
+
+    Ok(())
+  }
+
+  #[test]
+  fn test_equal_sizes_are_ordered_by_name() -> std::io::Result<()> {
+    let temp_dir = tempdir()?;
+    let path = temp_dir.path().canonicalize()?;
+    for name in ["delta.txt", "bravo.txt", "alpha.txt", "charlie.txt"] {
+      fs::write(path.join(name), vec![0u8; 100])?;
+    }
+    for name in ["sub_b", "sub_a"] {
+      fs::create_dir(path.join(name))?;
+      fs::write(path.join(name).join("file.txt"), vec![0u8; 1000])?;
+    }
+
+    let cache = scan_into_cache(&path)?;
+    let names = |nodes: &[FileSystemTreeNode]| -> Vec<String> {
+      nodes.iter().map(|node| node.name.clone()).collect()
+    };
+    let expected_files = vec!["alpha.txt", "bravo.txt", "charlie.txt", "delta.txt"];
+
+    let flat = build_tree_from_entries_with_depth(&cache.entries, &path, 1, false);
+    assert_eq!(names(&flat.children[..2]), vec!["sub_a", "sub_b"]);
+    assert_eq!(names(&flat.children[2..]), expected_files);
+
+    let indexed = build_tree_from_indices(
+      &cache.entries,
+      &cache.path_map,
+      &cache.children_map,
+      &path,
+      1,
+      false,
+    )
+    .unwrap();
+    assert_eq!(names(&indexed.children), names(&flat.children));
+
+    // The virtual files node keeps the same order for its members
+    let with_virtual = build_tree_from_entries_with_depth(&cache.entries, &path, 1, true);
+    let files_node = with_virtual
+      .children
+      .iter()
+      .find(|child| child.is_virtual_directory)
+      .unwrap();
+    assert_eq!(names(&files_node.children), expected_files);
+    assert_eq!(names(&with_virtual.children[..2]), vec!["sub_a", "sub_b"]);
+
+    Ok(())
+  }
+
+  #[test]
+  fn test_current_root_size() -> std::io::Result<()> {
+    let temp_dir = tempdir()?;
+    let path = temp_dir.path().canonicalize()?;
+    fs::create_dir(path.join("sub"))?;
+    fs::write(path.join("sub").join("file.txt"), vec![0u8; 5000])?;
+
+    // Nothing recorded for the root yet
+    let state = ScanState::default();
+    let empty = current_root_size(&path, &state);
+    assert_eq!((empty.size_bytes, empty.entry_count), (0, 0));
+
+    // Once the scan is done the root's own totals are reported
+    calculate_size_sync(&path, &state, &ScanOptions::default(), None)?;
+    let root = state.analytics_map.get(&path).unwrap().clone();
+    let done = current_root_size(&path, &state);
+    assert_eq!(done.size_bytes, root.size_bytes);
+    assert_eq!(done.entry_count, 3);
+
+    // Mid-scan, the running counters stand in for totals not yet added up
+    let running = ScanState::default();
+    running.analytics_map.insert(path.clone(), root.clone());
+    running.record_scanned(root.size_bytes + 1);
+    assert_eq!(
+      current_root_size(&path, &running).size_bytes,
+      root.size_bytes + 1
+    );
+
+    Ok(())
+  }
+
+  #[test]
+  fn test_cache_memory_estimate() {
+    let entries = vec![
+      test_entry("/root", 300, None),
+      test_entry("/root/a.txt", 100, Some("alice")),
+      test_entry("/root/b.txt", 200, None),
+    ];
+    let path_bytes = "/root".len() + "/root/a.txt".len() + "/root/b.txt".len();
+    let indexed = test_cache("/root", entries.clone());
+    let estimate = indexed.memory_estimate() as usize;
+    assert!(estimate >= 3 * std::mem::size_of::<AnalyticsInfo>() + path_bytes + "alice".len());
+
+    // The indices add to the footprint, and more entries cost more
+    let unindexed = ScanCache {
+      path_map: HashMap::new(),
+      children_map: HashMap::new(),
+      sorted_by_size: Vec::new(),
+      indexed: false,
+      ..test_cache("/root", entries.clone())
+    };
+    assert!(unindexed.memory_estimate() < indexed.memory_estimate());
+    let smaller = test_cache("/root", entries[..2].to_vec());
+    assert!(smaller.memory_estimate() < indexed.memory_estimate());
+  }
+
+  #[test]
+  fn test_root_overhead() {
+    // The root's 200 bytes of its own aren't in either child
+    let cache = test_cache(
+      "/root",
+      vec![
+        test_entry("/root", 1000, None),
+        test_entry("/root/a.txt", 300, None),
+        test_entry("/root/b.txt", 500, None),
+      ],
+    );
+    let build = || {
+      build_tree_from_indices(
+        &cache.entries,
+        &cache.path_map,
+        &cache.children_map,
+        Path::new("/root"),
+        1,
+        false,
+      )
+      .unwrap()
+    };
+    let percent_sum = |tree: &FileSystemTreeNode| {
+      tree
+        .children
+        .iter()
+        .map(|c| c.percent_of_parent)
+        .sum::<f64>()
+    };
+
+    let mut tree = build();
+    apply_root_overhead(&mut tree, RootOverhead::Unattributed);
+    assert!((percent_sum(&tree) - 80.0).abs() < 1e-9);
+
+    let mut tree = build();
+    apply_root_overhead(&mut tree, RootOverhead::ChildrenOnly);
+    assert!((percent_sum(&tree) - 100.0).abs() < 1e-9);
+    assert!((tree.children[0].percent_of_parent - 62.5).abs() < 1e-9);
+
+    let mut tree = build();
+    apply_root_overhead(&mut tree, RootOverhead::Node);
+    assert!((percent_sum(&tree) - 100.0).abs() < 1e-9);
+    let names: Vec<&str> = tree.children.iter().map(|c| c.name.as_str()).collect();
+    assert_eq!(names, vec!["b.txt", "a.txt", "[directory overhead]"]);
+    assert_eq!(tree.children[2].size_bytes, 200);
   }
-}
 
-// Command to clear the scan cache
-#[tauri::command]
-async fn clear_scan_cache() -> Result<(), String> {
-  if let Ok(mut global_cache) = GLOBAL_SCAN_CACHE.lock() {
-    *global_cache = None;
+  #[test]
+  fn test_export_ndjson() -> std::io::Result<()> {
+    let temp_dir = tempdir()?;
+    let path = temp_dir.path().canonicalize()?;
+    fs::create_dir(path.join("sub"))?;
+    fs::write(path.join("sub").join("file.txt"), vec![0u8; 1234])?;
+
+    let cache = scan_into_cache(&path)?;
+    let mut output = Vec::new();
+    let lines = write_ndjson_export(&cache, &mut output)?;
+    assert_eq!(lines, 3);
+
+    let records: Vec<serde_json::Value> = String::from_utf8(output)
+      .unwrap()
+      .lines()
+      .map(|line| serde_json::from_str(line).unwrap())
+      .collect();
+    assert_eq!(records.len(), 3);
+    let file = records
+      .iter()
+      .find(|record| record["path"].as_str().unwrap().ends_with("file.txt"))
+      .unwrap();
+    assert_eq!(file["size_bytes"], 1234);
+    assert_eq!(file["is_dir"], false);
+    let sub = records
+      .iter()
+      .find(|record| record["path"].as_str().unwrap().ends_with("sub"))
+      .unwrap();
+    assert_eq!(sub["is_dir"], true);
+    assert_eq!(sub["file_count"], 1);
+
     Ok(())
-  } else {
-    Err("Failed to acquire lock on global cache".to_string())
   }
-}
 
-#[cfg_attr(mobile, tauri::mobile_entry_point)]
-pub fn run() {
-  // Create a multi-threaded Tokio runtime
-  let runtime = tokio::runtime::Runtime::new().expect("Failed to create Tokio runtime");
+  #[test]
+  fn test_protected_paths() -> std::io::Result<()> {
+    let temp_dir = tempdir()?;
+    let path = temp_dir.path().canonicalize()?;
+    let protected = platform::protected_roots().swap_remove(0);
 
-  // Set the runtime as the default for this thread
-  let _guard = runtime.enter();
+    assert!(is_protected_root(&protected));
+    assert!(!is_protected_root(&path));
+    assert!(check_protected_paths(std::slice::from_ref(&path), false).is_ok());
 
-  tauri::Builder::default()
-    .plugin(tauri_plugin_opener::init())
-    .plugin(tauri_plugin_dialog::init())
-    .plugin(tauri_plugin_fs::init())
-    .plugin(tauri_plugin_shell::init())
-    .invoke_handler(tauri::generate_handler![
-      scan_directory_size,
-      get_free_space,
-      get_space_info,
-      get_directory_children, // Add the new command
-      clear_scan_cache        // Add cache clearing command
-    ])
-    .run(tauri::generate_context!())
-    .expect("error while running tauri application");
-}
+    let err = check_protected_paths(&[path.clone(), protected.clone()], false).unwrap_err();
+    assert!(err.contains("protected"), "{}", err);
+    assert!(check_protected_paths(&[path, protected], true).is_ok());
 
-#[cfg(test)]
-mod tests {
-  use super::*;
-  use std::fs::{self, File};
-  use std::io::Write;
-  use tempfile::tempdir;
+    #[cfg(target_family = "unix")]
+    {
+      assert!(is_protected_root(Path::new("/usr/")));
+      assert!(!is_protected_root(Path::new("/usr/local")));
+    }
 
-  #[tokio::test]
-  async fn test_calculate_size_empty_directory() -> std::io::Result<()> {
-    // Create a temporary directory for testing
-    let temp_dir = tempdir()?;
-    let path = temp_dir.path().to_path_buf();
+    // Canonical paths are verbatim on Windows, which is how scanned roots arrive
+    #[cfg(windows)]
+    {
+      let system_root = PathBuf::from(std::env::var_os("SystemRoot").unwrap()).canonicalize()?;
+      assert!(system_root.to_string_lossy().starts_with(r"\\?\"));
+      assert!(is_protected_root(&system_root));
+      assert!(check_protected_paths(&[system_root], false).is_err());
+    }
 
-    // Create analytics map and visited inodes
-    let analytics_map = Arc::new(DashMap::new());
-    let visited_inodes = Arc::new(DashSet::new());
-    let processed_paths = Arc::new(DashSet::new());
+    Ok(())
+  }
 
-    // Run the function
-    calculate_size_sync(
-      path.as_path(),
-      analytics_map.clone(),
-      path.as_path(),
-      visited_inodes,
-      processed_paths,
-    )?;
+  #[test]
+  fn test_allocated_percent_of_parent() {
+    // The compressed file is large but takes little space on disk
+    let with_allocated = |path: &str, size_bytes: u64, size_allocated_bytes: u64| {
+      Arc::new(AnalyticsInfo {
+        size_allocated_bytes,
+        unique_allocated_bytes: size_allocated_bytes,
+        ..(*test_entry(path, size_bytes, None)).clone()
+      })
+    };
+    let cache = test_cache(
+      "/root",
+      vec![
+        test_entry("/root", 1000, None),
+        with_allocated("/root/compressed.bin", 800, 200),
+        with_allocated("/root/plain.txt", 200, 800),
+      ],
+    );
 
-    // Verify the results
+    let by_name = |tree: &FileSystemTreeNode, name: &str| -> (f64, f64) {
+      let child = tree.children.iter().find(|c| c.name == name).unwrap();
+      (child.percent_of_parent, child.percent_of_parent_allocated)
+    };
+    let indexed = build_tree_from_indices(
+      &cache.entries,
+      &cache.path_map,
+      &cache.children_map,
+      Path::new("/root"),
+      1,
+      false,
+    )
+    .unwrap();
+    let flat = build_tree_from_entries_with_depth(&cache.entries, Path::new("/root"), 1, false);
+    for tree in [&indexed, &flat] {
+      assert_eq!(by_name(tree, "compressed.bin"), (80.0, 20.0));
+      assert_eq!(by_name(tree, "plain.txt"), (20.0, 80.0));
+    }
+
+    // The virtual files node and its members get both percentages too
+    let with_virtual = build_tree_from_indices(
+      &cache.entries,
+      &cache.path_map,
+      &cache.children_map,
+      Path::new("/root"),
+      1,
+      true,
+    )
+    .unwrap();
+    let files_node = &with_virtual.children[0];
+    assert!(files_node.is_virtual_directory);
+    assert_eq!(files_node.percent_of_parent_allocated, 100.0);
+    assert_eq!(by_name(files_node, "compressed.bin"), (80.0, 20.0));
+  }
+
+  #[test]
+  #[cfg(target_os = "linux")]
+  fn test_pseudo_filesystems() -> std::io::Result<()> {
+    let temp_dir = tempdir()?;
+    let regular = temp_dir.path().join("file.txt");
+    fs::write(&regular, vec![0u8; 100])?;
     assert!(
-      analytics_map.contains_key(&path),
-      "Path should be in the analytics map"
+      !platform::get_path_info(&regular, false)
+        .unwrap()
+        .is_pseudo_fs
     );
 
-    let analytics = analytics_map.get(&path).unwrap();
-    // Directories have different size behaviors on different platforms
-    #[cfg(target_family = "unix")]
-    assert!(
-      analytics.size_bytes > 0,
-      "Directory should have a size on Unix filesystems"
+    let status = platform::get_path_info("/proc/self/status", false).unwrap();
+    assert!(status.is_pseudo_fs);
+    assert_eq!((status.size_bytes, status.size_allocated_bytes), (0, 0));
+
+    // Entries are listed as empty, or skipped altogether below a root inside /proc
+    let path = Path::new("/proc/sys/kernel/random");
+    let cache = scan_into_cache(path)?;
+    assert!(cache.entries.len() > 1);
+    assert!(cache.entries.iter().all(|e| e.size_bytes == 0));
+
+    let options = ScanOptions {
+      include_pseudo_filesystems: false,
+      ..Default::default()
+    };
+    let cache = scan_into_cache_with(path, &options)?;
+    assert_eq!(cache.entries.len(), 1);
+    assert_eq!(cache.entries[0].path, path);
+
+    Ok(())
+  }
+
+  #[test]
+  fn test_heaviest_path() {
+    let cache = test_cache(
+      "/root",
+      vec![
+        test_entry("/root", 1000, None),
+        test_entry("/root/small", 100, None),
+        test_entry("/root/big", 900, None),
+        test_entry("/root/big/deeper", 600, None),
+        test_entry("/root/big/other", 300, None),
+        test_entry("/root/big/deeper/huge.bin", 600, None),
+      ],
     );
+    let paths = |steps: Vec<HeaviestPathStep>| -> Vec<PathBuf> {
+      steps.into_iter().map(|step| step.path).collect()
+    };
 
-    // On Windows, empty directories might report a size of 0
-    #[cfg(target_os = "windows")]
-    assert!(
-      analytics.size_bytes >= 0,
-      "Directory size might be 0 on Windows"
+    let steps = find_heaviest_path(&cache, 10);
+    assert_eq!(steps.last().unwrap().size_bytes, 600);
+    assert_eq!(
+      paths(steps),
+      vec![
+        PathBuf::from("/root"),
+        PathBuf::from("/root/big"),
+        PathBuf::from("/root/big/deeper"),
+        PathBuf::from("/root/big/deeper/huge.bin"),
+      ]
     );
 
-    assert_eq!(analytics.entry_count, 1, "Should count itself as one entry");
-    assert_eq!(analytics.file_count, 0, "Should have 0 files");
+    // The depth limit counts levels below the root
     assert_eq!(
-      analytics.directory_count, 1,
-      "Should count itself as one directory"
+      paths(find_heaviest_path(&cache, 1)),
+      vec![PathBuf::from("/root"), PathBuf::from("/root/big")]
+    );
+    assert_eq!(find_heaviest_path(&cache, 0).len(), 1);
+  }
+
+  #[test]
+  fn test_min_record_size() -> std::io::Result<()> {
+    let temp_dir = tempdir()?;
+    let path = temp_dir.path().canonicalize()?;
+    fs::create_dir(path.join("sub"))?;
+    fs::write(path.join("big.bin"), vec![0u8; 5000])?;
+    fs::write(path.join("tiny_a.txt"), b"a")?;
+    fs::write(path.join("tiny_b.txt"), b"bb")?;
+    fs::write(path.join("sub").join("tiny_c.txt"), b"ccc")?;
+    fs::write(path.join("sub").join("big.bin"), vec![0u8; 3000])?;
+
+    let full = scan_into_cache(&path)?;
+    let options = ScanOptions {
+      min_record_size: 100,
+      ..Default::default()
+    };
+    let pruned = scan_into_cache_with(&path, &options)?;
+
+    // The tiny files are gone, and every directory's totals are unchanged
+    assert_eq!(pruned.entries.len(), full.entries.len() - 3);
+    assert!(pruned
+      .entries
+      .iter()
+      .all(|e| !e.path.ends_with("tiny_c.txt")));
+    for entry in pruned.entries.iter().filter(|e| is_directory_entry(e)) {
+      let unfiltered = &full.entries[full.path_map[&entry.path]];
+      assert_eq!(entry.size_bytes, unfiltered.size_bytes);
+      assert_eq!(entry.size_allocated_bytes, unfiltered.size_allocated_bytes);
+      assert_eq!(entry.entry_count, unfiltered.entry_count);
+      assert_eq!(entry.file_count, unfiltered.file_count);
+    }
+
+    // Listings show them as one node making up the difference
+    let root = &pruned.entries[pruned.path_map[&path]];
+    let mut tree = build_tree_from_indices(
+      &pruned.entries,
+      &pruned.path_map,
+      &pruned.children_map,
+      &path,
+      1,
+      true,
+    )
+    .unwrap();
+    add_unrecorded_files_node(&mut tree, root);
+    let small = tree
+      .children
+      .iter()
+      .find(|child| child.name == "[small files]")
+      .unwrap();
+    assert_eq!(
+      (small.size_bytes, small.file_count, small.entry_count),
+      (3, 2, 2)
     );
 
+    // Nothing is added for a scan that recorded every file
+    let full_root = &full.entries[full.path_map[&path]];
+    let mut tree = build_tree_from_entries_with_depth(&full.entries, &path, 1, true);
+    let children_before = tree.children.len();
+    add_unrecorded_files_node(&mut tree, full_root);
+    assert_eq!(tree.children.len(), children_before);
+
     Ok(())
   }
 
   #[tokio::test]
-  async fn test_calculate_size_with_files() -> std::io::Result<()> {
-    // Create a temporary directory with files
+  async fn test_scan_phase_ignores_superseded_generations() {
+    let _guard = GLOBAL_CACHE_TEST_LOCK.lock().await;
+    let generation = start_cache_generation(true).unwrap();
+    assert!(update_scan_phase(generation, ScanPhase::Scanning));
+    assert!(update_scan_phase(generation, ScanPhase::BuildingIndex));
+    assert_eq!(get_scan_state().await.unwrap(), ScanPhase::BuildingIndex);
+
+    // A clear takes over, the old scan finishing late doesn't undo it
+    let cleared = reset_scan_cache().unwrap();
+    assert!(update_scan_phase(cleared, ScanPhase::Idle));
+    assert!(!update_scan_phase(generation, ScanPhase::Ready));
+    assert_eq!(get_scan_state().await.unwrap(), ScanPhase::Idle);
+  }
+
+  #[test]
+  fn test_unreadable_scan_root() -> std::io::Result<()> {
     let temp_dir = tempdir()?;
-    let path = temp_dir.path().to_path_buf();
+    let path = temp_dir.path().canonicalize()?;
+    let file = path.join("file.txt");
+    fs::write(&file, b"data")?;
+    assert!(check_root_readable(&path).is_ok());
+    assert!(check_root_readable(&file).is_ok());
 
-    // Create a file with known content
-    let file_path = path.join("test_file.txt");
-    let mut file = File::create(&file_path)?;
-    let test_data = "Hello, world!";
-    file.write_all(test_data.as_bytes())?;
+    #[cfg(target_family = "unix")]
+    {
+      use std::os::unix::fs::PermissionsExt;
+      // Root can list any directory, so there is nothing to test as root
+      if unsafe { libc::geteuid() } != 0 {
+        let locked = path.join("locked");
+        fs::create_dir(&locked)?;
+        fs::set_permissions(&locked, fs::Permissions::from_mode(0o000))?;
+        let err = check_root_readable(&locked).unwrap_err();
+        fs::set_permissions(&locked, fs::Permissions::from_mode(0o755))?;
+        assert_eq!(err.kind(), std::io::ErrorKind::PermissionDenied);
+        assert_eq!(err.to_string(), "Cannot read directory: permission denied");
+      }
+    }
 
-    // Create analytics map and visited inodes
-    let analytics_map = Arc::new(DashMap::new());
-    let visited_inodes = Arc::new(DashSet::new());
-    let processed_paths = Arc::new(DashSet::new());
+    Ok(())
+  }
 
-    // Run the function
-    calculate_size_sync(
-      path.as_path(),
-      analytics_map.clone(),
-      path.as_path(),
-      visited_inodes,
-      processed_paths,
-    )?;
+  #[test]
+  fn test_children_by_owner() {
+    // test_entry has no path info, give directories a directory's with 10 bytes of their own
+    let mut dir_info = platform::get_path_info(std::env::temp_dir(), false).unwrap();
+    dir_info.size_bytes = 10;
+    dir_info.size_allocated_bytes = 10;
+    let directory = |path: &str, size_bytes: u64, owner: &str| {
+      let mut entry = (*test_entry(path, size_bytes, Some(owner))).clone();
+      entry.file_count = 0;
+      entry.directory_count = 1;
+      entry.path_info = Some(dir_info.clone());
+      Arc::new(entry)
+    };
+    let file = |path: &str, size_bytes: u64, owner: &str| test_entry(path, size_bytes, Some(owner));
+    let cache = test_cache(
+      "/root",
+      vec![
+        directory("/root", 1000, "root"),
+        directory("/root/shared", 600, "root"),
+        file("/root/shared/alice.bin", 300, "alice"),
+        file("/root/shared/bob.bin", 290, "bob"),
+        directory("/root/bobs", 200, "bob"),
+        file("/root/bobs/notes.txt", 190, "bob"),
+        file("/root/alice.txt", 150, "alice"),
+        file("/root/root.txt", 40, "root"),
+      ],
+    );
 
-    // Verify the results
-    assert!(
-      analytics_map.contains_key(&path),
-      "Path should be in the analytics map"
+    let tree = build_owner_tree(&cache, Path::new("/root"), "alice").unwrap();
+    assert_eq!(tree.size_bytes, 450);
+    assert_eq!((tree.file_count, tree.directory_count), (2, 2));
+    let children: Vec<(&str, u64)> = tree
+      .children
+      .iter()
+      .map(|c| (c.name.as_str(), c.size_bytes))
+      .collect();
+    assert_eq!(children, vec![("shared", 300), ("alice.txt", 150)]);
+    assert_eq!(tree.children[0].percent_of_parent, 66.67);
+
+    // Directories count their own size towards their owner
+    let tree = build_owner_tree(&cache, Path::new("/root"), "bob").unwrap();
+    let children: Vec<(&str, u64)> = tree
+      .children
+      .iter()
+      .map(|c| (c.name.as_str(), c.size_bytes))
+      .collect();
+    assert_eq!(children, vec![("shared", 290), ("bobs", 200)]);
+    assert_eq!(tree.children[1].directory_count, 1);
+
+    let nobody = build_owner_tree(&cache, Path::new("/root"), "nobody").unwrap();
+    assert!(nobody.children.is_empty());
+    assert_eq!(nobody.size_bytes, 0);
+  }
+
+  #[test]
+  fn test_space_watch_reports_crossings_once() {
+    let mut watch = SpaceWatch::new(10.0);
+    assert_eq!(watch.record(500, 1000), None);
+    assert_eq!(watch.record(50, 1000), Some("low-space"));
+    assert_eq!(watch.record(40, 1000), None);
+    assert_eq!(watch.record(100, 1000), Some("space-recovered"));
+    assert_eq!(watch.record(200, 1000), None);
+
+    // Starting out below the threshold is reported on the first check
+    let mut watch = SpaceWatch::new(10.0);
+    assert_eq!(watch.record(10, 1000), Some("low-space"));
+  }
+
+  #[test]
+  fn test_expand_input_path() {
+    let home = tempfile::tempdir().unwrap();
+    let home_path = home.path();
+    std::fs::create_dir(home_path.join("sub")).unwrap();
+
+    assert_eq!(expand_input_path("~", Some(home_path)), home_path);
+    assert_eq!(
+      expand_input_path("~/sub", Some(home_path)),
+      home_path.join("sub")
+    );
+    assert_eq!(
+      expand_input_path("./sub", Some(home_path))
+        .canonicalize()
+        .unwrap(),
+      home_path.join("sub").canonicalize().unwrap()
     );
 
-    let analytics = analytics_map.get(&path).unwrap();
-    assert!(
-      analytics.size_bytes >= test_data.len() as u64,
-      "Directory size should include the file size"
+    // Absolute paths and names merely starting with a tilde are left alone
+    let absolute = home_path.join("sub");
+    assert_eq!(
+      expand_input_path(absolute.to_str().unwrap(), Some(home_path)),
+      absolute
     );
-    assert_eq!(analytics.entry_count, 2, "Should count itself and one file");
-    assert_eq!(analytics.file_count, 1, "Should have 1 file");
     assert_eq!(
-      analytics.directory_count, 1,
-      "Should count itself as one directory"
+      expand_input_path("~other", Some(home_path)),
+      home_path.join("~other")
     );
 
-    Ok(())
+    let missing = resolve_input_path("~/tree-size-missing-directory").unwrap_err();
+    assert_eq!(missing.kind(), std::io::ErrorKind::NotFound);
+    assert!(missing.to_string().starts_with("Path does not exist"));
   }
 
   #[tokio::test]
-  async fn test_calculate_size_with_subdirectories() -> std::io::Result<()> {
-    // Create a temporary directory with subdirectories and files
-    let temp_dir = tempdir()?;
-    let path = temp_dir.path().to_path_buf();
+  async fn test_size_delta_survives_rescan() {
+    let _guard = GLOBAL_CACHE_TEST_LOCK.lock().await;
+    let temp_dir = tempfile::tempdir().unwrap();
+    let root = temp_dir.path().canonicalize().unwrap();
+    std::fs::write(root.join("a.txt"), vec![0u8; 100]).unwrap();
+    let path = root.to_string_lossy().to_string();
+
+    let generation = start_cache_generation(true).unwrap();
+    assert!(store_scan_cache(
+      scan_into_cache(&root).unwrap(),
+      generation
+    ));
+    let snapshot = snapshot_size(path.clone()).await.unwrap();
+    assert!(snapshot.size_bytes >= 100);
+
+    std::fs::write(root.join("b.txt"), vec![0u8; 50]).unwrap();
+    let generation = start_cache_generation(true).unwrap();
+    assert!(store_scan_cache(
+      scan_into_cache(&root).unwrap(),
+      generation
+    ));
+    let delta = size_delta_since_snapshot(path).await.unwrap();
+    assert_eq!(delta.previous_size_bytes, snapshot.size_bytes);
+    assert_eq!(delta.current_size_bytes, snapshot.size_bytes + 50);
+    assert_eq!(delta.delta_bytes, 50);
+
+    let shrunk = size_delta(
+      root,
+      snapshot,
+      snapshot.size_bytes - 60,
+      snapshot.taken_at + Duration::from_secs(10),
+    );
+    assert_eq!(shrunk.delta_bytes, -60);
+    assert_eq!(shrunk.elapsed_secs, 10.0);
 
-    // Create a subdirectory
-    let subdir_path = path.join("subdir");
-    fs::create_dir(&subdir_path)?;
+    reset_scan_cache().unwrap();
+  }
 
-    // Create a file in the subdirectory
-    let file_path = subdir_path.join("test_file.txt");
-    let mut file = File::create(&file_path)?;
-    let test_data = "Hello, nested world!";
-    file.write_all(test_data.as_bytes())?;
+  #[test]
+  #[cfg(unix)]
+  fn test_prefetched_entries_match_path_info() -> std::io::Result<()> {
+    let temp_dir = tempdir()?;
+    let path = temp_dir.path().canonicalize()?;
+    fs::write(path.join("file.txt"), vec![0u8; 1234])?;
+    std::os::unix::fs::symlink(path.join("file.txt"), path.join("link"))?;
 
-    // Create another file in the root directory
-    let root_file_path = path.join("root_file.txt");
-    let mut root_file = File::create(&root_file_path)?;
-    let root_test_data = "Root data";
-    root_file.write_all(root_test_data.as_bytes())?;
+    for entry in fs::read_dir(&path)? {
+      let entry = entry?;
+      let prefetched = PrefetchedEntry::from_dir_entry(&entry);
+      assert_eq!(
+        prefetched.file_type.map(|t| t.is_symlink()),
+        Some(entry.path().ends_with("link"))
+      );
+      // Only Windows gets metadata from read_dir for free
+      assert!(prefetched.metadata.is_none());
+    }
 
-    // Create analytics map and visited inodes
-    let analytics_map = Arc::new(DashMap::new());
-    let visited_inodes = Arc::new(DashSet::new());
-    let processed_paths = Arc::new(DashSet::new());
+    let file = path.join("file.txt");
+    let metadata = fs::symlink_metadata(&file)?;
+    let from_metadata = platform::get_path_info_from_metadata(&file, &metadata, true);
+    let from_path = platform::get_path_info_with_allocation(&file, false, true);
+    assert_eq!(
+      from_metadata.map(|info| (
+        info.size_bytes,
+        info.size_allocated_bytes,
+        info.inode_device
+      )),
+      from_path.map(|info| (
+        info.size_bytes,
+        info.size_allocated_bytes,
+        info.inode_device
+      ))
+    );
 
-    // Run the function
-    calculate_size_sync(
-      path.as_path(),
-      analytics_map.clone(),
-      path.as_path(),
-      visited_inodes,
-      processed_paths,
-    )?;
+    // Scans still see the link as a link when its type comes from read_dir
+    let state = ScanState::default();
+    calculate_size_sync(&path, &state, &ScanOptions::default(), None)?;
+    let link = state.analytics_map.get(&path.join("link")).unwrap();
+    assert!(link.path_info.as_ref().unwrap().is_symlink);
+    assert_eq!(state.analytics_map.get(&path).unwrap().file_count, 1);
+    Ok(())
+  }
 
-    // Verify the results for the root directory
-    assert!(
-      analytics_map.contains_key(&path),
-      "Root path should be in the analytics map"
+  #[test]
+  fn test_extension_stats() {
+    let cache = test_cache(
+      "/data",
+      vec![
+        test_entry("/data", 0, None),
+        test_entry("/data/a.txt", 10, None),
+        test_entry("/data/b.TXT", 30, None),
+        test_entry("/data/c.txt", 20, None),
+        test_entry("/data/d.txt", 900, None),
+        test_entry("/data/movie.mp4", 500, None),
+        test_entry("/data/Makefile", 5, None),
+      ],
     );
 
-    let root_analytics = analytics_map.get(&path).unwrap();
-    let expected_size = (test_data.len() + root_test_data.len()) as u64;
-    assert!(
-      root_analytics.size_bytes >= expected_size,
-      "Directory size should include all files and subdirectories"
-    );
-    assert_eq!(
-      root_analytics.entry_count, 4,
-      "Should count root dir, subdir, and 2 files"
+    let stats = compute_extension_stats(&cache);
+    let extensions: Vec<&str> = stats.iter().map(|s| s.extension.as_str()).collect();
+    assert_eq!(extensions, vec!["txt", "mp4", "none"]);
+
+    let txt = &stats[0];
+    assert_eq!(txt.file_count, 4);
+    assert_eq!(txt.total_bytes, 960);
+    assert_eq!(txt.average_bytes, 240);
+    assert_eq!(txt.median_bytes, 25);
+    assert_eq!(txt.max_bytes, 900);
+    assert_eq!(txt.max_path, PathBuf::from("/data/d.txt"));
+    assert_eq!(stats[1].median_bytes, 500);
+
+    assert_eq!(median_size(&mut [7, 1, 3]), 3);
+    assert_eq!(median_size(&mut []), 0);
+  }
+
+  #[test]
+  fn test_extension_by_toplevel() {
+    let dir = |path: &str| {
+      let mut entry = (*test_entry(path, 0, None)).clone();
+      entry.directory_count = 1;
+      Arc::new(entry)
+    };
+    let cache = test_cache(
+      "/data",
+      vec![
+        dir("/data"),
+        dir("/data/projects"),
+        dir("/data/projects/app"),
+        test_entry("/data/projects/app/disk.iso", 700, None),
+        test_entry("/data/projects/app/notes.txt", 5, None),
+        dir("/data/downloads"),
+        test_entry("/data/downloads/a.ISO", 300, None),
+        test_entry("/data/downloads/b.iso", 600, None),
+        dir("/data/music"),
+        test_entry("/data/music/song.mp3", 80, None),
+        test_entry("/data/loose.iso", 50, None),
+      ],
     );
-    assert_eq!(root_analytics.file_count, 2, "Should have 2 files");
+
+    let totals = extension_by_toplevel(&cache, ".iso");
     assert_eq!(
-      root_analytics.directory_count, 2,
-      "Should count root and subdirectory"
+      totals,
+      vec![
+        (PathBuf::from("/data/downloads"), 900, 2),
+        (PathBuf::from("/data/projects"), 700, 1),
+      ]
     );
+    assert_eq!(extension_by_toplevel(&cache, "ISO"), totals);
+    assert!(extension_by_toplevel(&cache, "flac").is_empty());
+  }
 
-    // Verify the results for the subdirectory
-    assert!(
-      analytics_map.contains_key(&subdir_path),
-      "Subdir path should be in the analytics map"
-    );
+  #[test]
+  #[cfg(unix)]
+  fn test_symlink_nodes_report_their_target() -> std::io::Result<()> {
+    let temp_dir = tempdir()?;
+    let root = temp_dir.path().canonicalize()?;
+    fs::write(root.join("real.txt"), b"contents")?;
+    std::os::unix::fs::symlink("real.txt", root.join("link"))?;
+    std::os::unix::fs::symlink(root.join("gone.txt"), root.join("dangling"))?;
 
-    let subdir_analytics = analytics_map.get(&subdir_path).unwrap();
-    assert!(
-      subdir_analytics.size_bytes >= test_data.len() as u64,
-      "Subdirectory size should include its file size"
-    );
+    let cache = scan_into_cache(&root)?;
+    let tree = build_tree_from_indices(
+      &cache.entries,
+      &cache.path_map,
+      &cache.children_map,
+      &root,
+      1,
+      false,
+    )
+    .unwrap();
+    let child = |name: &str| {
+      tree
+        .children
+        .iter()
+        .find(|node| node.name == name)
+        .unwrap_or_else(|| panic!("{} should be listed", name))
+    };
+
+    assert!(!child("real.txt").is_symlink);
+    assert_eq!(child("real.txt").symlink_target, None);
+    assert!(child("link").is_symlink);
     assert_eq!(
-      subdir_analytics.entry_count, 2,
-      "Should count subdir and its file"
+      child("link").symlink_target,
+      Some(PathBuf::from("real.txt"))
     );
-    assert_eq!(subdir_analytics.file_count, 1, "Should have 1 file");
+    // A broken link is still listed, pointing at its missing target
+    assert!(child("dangling").is_symlink);
     assert_eq!(
-      subdir_analytics.directory_count, 1,
-      "Should count itself as one directory"
+      child("dangling").symlink_target,
+      Some(root.join("gone.txt"))
+    );
+    Ok(())
+  }
+
+  #[test]
+  fn test_counts_only_scan_zeroes_sizes() -> std::io::Result<()> {
+    let temp_dir = tempdir()?;
+    let root = temp_dir.path().canonicalize()?;
+    fs::create_dir(root.join("sub"))?;
+    fs::write(root.join("a.bin"), vec![0u8; 5000])?;
+    fs::write(root.join("sub").join("b.bin"), vec![0u8; 3000])?;
+    fs::write(root.join("sub").join("c.bin"), b"c")?;
+
+    let options = ScanOptions {
+      counts_only: true,
+      ..ScanOptions::default()
+    };
+    let cache = scan_into_cache_with(&root, &options)?;
+    let root_entry = &cache.entries[cache.path_map[&root]];
+    assert_eq!(root_entry.file_count, 3);
+    assert_eq!(root_entry.directory_count, 2);
+    assert_eq!(root_entry.entry_count, 5);
+    assert!(cache
+      .entries
+      .iter()
+      .all(|entry| entry.size_bytes == 0 && entry.size_allocated_bytes == 0));
+
+    // Files still know what they are, for the tree's icons and grouping
+    let file = &cache.entries[cache.path_map[&root.join("sub").join("b.bin")]];
+    assert!(is_file_entry(file));
+
+    // A link to a file is an entry of its own, not another file
+    #[cfg(target_family = "unix")]
+    {
+      std::os::unix::fs::symlink(root.join("a.bin"), root.join("link.bin"))?;
+      let cache = scan_into_cache_with(&root, &options)?;
+      let root_entry = &cache.entries[cache.path_map[&root]];
+      assert_eq!(root_entry.file_count, 3);
+      assert_eq!(root_entry.entry_count, 6);
+    }
+    Ok(())
+  }
+
+  #[test]
+  fn test_structure_export_lists_directories_parents_first() -> std::io::Result<()> {
+    let temp_dir = tempdir()?;
+    let root = temp_dir.path().canonicalize()?;
+    fs::create_dir_all(root.join("src").join("bin"))?;
+    fs::create_dir_all(root.join("docs"))?;
+    fs::create_dir_all(root.join("src-old"))?;
+    fs::write(root.join("src").join("main.rs"), b"fn main() {}")?;
+
+    let cache = scan_into_cache(&root)?;
+    let mut output = Vec::new();
+    let count = write_structure_export(&cache, &root, &mut output)?;
+    assert_eq!(count, 4);
+
+    let expected: Vec<String> = [
+      PathBuf::from("docs"),
+      PathBuf::from("src"),
+      Path::new("src").join("bin"),
+      PathBuf::from("src-old"),
+    ]
+    .iter()
+    .map(|path| path.display().to_string())
+    .collect();
+    let lines: Vec<&str> = std::str::from_utf8(&output).unwrap().lines().collect();
+    assert_eq!(lines, expected);
+
+    // Exporting a subdirectory lists paths relative to it
+    let mut output = Vec::new();
+    write_structure_export(&cache, &root.join("src"), &mut output)?;
+    assert_eq!(std::str::from_utf8(&output).unwrap().trim(), "bin");
+    Ok(())
+  }
+
+  #[test]
+  fn test_parent_context_prefers_cached_parent() {
+    let cache = test_cache(
+      "/home",
+      vec![
+        test_entry("/home", 1000, None),
+        test_entry("/home/me", 800, None),
+        test_entry("/home/me/project", 100, None),
+      ],
     );
 
-    Ok(())
+    let context = find_parent_context(Path::new("/home/me/project"), Some(&cache)).unwrap();
+    assert_eq!(context.parent_path, PathBuf::from("/home/me"));
+    assert_eq!(context.parent_total_bytes, 800);
+    assert!(!context.is_volume_total);
+
+    // Without a cached parent, the volume's used space stands in for it
+    let temp_dir = tempdir().unwrap();
+    let root = temp_dir.path().join("project");
+    let context = find_parent_context(&root, Some(&cache)).unwrap();
+    assert_eq!(context.parent_path, temp_dir.path());
+    assert!(context.is_volume_total);
+
+    assert!(find_parent_context(Path::new("/"), Some(&cache)).is_none());
   }
 
   #[tokio::test]
-  #[cfg(not(target_os = "windows"))]
-  async fn test_calculate_size_with_symlink() -> std::io::Result<()> {
-    // Create a temporary directory with a symlink
+  async fn test_failed_scan_invalidates_cache() -> std::io::Result<()> {
+    let _guard = GLOBAL_CACHE_TEST_LOCK.lock().await;
     let temp_dir = tempdir()?;
-    let path = temp_dir.path().to_path_buf();
-
-    // Create a file with known content
-    let file_path = path.join("test_file.txt");
-    let mut file = File::create(&file_path)?;
-    let test_data = "Hello, world!";
-    file.write_all(test_data.as_bytes())?;
+    let root = temp_dir.path().canonicalize()?;
+    let sub = root.join("sub");
+    fs::create_dir(&sub)?;
+    fs::write(sub.join("a.bin"), vec![0u8; 100])?;
+
+    let generation = start_cache_generation(true).unwrap();
+    assert!(store_scan_cache(scan_into_cache(&root)?, generation));
+
+    // A merge rescan of `sub` fails midway, as scan_directory_size sees it: `sub` must not
+    // keep serving the old data
+    let generation = start_cache_generation(false).unwrap();
+    discard_failed_scan(Some(&sub), true, generation);
+    let cache = indexed_cache_snapshot().unwrap().unwrap();
+    assert!(!cache.path_map.contains_key(&sub));
+    assert!(!cache.path_map.contains_key(&sub.join("a.bin")));
+    assert!(cache.path_map.contains_key(&root));
+
+    // Cleanup for a scan that was superseded leaves the newer cache alone
+    let stale_generation = generation;
+    let generation = start_cache_generation(true).unwrap();
+    assert!(store_scan_cache(scan_into_cache(&root)?, generation));
+    discard_failed_scan(Some(&root), false, stale_generation);
+    assert!(cache_snapshot().unwrap().is_some());
+
+    // A failed fresh scan leaves nothing cached
+    discard_failed_scan(Some(&root), false, generation);
+    assert!(cache_snapshot().unwrap().is_none());
+
+    reset_scan_cache().unwrap();
+    Ok(())
+  }
 
-    // Create a symlink to the file
-    let symlink_path = path.join("test_symlink");
-    std::os::unix::fs::symlink(&file_path, &symlink_path)?;
+  #[test]
+  fn test_child_mount_points_compare_devices() {
+    let dir_info = |device: u64| {
+      let mut info = platform::get_path_info(std::env::temp_dir(), false).unwrap();
+      info.inode_device = Some((1, device));
+      Some(info)
+    };
+    let with_device = |path: &str, device: u64| {
+      let mut entry = (*test_entry(path, 0, None)).clone();
+      entry.path_info = dir_info(device);
+      Arc::new(entry)
+    };
+    let cache = test_cache(
+      "/home",
+      vec![
+        with_device("/home", 1),
+        with_device("/home/docs", 1),
+        with_device("/home/usb", 7),
+        test_entry("/home/file.txt", 10, None),
+      ],
+    );
 
-    // Create analytics map and visited inodes
-    let analytics_map = Arc::new(DashMap::new());
-    let visited_inodes = Arc::new(DashSet::new());
-    let processed_paths = Arc::new(DashSet::new());
+    let mounts = find_child_mount_points(&cache, Path::new("/home"));
+    assert_eq!(mounts.len(), 1);
+    assert_eq!(mounts[0].path, PathBuf::from("/home/usb"));
+    assert_eq!(mounts[0].device, 7);
 
-    // Run the function
-    calculate_size_sync(
-      path.as_path(),
-      analytics_map.clone(),
-      path.as_path(),
-      visited_inodes,
-      processed_paths,
-    )?;
+    // Nothing to compare against without the parent's device
+    assert!(find_child_mount_points(&cache, Path::new("/home/file.txt")).is_empty());
+  }
 
-    // Verify the results
-    assert!(
-      analytics_map.contains_key(&path),
-      "Path should be in the analytics map"
-    );
+  #[test]
+  fn test_duration_micros_saturates() {
+    assert_eq!(duration_micros(Duration::from_nanos(1_500)), 1);
+    assert_eq!(duration_micros(Duration::from_millis(3)), 3_000);
+    assert_eq!(duration_micros(Duration::MAX), u64::MAX);
+  }
 
-    let analytics = analytics_map.get(&path).unwrap();
-    // The symlink is counted in the entry count, but not as a file
-    // In some implementations, symlinks might be treated differently
-    assert!(
-      analytics.entry_count >= 2,
-      "Should count at least the directory and file"
-    );
+  #[test]
+  fn test_validate_scan_target() -> std::io::Result<()> {
+    let temp_dir = tempdir()?;
+    let root = temp_dir.path().canonicalize()?;
+    fs::create_dir(root.join("sub"))?;
+    fs::write(root.join("sub").join("deep.txt"), b"not counted")?;
+    fs::write(root.join("a.txt"), b"a")?;
+    fs::write(root.join("b.txt"), b"b")?;
+
+    let validation = validate_target(&root.to_string_lossy());
+    assert!(validation.exists && validation.is_dir && validation.is_readable);
     assert_eq!(
-      analytics.file_count, 1,
-      "Should count only the actual file, not the symlink as a file"
+      validation.estimated_entry_count,
+      Some(1 + 2 + ESTIMATED_ENTRIES_PER_SUBDIRECTORY)
     );
 
-    // Let's also check that the symlink exists
-    assert!(symlink_path.exists(), "Symlink should exist");
+    let file = validate_target(&root.join("a.txt").to_string_lossy());
+    assert!(file.exists && !file.is_dir && file.is_readable);
+    assert_eq!(file.estimated_entry_count, Some(1));
 
+    let missing = validate_target(&root.join("missing").to_string_lossy());
+    assert!(!missing.exists && !missing.is_readable);
+    assert_eq!(missing.estimated_entry_count, None);
     Ok(())
   }
 
-  // A manual benchmark that can be run with cargo test -- --ignored
-  #[tokio::test]
-  #[ignore]
-  async fn benchmark_rayon_parallel_vs_sequential() -> std::io::Result<()> {
-    // Choose a directory to scan - use home directory for a more realistic test
-    let test_dir = dirs::home_dir()
-      .unwrap_or_else(|| std::env::current_dir().unwrap())
-      .to_path_buf();
-
-    println!("Benchmarking directory: {:?}", test_dir);
+  #[test]
+  fn test_virtual_files_grouped_by_kind() -> std::io::Result<()> {
+    let temp_dir = tempdir()?;
+    let root = temp_dir.path().canonicalize()?;
+    fs::create_dir(root.join("sub"))?;
+    fs::write(root.join("photo.jpg"), vec![0u8; 300])?;
+    fs::write(root.join("a.txt"), vec![0u8; 100])?;
+    fs::write(root.join("b.md"), vec![0u8; 100])?;
+
+    let cache = scan_into_cache(&root)?;
+    let mut tree = build_tree_from_indices(
+      &cache.entries,
+      &cache.path_map,
+      &cache.children_map,
+      &root,
+      1,
+      true,
+    )
+    .unwrap();
+    group_virtual_files_by_kind(&mut tree);
 
-    // First measure with parallelism
-    let start = std::time::Instant::now();
-    {
-      let analytics_map = Arc::new(DashMap::new());
-      let visited_inodes = Arc::new(DashSet::new());
-      let processed_paths = Arc::new(DashSet::new());
+    let files_node = tree
+      .children
+      .iter()
+      .find(|child| child.is_virtual_directory)
+      .unwrap();
+    assert_eq!(files_node.size_bytes, 500);
+    let categories: Vec<(&str, u64, u64)> = files_node
+      .children
+      .iter()
+      .map(|category| {
+        (
+          category.name.as_str(),
+          category.size_bytes,
+          category.file_count,
+        )
+      })
+      .collect();
+    assert_eq!(
+      categories,
+      vec![("[Images]", 300, 1), ("[Documents]", 200, 2)]
+    );
+    assert_eq!(files_node.children[0].percent_of_parent, 60.0);
+    assert_eq!(files_node.children[1].children[0].percent_of_parent, 50.0);
 
-      // Use Rayon's default thread pool (parallel)
-      calculate_size_sync(
-        test_dir.as_path(),
-        analytics_map.clone(),
-        test_dir.as_path(),
-        visited_inodes,
-        processed_paths,
-      )?;
+    // Real directories are left alone
+    assert!(tree
+      .children
+      .iter()
+      .any(|child| child.name == "sub" && !child.is_virtual_directory));
+    Ok(())
+  }
 
-      println!("Parallel scan found {} entries", analytics_map.len());
+  #[tokio::test]
+  async fn test_concurrent_scans_keep_only_the_latest() -> std::io::Result<()> {
+    let _guard = GLOBAL_CACHE_TEST_LOCK.lock().await;
+    let first_dir = tempdir()?;
+    let second_dir = tempdir()?;
+    for (dir, count) in [(&first_dir, 200), (&second_dir, 3)] {
+      for i in 0..count {
+        fs::write(dir.path().join(format!("{}.bin", i)), b"data")?;
+      }
     }
-    let parallel_duration = start.elapsed();
-    println!("Parallel processing took: {:?}", parallel_duration);
 
-    // Then measure with single thread
-    let start = std::time::Instant::now();
-    {
-      let analytics_map = Arc::new(DashMap::new());
-      let visited_inodes = Arc::new(DashSet::new());
-      let processed_paths = Arc::new(DashSet::new());
+    // Started the way scan_directory_complete starts a scan, and finished by the same code
+    async fn scan(root: PathBuf) -> (u64, Arc<ScanState>, bool) {
+      let generation = start_cache_generation(true).unwrap();
+      let state = Arc::new(ScanState::default());
+      begin_current_scan(root.clone(), state.clone());
+      let scan_state = state.clone();
+      let stored = tokio::task::spawn_blocking(move || {
+        calculate_size_sync(&root, &scan_state, &ScanOptions::default(), None).unwrap();
+        finish_scan(&root, &scan_state, generation, false, 0).is_ok_and(|finished| finished.stored)
+      })
+      .await
+      .unwrap();
+      (generation, state, stored)
+    }
 
-      // Create a single-threaded pool to simulate sequential processing
-      let pool = rayon::ThreadPoolBuilder::new()
-        .num_threads(1)
-        .build()
-        .unwrap();
+    let first_root = first_dir.path().canonicalize()?;
+    let second_root = second_dir.path().canonicalize()?;
+    let (first, second) = tokio::join!(
+      tokio::spawn(scan(first_root.clone())),
+      tokio::spawn(scan(second_root.clone()))
+    );
+    let (first, second) = (first.unwrap(), second.unwrap());
 
-      pool.install(|| {
-        let _ = calculate_size_sync(
-          test_dir.as_path(),
-          analytics_map.clone(),
-          test_dir.as_path(),
-          visited_inodes,
-          processed_paths,
-        );
-      });
+    // Whichever started last owns the cache, the other was cancelled and stored nothing
+    let (latest, replaced, latest_root) = if first.0 > second.0 {
+      (first, second, first_root)
+    } else {
+      (second, first, second_root)
+    };
+    assert!(latest.2);
+    assert!(!replaced.2);
+    assert!(replaced.1.cancelled.load(Ordering::Relaxed));
+    assert!(!latest.1.cancelled.load(Ordering::Relaxed));
+    let cache = cache_snapshot().unwrap().unwrap();
+    assert_eq!(cache.root_path, latest_root);
+    assert_eq!(cache.generation, latest.0);
+
+    reset_scan_cache().unwrap();
+    Ok(())
+  }
 
-      println!("Sequential scan found {} entries", analytics_map.len());
+  #[test]
+  fn test_normalize_separators() {
+    if cfg!(windows) {
+      assert_eq!(normalize_separators("C:/Users/me"), "C:\\Users\\me");
+    } else {
+      assert_eq!(normalize_separators("/home/me"), "/home/me");
     }
-    let sequential_duration = start.elapsed();
-    println!("Sequential processing took: {:?}", sequential_duration);
-
-    // Calculate and print the speedup
-    let speedup = sequential_duration.as_secs_f64() / parallel_duration.as_secs_f64();
-    println!("Speedup from parallelism: {:.2}x", speedup);
+    assert_eq!(normalize_separators("plain"), "plain");
+  }
 
+  #[test]
+  #[cfg(windows)]
+  fn test_forward_slash_paths_resolve_against_cache() -> std::io::Result<()> {
+    let temp_dir = tempdir()?;
+    let root = temp_dir.path().canonicalize()?;
+    fs::create_dir(root.join("sub"))?;
+    fs::write(root.join("sub").join("file.txt"), b"data")?;
+    let cache = scan_into_cache(&root)?;
+
+    let forward = root.join("sub").to_string_lossy().replace('\\', "/");
+    let resolved = resolve_cached_path(&cache, &forward).unwrap();
+    assert_eq!(resolved, root.join("sub"));
+    assert!(cache.path_map.contains_key(&resolved));
     Ok(())
   }
 
-  #[tokio::test]
-  #[cfg(target_family = "unix")]
-  async fn test_owner_name_unix() -> std::io::Result<()> {
-    // Create a temporary directory with a file
+  #[test]
+  fn test_max_child_depth() -> std::io::Result<()> {
     let temp_dir = tempdir()?;
-    let path = temp_dir.path().to_path_buf();
-    let file_path = path.join("test_owner.txt");
-    let mut file = File::create(&file_path)?;
-    file.write_all(b"Testing owner")?;
+    let root = temp_dir.path().canonicalize()?;
+    fs::create_dir_all(root.join("deep/a/b/c"))?;
+    fs::write(root.join("deep/a/b/c/file.txt"), b"data")?;
+    fs::create_dir(root.join("shallow"))?;
+    fs::write(root.join("shallow/file.txt"), b"data")?;
+    fs::create_dir(root.join("empty"))?;
+
+    let mut cache = scan_into_cache(&root)?;
+    let depth_of =
+      |cache: &ScanCache, path: &Path| cache.entries[cache.path_map[path]].max_child_depth;
+    assert_eq!(depth_of(&cache, &root), 5);
+    assert_eq!(depth_of(&cache, &root.join("deep")), 4);
+    assert_eq!(depth_of(&cache, &root.join("shallow")), 1);
+    assert_eq!(depth_of(&cache, &root.join("empty")), 0);
+    assert_eq!(depth_of(&cache, &root.join("shallow/file.txt")), 0);
+
+    let tree = build_tree_from_indices(
+      &cache.entries,
+      &cache.path_map,
+      &cache.children_map,
+      &root,
+      1,
+      false,
+    )
+    .unwrap();
+    assert_eq!(tree.max_child_depth, 5);
+    let deep = tree.children.iter().find(|c| c.name == "deep").unwrap();
+    assert_eq!(deep.max_child_depth, 4);
+
+    // Removing the deepest branch makes the ancestors shallower
+    assert!(remove_path_from_cache(&mut cache, &root.join("deep/a/b")));
+    assert_eq!(depth_of(&cache, &root.join("deep")), 1);
+    assert_eq!(depth_of(&cache, &root), 2);
+    Ok(())
+  }
 
-    // Get path info directly
-    let path_info = platform::get_path_info(&file_path, false);
-    assert!(path_info.is_some(), "Should get path info");
+  #[test]
+  fn test_size_trend() -> std::io::Result<()> {
+    let temp_dir = tempdir()?;
+    let root = temp_dir.path().canonicalize()?;
+    assert!(size_trend(&root).unwrap().is_empty());
 
-    let info = path_info.unwrap();
-    assert!(info.owner_name.is_some(), "Should have owner name on Unix");
+    let at = |secs: u64| UNIX_EPOCH + Duration::from_secs(secs);
+    record_scan_totals(&root, at(200), 2000, 20);
+    record_scan_totals(&root, at(100), 1000, 10);
+    record_scan_totals(&root, at(300), 3000, 30);
+    assert_eq!(
+      size_trend(&root).unwrap(),
+      vec![(100, 1000, 10), (200, 2000, 20), (300, 3000, 30)]
+    );
 
-    // Current user should be the owner of the file we just created
-    let current_user = std::env::var("USER").or_else(|_| std::env::var("LOGNAME"));
-    if let Ok(username) = current_user {
-      assert_eq!(
-        info.owner_name.as_deref(),
-        Some(username.as_str()),
-        "File should be owned by current user"
-      );
+    // Only the latest scans are kept
+    for i in 0..MAX_SCAN_HISTORY as u64 {
+      record_scan_totals(&root, at(1000 + i), i, 1);
     }
+    let trend = size_trend(&root).unwrap();
+    assert_eq!(trend.len(), MAX_SCAN_HISTORY);
+    assert_eq!(trend[0], (1000, 0, 1));
+
+    // Completed scans are recorded as they finish
+    let other = tempdir()?;
+    let other_root = other.path().canonicalize()?;
+    fs::write(other_root.join("file.txt"), b"data")?;
+    let state = Arc::new(ScanState::default());
+    calculate_size_sync(&other_root, &state, &ScanOptions::default(), None)?;
+    let root_entry = state.analytics_map.get(&other_root).unwrap().clone();
+    record_scan_totals(
+      &other_root,
+      SystemTime::now(),
+      root_entry.size_bytes,
+      root_entry.file_count,
+    );
+    let trend = size_trend(&other_root).unwrap();
+    assert_eq!(trend.len(), 1);
+    assert_eq!(trend[0].2, 1);
+    Ok(())
+  }
 
-    // Test through the analytics map
-    let analytics_map = Arc::new(DashMap::new());
-    let visited_inodes = Arc::new(DashSet::new());
-    let processed_paths = Arc::new(DashSet::new());
-
-    calculate_size_sync(
-      path.as_path(),
-      analytics_map.clone(),
-      path.as_path(),
-      visited_inodes,
-      processed_paths,
-    )?;
+  #[test]
+  fn test_skipped_children() -> std::io::Result<()> {
+    let temp_dir = tempdir()?;
+    let path = temp_dir.path().canonicalize()?;
+    fs::write(path.join("a.txt"), b"data")?;
+    fs::write(path.join("b.txt"), b"data")?;
+    fs::create_dir(path.join("node_modules"))?;
+
+    // Excluded names are left out on purpose, not skipped
+    let options = ScanOptions {
+      exclude_names: vec!["node_modules".to_string()],
+      ..ScanOptions::default()
+    };
+    let state = ScanState::default();
+    calculate_size_sync(&path, &state, &options, None)?;
+    assert_eq!(state.analytics_map.get(&path).unwrap().skipped_children, 0);
+
+    // A scan cancelled before it reached the children has none of them in its totals
+    let state = ScanState::default();
+    state.cancelled.store(true, Ordering::Relaxed);
+    calculate_size_sync(&path, &state, &options, None)?;
+    let root = state.analytics_map.get(&path).unwrap();
+    assert_eq!(root.skipped_children, 2);
+    assert_eq!(root.file_count, 0);
+    Ok(())
+  }
 
-    // Convert to entries and check owner_name is preserved
-    let entries = analytics_map_to_entries(&analytics_map);
-    let file_entry = entries.iter().find(|e| e.path == file_path);
+  #[test]
+  fn test_percentages_have_two_decimals() {
+    let cache = test_cache(
+      "/data",
+      vec![
+        test_entry("/data", 300, None),
+        test_entry("/data/a", 100, None),
+        test_entry("/data/b", 100, None),
+        test_entry("/data/c", 100, None),
+        test_entry("/data/sub", 0, None),
+        test_entry("/data/sub/x", 2, None),
+        test_entry("/data/sub/y", 1, None),
+      ],
+    );
+    let tree = build_tree_from_indices(
+      &cache.entries,
+      &cache.path_map,
+      &cache.children_map,
+      Path::new("/data"),
+      2,
+      false,
+    )
+    .unwrap();
+    assert_eq!(tree.children[0].percent_of_parent, 33.33);
+
+    // Every percentage in the payload has at most two decimals
+    let json = serde_json::to_value(&tree).unwrap();
+    let mut nodes = vec![&json];
+    let mut checked = 0;
+    while let Some(node) = nodes.pop() {
+      for key in ["percent_of_parent", "percent_of_parent_allocated"] {
+        let text = node[key].to_string();
+        let decimals = text
+          .split_once('.')
+          .map_or(0, |(_, fraction)| fraction.len());
+        assert!(decimals <= 2, "{} is {}", key, text);
+        checked += 1;
+      }
+      nodes.extend(node["children"].as_array().unwrap());
+    }
+    assert!(checked > 2);
+    assert_eq!(percent_of(1, 3), 33.33);
+    assert_eq!(percent_of(2, 3), 66.67);
+  }
 
-    assert!(file_entry.is_some(), "File entry should exist");
-    if let Some(entry) = file_entry {
-      assert!(
-        entry.owner_name.is_some(),
-        "Owner name should be present in entry"
-      );
+  #[test]
+  fn test_list_removable_drives() {
+    // Which drives are present depends on the machine, but every one listed is readable
+    for drive in platform::list_removable_drives() {
+      assert!(fs::read_dir(&drive.mount_point).is_ok());
+      assert!(drive.available_bytes <= drive.total_bytes);
     }
+  }
+
+  #[test]
+  #[cfg(target_os = "linux")]
+  fn test_decode_udev_label() {
+    assert_eq!(platform::decode_udev_label("MY\\x20STICK"), "MY STICK");
+    assert_eq!(platform::decode_udev_label("a\\x2fb\\x"), "a/b\\x");
+    assert_eq!(platform::decode_udev_label("plain"), "plain");
+  }
+
+  #[test]
+  fn test_hash_largest_files() -> std::io::Result<()> {
+    let temp_dir = tempdir()?;
+    let path = temp_dir.path().canonicalize()?;
+    fs::create_dir(path.join("sub"))?;
+    fs::write(path.join("sub/big.bin"), vec![7u8; 5000])?;
+    fs::write(path.join("medium.bin"), vec![1u8; 3000])?;
+    fs::write(path.join("small.txt"), b"hi")?;
+
+    let hash_of = |state: &ScanState, name: &str| {
+      state
+        .analytics_map
+        .get(&path.join(name))
+        .unwrap()
+        .content_hash
+        .clone()
+    };
 
+    // Off by default
+    let state = ScanState::default();
+    calculate_size_sync(&path, &state, &ScanOptions::default(), None)?;
+    assert!(state
+      .analytics_map
+      .iter()
+      .all(|entry| entry.content_hash.is_none()));
+
+    let options = ScanOptions {
+      hash_largest: Some(2),
+      ..ScanOptions::default()
+    };
+    let state = ScanState::default();
+    calculate_size_sync(&path, &state, &options, None)?;
+    assert_eq!(
+      hash_of(&state, "sub/big.bin"),
+      Some(blake3::hash(&[7u8; 5000]).to_hex().to_string())
+    );
+    assert_eq!(
+      hash_of(&state, "medium.bin"),
+      Some(blake3::hash(&[1u8; 3000]).to_hex().to_string())
+    );
+    assert_eq!(hash_of(&state, "small.txt"), None);
+    assert_eq!(hash_of(&state, "sub"), None);
     Ok(())
   }
 
-  #[tokio::test]
-  #[cfg(target_os = "windows")]
-  async fn test_owner_name_windows() -> std::io::Result<()> {
-    use std::fs::metadata;
+  #[test]
+  fn test_find_junk_locations() {
+    let dir = |path: &str, size_bytes: u64| {
+      Arc::new(AnalyticsInfo {
+        directory_count: 1,
+        ..(*test_entry(path, size_bytes, None)).clone()
+      })
+    };
+    let cache = test_cache(
+      "/home",
+      vec![
+        dir("/home", 0),
+        dir("/home/app", 0),
+        test_entry("/home/app/Cargo.toml", 1, None),
+        dir("/home/app/target", 900),
+        dir("/home/app/node_modules", 500),
+        dir("/home/app/node_modules/pkg", 0),
+        dir("/home/app/node_modules/pkg/node_modules", 200),
+        dir("/home/site", 0),
+        dir("/home/site/target", 300),
+        dir("/home/.Cache", 700),
+        test_entry("/home/tmp", 50, None),
+      ],
+    );
 
-    // Create a temporary directory with a file
-    let temp_dir = tempdir()?;
-    let path = temp_dir.path().to_path_buf();
-    let file_path = path.join("test_owner.txt");
-    let mut file = File::create(&file_path)?;
-    file.write_all(b"Testing owner")?;
+    let junk = find_junk_locations(&cache, Path::new("/home"));
+    let found: Vec<(&str, &str, u64)> = junk
+      .iter()
+      .map(|j| (j.path.to_str().unwrap(), j.category, j.size_bytes))
+      .collect();
+    // Nested node_modules, a target without a Cargo.toml and files named like junk are
+    // left out
+    assert_eq!(
+      found,
+      vec![
+        ("/home/app/target", "build-artifacts", 900),
+        ("/home/.Cache", "cache", 700),
+        ("/home/app/node_modules", "dependencies", 500),
+      ]
+    );
+    // Searching inside a junk directory finds what is nested in it
+    let nested = find_junk_locations(&cache, Path::new("/home/app/node_modules"));
+    assert_eq!(
+      nested[0].path,
+      PathBuf::from("/home/app/node_modules/pkg/node_modules")
+    );
+  }
 
-    // Get metadata directly first to verify file creation
-    let file_metadata = metadata(&file_path)?;
-    assert!(file_metadata.is_file(), "Should be a file");
+  #[test]
+  fn test_round_up_to_cluster() {
+    assert_eq!(round_up_to_cluster(0, 4096), 0);
+    assert_eq!(round_up_to_cluster(1, 4096), 4096);
+    assert_eq!(round_up_to_cluster(4096, 4096), 4096);
+    assert_eq!(round_up_to_cluster(5000, 4096), 8192);
+    assert_eq!(
+      round_up_to_cluster(5000, 0),
+      5000,
+      "Unknown cluster size is a no-op"
+    );
+    assert_eq!(
+      round_up_to_cluster(u64::MAX, 4096),
+      u64::MAX,
+      "Should saturate"
+    );
+  }
 
-    // Get path info directly with debug output
-    println!("Attempting to get path info for: {:?}", file_path);
-    let path_info = platform::get_path_info(&file_path, false);
+  #[test]
+  fn test_fat_volumes_account_for_cluster_slack() {
+    // A 1-byte file on an exFAT drive with 128 KiB clusters still occupies a whole cluster
+    let exfat = VolumeInfo {
+      cluster_size: Some(128 * 1024),
+      is_fat: true,
+      capacity: None,
+    };
+    assert_eq!(
+      adjusted_allocated_size(1, &exfat, RoundingMode::None),
+      128 * 1024
+    );
+    assert_eq!(
+      adjusted_allocated_size(200 * 1024, &exfat, RoundingMode::None),
+      256 * 1024
+    );
 
-    match &path_info {
-      Some(info) => {
-        println!("Got path info with owner: {:?}", info.owner_name);
-        assert!(
-          info.owner_name.is_some(),
-          "Should have owner name on Windows"
-        );
-      }
-      None => {
-        panic!("Failed to get path info for file");
-      }
-    }
+    // Other volumes keep the reported size unless rounding was asked for
+    let ntfs = VolumeInfo {
+      cluster_size: Some(4096),
+      is_fat: false,
+      capacity: None,
+    };
+    assert_eq!(adjusted_allocated_size(1, &ntfs, RoundingMode::None), 1);
+    assert_eq!(
+      adjusted_allocated_size(1, &ntfs, RoundingMode::ClusterUp),
+      4096
+    );
 
-    // Test through the analytics map
-    let analytics_map = Arc::new(DashMap::new());
-    let visited_inodes = Arc::new(DashSet::new());
-    let processed_paths = Arc::new(DashSet::new());
+    assert!(platform::is_fat_filesystem("exFAT"));
+    assert!(platform::is_fat_filesystem("FAT32"));
+    assert!(!platform::is_fat_filesystem("NTFS"));
+  }
 
-    calculate_size_sync(
-      path.as_path(),
-      analytics_map.clone(),
-      path.as_path(),
-      visited_inodes,
-      processed_paths,
-    )?;
+  #[tokio::test]
+  async fn test_rounding_modes() -> std::io::Result<()> {
+    let temp_dir = tempdir()?;
+    let path = temp_dir.path().to_path_buf();
+    let file_path = path.join("small.txt");
+    fs::write(&file_path, b"x")?;
+
+    let scan_file_allocated = |rounding: RoundingMode| -> std::io::Result<u64> {
+      let state = ScanState::default();
+      let options = ScanOptions {
+        rounding,
+        ..Default::default()
+      };
+      calculate_size_sync(path.as_path(), &state, &options, None)?;
+      let allocated = state
+        .analytics_map
+        .get(&file_path)
+        .unwrap()
+        .size_allocated_bytes;
+      Ok(allocated)
+    };
 
-    // Convert to entries and check owner_name is preserved
-    let entries = analytics_map_to_entries(&analytics_map);
-    let file_entry = entries.iter().find(|e| e.path == file_path);
+    // RoundingMode::None reports exactly what the OS reports
+    let reported = platform::get_path_info(&file_path, false)
+      .unwrap()
+      .size_allocated_bytes;
+    assert_eq!(scan_file_allocated(RoundingMode::None)?, reported);
 
-    match file_entry {
-      Some(entry) => {
-        println!("Found file entry with owner: {:?}", entry.owner_name);
-        assert!(
-          entry.owner_name.is_some(),
-          "Owner name should be present in entry"
-        );
-        assert!(
-          !entry.owner_name.as_ref().unwrap().is_empty(),
-          "Owner name should not be empty"
-        );
-      }
-      None => {
-        panic!("File entry not found in analytics map");
-      }
-    }
+    // RoundingMode::ClusterUp reports whole clusters
+    let cluster_size = platform::get_cluster_size(&file_path).expect("Should get cluster size");
+    let rounded = scan_file_allocated(RoundingMode::ClusterUp)?;
+    assert_eq!(
+      rounded % cluster_size,
+      0,
+      "Should be a whole number of clusters"
+    );
+    assert!(
+      rounded >= reported,
+      "Rounding should never shrink the allocation"
+    );
 
     Ok(())
   }
@@ -15,6 +15,30 @@ fn get_block_size() -> u64 {
 type InodeAndDevice = (u64, u64);
 type FileTime = (i64, i64, i64);
 
+/// What kind of filesystem object a path is
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+// Devices, FIFOs and sockets are only detected on Unix
+#[cfg_attr(not(target_family = "unix"), allow(dead_code))]
+pub enum FileKind {
+  Regular,
+  Dir,
+  Symlink,
+  BlockDevice,
+  CharDevice,
+  Fifo,
+  Socket,
+}
+
+impl FileKind {
+  /// Whether this is a device, FIFO or socket rather than a file, directory or symlink
+  pub fn is_special(self) -> bool {
+    matches!(
+      self,
+      FileKind::BlockDevice | FileKind::CharDevice | FileKind::Fifo | FileKind::Socket
+    )
+  }
+}
+
 /// Represents complete information about a filesystem path
 #[derive(Debug, Clone, Serialize)]
 pub struct PathInfo {
@@ -35,29 +59,71 @@ pub struct PathInfo {
   pub is_symlink: bool,
   // The owner of the path
   pub owner_name: Option<String>,
+  /// What kind of object the path is
+  pub kind: FileKind,
+  /// Whether the path is on a pseudo-filesystem like procfs or sysfs (Linux only)
+  /// Its sizes are reported as 0, since they don't take up any disk
+  pub is_pseudo_fs: bool,
 }
 
 /// Get complete path information in a platform-agnostic way
 pub fn get_path_info<P: AsRef<Path>>(path: P, follow_links: bool) -> Option<PathInfo> {
-  let path_ref = path.as_ref();
-
-  // First get the metadata
-  let (size_bytes, size_allocated_bytes, inode_device, times) =
-    get_metadata(path_ref, follow_links)?;
+  get_path_info_with_allocation(path, follow_links, true)
+}
 
-  // Then determine if it's a directory
+/// Same as `get_path_info`, but when `compute_allocated` is false the allocated size is
+/// just the apparent size, skipping the extra work measuring it takes on Windows
+pub fn get_path_info_with_allocation<P: AsRef<Path>>(
+  path: P,
+  follow_links: bool,
+  compute_allocated: bool,
+) -> Option<PathInfo> {
+  let path_ref = path.as_ref();
   let metadata = if follow_links {
     fs::metadata(path_ref).ok()?
   } else {
     fs::symlink_metadata(path_ref).ok()?
   };
+  get_path_info_from_metadata(path_ref, &metadata, compute_allocated)
+}
+
+/// Path information from the file type alone, as `read_dir` returns it without a stat, for
+/// scans that only count entries. Sizes and times are 0, and there's no owner or file ID
+pub fn get_path_info_from_file_type(file_type: &fs::FileType) -> PathInfo {
+  PathInfo {
+    size_bytes: 0,
+    size_allocated_bytes: 0,
+    inode_device: None,
+    times: (0, 0, 0),
+    is_dir: file_type.is_dir(),
+    is_file: file_type.is_file(),
+    is_symlink: file_type.is_symlink(),
+    owner_name: None,
+    kind: get_file_kind(file_type),
+    is_pseudo_fs: false,
+  }
+}
+
+/// Same as `get_path_info_with_allocation`, for metadata the caller already has, such as
+/// the metadata `read_dir` hands out for free on Windows
+pub fn get_path_info_from_metadata(
+  path_ref: &Path,
+  metadata: &fs::Metadata,
+  compute_allocated: bool,
+) -> Option<PathInfo> {
+  let (size_bytes, size_allocated_bytes, inode_device, times) =
+    get_metadata_sizes(path_ref, metadata, compute_allocated)?;
 
   let is_dir = metadata.is_dir();
   let is_file = metadata.is_file();
   let is_symlink = metadata.file_type().is_symlink();
+  let kind = get_file_kind(&metadata.file_type());
 
   // Get the owner name
-  let owner_name = get_owner_name(path_ref, &metadata);
+  let owner_name = get_owner_name(path_ref, metadata);
+
+  let is_pseudo_fs =
+    !is_symlink && inode_device.is_some_and(|(_, device)| is_pseudo_filesystem(path_ref, device));
 
   Some(PathInfo {
     size_bytes,
@@ -68,36 +134,172 @@ pub fn get_path_info<P: AsRef<Path>>(path: P, follow_links: bool) -> Option<Path
     is_file,
     is_symlink,
     owner_name,
+    kind,
+    is_pseudo_fs,
   })
 }
 
+// Inode flags from linux/fs.h, FS_IOC_GETFLAGS is in libc but these aren't
+#[cfg(target_os = "linux")]
+const FS_IMMUTABLE_FL: libc::c_int = 0x10;
+#[cfg(target_os = "linux")]
+const FS_APPEND_FL: libc::c_int = 0x20;
+
+/// Reads the immutable and append-only inode flags of a file or directory, not following
+/// symlinks. Returns (false, false) when they can't be read, e.g. without read permission or
+/// on a filesystem that doesn't support them
+/// Costs an open per path, so it is only called on demand rather than during scans
+#[cfg(target_os = "linux")]
+pub fn get_inode_flags(path: &Path) -> (bool, bool) {
+  use std::os::unix::fs::OpenOptionsExt;
+  use std::os::unix::io::AsRawFd;
+
+  let Ok(file) = fs::OpenOptions::new()
+    .read(true)
+    .custom_flags(libc::O_NONBLOCK | libc::O_NOFOLLOW)
+    .open(path)
+  else {
+    return (false, false);
+  };
+
+  // The kernel writes an int, whatever the ioctl's name suggests
+  let mut flags: libc::c_int = 0;
+  let result = unsafe { libc::ioctl(file.as_raw_fd(), libc::FS_IOC_GETFLAGS, &mut flags) };
+  if result != 0 {
+    return (false, false);
+  }
+  (flags & FS_IMMUTABLE_FL != 0, flags & FS_APPEND_FL != 0)
+}
+
+// Inode flags are only read on Linux
+#[cfg(not(target_os = "linux"))]
+pub fn get_inode_flags(_path: &Path) -> (bool, bool) {
+  (false, false)
+}
+
+// Filesystems whose files are generated by the kernel rather than stored anywhere
+#[cfg(target_os = "linux")]
+const PSEUDO_FILESYSTEM_MAGICS: [libc::c_long; 10] = [
+  libc::PROC_SUPER_MAGIC,
+  libc::SYSFS_MAGIC,
+  libc::DEBUGFS_MAGIC,
+  libc::TRACEFS_MAGIC,
+  libc::SECURITYFS_MAGIC,
+  libc::CGROUP_SUPER_MAGIC,
+  libc::CGROUP2_SUPER_MAGIC,
+  libc::DEVPTS_SUPER_MAGIC,
+  libc::BPF_FS_MAGIC,
+  libc::NSFS_MAGIC,
+];
+
+/// Whether `path`, on device `device`, is on a pseudo-filesystem such as procfs or sysfs,
+/// whose files report lengths without taking up any disk. statfs is called once per device
+#[cfg(target_os = "linux")]
+fn is_pseudo_filesystem(path: &Path, device: u64) -> bool {
+  use std::collections::HashMap;
+  use std::ffi::CString;
+  use std::os::unix::ffi::OsStrExt;
+  use std::sync::{OnceLock, RwLock};
+
+  static PSEUDO_DEVICES: OnceLock<RwLock<HashMap<u64, bool>>> = OnceLock::new();
+  let devices = PSEUDO_DEVICES.get_or_init(Default::default);
+  if let Some(is_pseudo) = devices
+    .read()
+    .ok()
+    .and_then(|devices| devices.get(&device).copied())
+  {
+    return is_pseudo;
+  }
+
+  let Ok(c_path) = CString::new(path.as_os_str().as_bytes()) else {
+    return false;
+  };
+  let mut stats: libc::statfs = unsafe { std::mem::zeroed() };
+  if unsafe { libc::statfs(c_path.as_ptr(), &mut stats) } != 0 {
+    return false;
+  }
+  // f_type's and the magics' widths differ between architectures
+  #[allow(clippy::unnecessary_cast)]
+  let is_pseudo = PSEUDO_FILESYSTEM_MAGICS
+    .iter()
+    .any(|&magic| stats.f_type as i64 == magic as i64);
+  if let Ok(mut devices) = devices.write() {
+    devices.insert(device, is_pseudo);
+  }
+  is_pseudo
+}
+
+// Pseudo-filesystems are only detected on Linux
+#[cfg(not(target_os = "linux"))]
+fn is_pseudo_filesystem(_path: &Path, _device: u64) -> bool {
+  false
+}
+
 #[cfg(target_family = "unix")]
-pub fn get_metadata<P: AsRef<Path>>(
-  path: P,
-  follow_links: bool,
+fn get_file_kind(file_type: &fs::FileType) -> FileKind {
+  use std::os::unix::fs::FileTypeExt;
+
+  if file_type.is_dir() {
+    FileKind::Dir
+  } else if file_type.is_symlink() {
+    FileKind::Symlink
+  } else if file_type.is_block_device() {
+    FileKind::BlockDevice
+  } else if file_type.is_char_device() {
+    FileKind::CharDevice
+  } else if file_type.is_fifo() {
+    FileKind::Fifo
+  } else if file_type.is_socket() {
+    FileKind::Socket
+  } else {
+    FileKind::Regular
+  }
+}
+
+#[cfg(not(target_family = "unix"))]
+fn get_file_kind(file_type: &fs::FileType) -> FileKind {
+  if file_type.is_dir() {
+    FileKind::Dir
+  } else if file_type.is_symlink() {
+    FileKind::Symlink
+  } else {
+    FileKind::Regular
+  }
+}
+
+#[cfg(target_family = "unix")]
+fn get_metadata_sizes(
+  path: &Path,
+  md: &fs::Metadata,
+  compute_allocated: bool,
 ) -> Option<(u64, u64, Option<InodeAndDevice>, FileTime)> {
   use std::os::unix::fs::MetadataExt;
-  let metadata = if follow_links {
-    path.as_ref().metadata()
+
+  // Files on procfs, sysfs and the like take up no disk, whatever length they report
+  if !md.file_type().is_symlink() && is_pseudo_filesystem(path, md.dev()) {
+    return Some((
+      0,
+      0,
+      Some((md.ino(), md.dev())),
+      (md.mtime(), md.atime(), md.ctime()),
+    ));
+  }
+
+  // Apparent size
+  let size = md.len();
+  // Allocated size
+  let size_allocated = if compute_allocated {
+    md.blocks() * get_block_size()
   } else {
-    path.as_ref().symlink_metadata()
+    size
   };
-  match metadata {
-    Ok(md) => {
-      // Apparent size
-      let size = md.len();
-      // Allocated size
-      let size_allocated = md.blocks() * get_block_size();
-
-      Some((
-        size,
-        size_allocated,
-        Some((md.ino(), md.dev())),
-        (md.mtime(), md.atime(), md.ctime()),
-      ))
-    }
-    Err(_e) => None,
-  }
+
+  Some((
+    size,
+    size_allocated,
+    Some((md.ino(), md.dev())),
+    (md.mtime(), md.atime(), md.ctime()),
+  ))
 }
 
 #[cfg(target_family = "windows")]
@@ -108,9 +310,10 @@ fn windows_time_to_unix_time(windows_time: i64) -> i64 {
 }
 
 #[cfg(target_family = "windows")]
-pub fn get_metadata<P: AsRef<Path>>(
-  path: P,
-  follow_links: bool,
+fn get_metadata_sizes(
+  path: &Path,
+  md: &fs::Metadata,
+  compute_allocated: bool,
 ) -> Option<(u64, u64, Option<InodeAndDevice>, FileTime)> {
   // On windows opening the file to get size, file ID and volume can be very
   // expensive because 1) it causes a few system calls, and more importantly 2) it can cause
@@ -129,12 +332,10 @@ pub fn get_metadata<P: AsRef<Path>>(
   // contains it.
 
   // File ID:
-  // This optimization does deprive us of access to a file ID. As a
-  // workaround, we just make one up that hopefully does not collide with real
-  // file IDs.
-  // Hard links: Unresolved. We don't get inode/file index, so hard links
-  // count once for each link. Hopefully they are not too commonly in use on
-  // windows.
+  // The free information has no file ID. Files still get theirs from a handle
+  // opened with FILE_READ_ATTRIBUTES alone, which reads no data, so a file
+  // replaced since the scan can be told apart from the one that was scanned.
+  // Directories go without one.
 
   // Size:
   // We assume (naively?) that for the common cases the free size info is the
@@ -175,6 +376,29 @@ pub fn get_metadata<P: AsRef<Path>>(
     Ok(Handle::from_file(file))
   }
 
+  // The file index and volume serial number of a file, from the link itself when `md` is
+  // a link's own metadata
+  fn get_file_id(path: &Path, md: &fs::Metadata) -> Option<InodeAndDevice> {
+    use std::fs::OpenOptions;
+    use std::os::windows::fs::OpenOptionsExt;
+    use winapi_util::file::information;
+    const FILE_READ_ATTRIBUTES: u32 = 0x0080;
+    const FILE_FLAG_OPEN_REPARSE_POINT: u32 = 0x0020_0000;
+
+    let flags = if md.file_type().is_symlink() {
+      FILE_FLAG_OPEN_REPARSE_POINT
+    } else {
+      0
+    };
+    let file = OpenOptions::new()
+      .access_mode(FILE_READ_ATTRIBUTES)
+      .custom_flags(flags)
+      .open(path)
+      .ok()?;
+    let info = information(Handle::from_file(file)).ok()?;
+    Some((info.file_index(), info.volume_serial_number()))
+  }
+
   fn get_metadata_expensive(path: &Path) -> Option<(u64, u64, Option<InodeAndDevice>, FileTime)> {
     use filesize::PathExt;
     use winapi_util::file::information;
@@ -199,64 +423,55 @@ pub fn get_metadata<P: AsRef<Path>>(
   }
 
   use std::os::windows::fs::MetadataExt;
-  let path = path.as_ref();
-  let metadata = if follow_links {
-    path.metadata()
+  const FILE_ATTRIBUTE_ARCHIVE: u32 = 0x20;
+  const FILE_ATTRIBUTE_READONLY: u32 = 0x01;
+  const FILE_ATTRIBUTE_HIDDEN: u32 = 0x02;
+  const FILE_ATTRIBUTE_SYSTEM: u32 = 0x04;
+  const FILE_ATTRIBUTE_NORMAL: u32 = 0x80;
+  const FILE_ATTRIBUTE_DIRECTORY: u32 = 0x10;
+  const FILE_ATTRIBUTE_SPARSE_FILE: u32 = 0x00000200;
+  const FILE_ATTRIBUTE_PINNED: u32 = 0x00080000;
+  const FILE_ATTRIBUTE_UNPINNED: u32 = 0x00100000;
+  const FILE_ATTRIBUTE_RECALL_ON_OPEN: u32 = 0x00040000;
+  const FILE_ATTRIBUTE_RECALL_ON_DATA_ACCESS: u32 = 0x00400000;
+  const FILE_ATTRIBUTE_OFFLINE: u32 = 0x00001000;
+  // normally FILE_ATTRIBUTE_SPARSE_FILE would be enough, however Windows sometimes likes to mask it out. see: https://stackoverflow.com/q/54560454
+  const IS_PROBABLY_ONEDRIVE: u32 = FILE_ATTRIBUTE_SPARSE_FILE
+    | FILE_ATTRIBUTE_PINNED
+    | FILE_ATTRIBUTE_UNPINNED
+    | FILE_ATTRIBUTE_RECALL_ON_OPEN
+    | FILE_ATTRIBUTE_RECALL_ON_DATA_ACCESS
+    | FILE_ATTRIBUTE_OFFLINE;
+  let attr_filtered = md.file_attributes()
+    & !(FILE_ATTRIBUTE_HIDDEN | FILE_ATTRIBUTE_READONLY | FILE_ATTRIBUTE_SYSTEM);
+  // Without allocated sizes, the expensive call has nothing to add that get_file_id doesn't
+  if !compute_allocated
+    || ((attr_filtered & FILE_ATTRIBUTE_ARCHIVE) != 0
+      || (attr_filtered & FILE_ATTRIBUTE_DIRECTORY) != 0
+      || md.file_attributes() == FILE_ATTRIBUTE_NORMAL)
+      && !(attr_filtered & IS_PROBABLY_ONEDRIVE != 0)
+  {
+    // For normal files, we use the standard metadata
+    let apparent_size = md.len();
+
+    // For simple files, apparent size is often the same as allocated size
+    // But we would need an expensive call to get the exact allocated size
+    // We'll just use apparent size for both in this simple case
+    let allocated_size = apparent_size;
+
+    Some((
+      apparent_size,
+      allocated_size,
+      (!md.is_dir()).then(|| get_file_id(path, md)).flatten(),
+      (
+        windows_time_to_unix_time(md.last_write_time() as i64),
+        windows_time_to_unix_time(md.last_access_time() as i64),
+        windows_time_to_unix_time(md.creation_time() as i64),
+      ),
+    ))
   } else {
-    path.symlink_metadata()
-  };
-  match metadata {
-    Ok(ref md) => {
-      const FILE_ATTRIBUTE_ARCHIVE: u32 = 0x20;
-      const FILE_ATTRIBUTE_READONLY: u32 = 0x01;
-      const FILE_ATTRIBUTE_HIDDEN: u32 = 0x02;
-      const FILE_ATTRIBUTE_SYSTEM: u32 = 0x04;
-      const FILE_ATTRIBUTE_NORMAL: u32 = 0x80;
-      const FILE_ATTRIBUTE_DIRECTORY: u32 = 0x10;
-      const FILE_ATTRIBUTE_SPARSE_FILE: u32 = 0x00000200;
-      const FILE_ATTRIBUTE_PINNED: u32 = 0x00080000;
-      const FILE_ATTRIBUTE_UNPINNED: u32 = 0x00100000;
-      const FILE_ATTRIBUTE_RECALL_ON_OPEN: u32 = 0x00040000;
-      const FILE_ATTRIBUTE_RECALL_ON_DATA_ACCESS: u32 = 0x00400000;
-      const FILE_ATTRIBUTE_OFFLINE: u32 = 0x00001000;
-      // normally FILE_ATTRIBUTE_SPARSE_FILE would be enough, however Windows sometimes likes to mask it out. see: https://stackoverflow.com/q/54560454
-      const IS_PROBABLY_ONEDRIVE: u32 = FILE_ATTRIBUTE_SPARSE_FILE
-        | FILE_ATTRIBUTE_PINNED
-        | FILE_ATTRIBUTE_UNPINNED
-        | FILE_ATTRIBUTE_RECALL_ON_OPEN
-        | FILE_ATTRIBUTE_RECALL_ON_DATA_ACCESS
-        | FILE_ATTRIBUTE_OFFLINE;
-      let attr_filtered = md.file_attributes()
-        & !(FILE_ATTRIBUTE_HIDDEN | FILE_ATTRIBUTE_READONLY | FILE_ATTRIBUTE_SYSTEM);
-      if ((attr_filtered & FILE_ATTRIBUTE_ARCHIVE) != 0
-        || (attr_filtered & FILE_ATTRIBUTE_DIRECTORY) != 0
-        || md.file_attributes() == FILE_ATTRIBUTE_NORMAL)
-        && !(attr_filtered & IS_PROBABLY_ONEDRIVE != 0)
-      {
-        // For normal files, we use the standard metadata
-        let apparent_size = md.len();
-
-        // For simple files, apparent size is often the same as allocated size
-        // But we would need an expensive call to get the exact allocated size
-        // We'll just use apparent size for both in this simple case
-        let allocated_size = apparent_size;
-
-        Some((
-          apparent_size,
-          allocated_size,
-          None,
-          (
-            windows_time_to_unix_time(md.last_write_time() as i64),
-            windows_time_to_unix_time(md.last_access_time() as i64),
-            windows_time_to_unix_time(md.creation_time() as i64),
-          ),
-        ))
-      } else {
-        // For special files (compressed, sparse, etc.), we need the expensive call
-        get_metadata_expensive(path)
-      }
-    }
-    _ => get_metadata_expensive(path),
+    // For special files (compressed, sparse, etc.), we need the expensive call
+    get_metadata_expensive(path)
   }
 }
 
@@ -324,6 +539,513 @@ pub fn get_space_info<P: AsRef<Path>>(path: P) -> Option<(u64, u64, u64)> {
   None
 }
 
+/// A mounted drive that can be unplugged, such as a USB stick or SD card
+#[derive(Debug, Clone, Serialize)]
+pub struct RemovableDrive {
+  /// Where the drive is mounted, the drive root (e.g. E:\) on Windows
+  pub mount_point: std::path::PathBuf,
+  /// Volume label, empty when the drive has none
+  pub label: String,
+  /// Name of the filesystem, e.g. "vfat" or "exFAT"
+  pub filesystem: String,
+  /// Capacity in bytes
+  pub total_bytes: u64,
+  /// Free space in bytes
+  pub available_bytes: u64,
+}
+
+/// List the removable drives that are mounted and can be read, so they can be scanned
+pub fn list_removable_drives() -> Vec<RemovableDrive> {
+  use sysinfo::Disks;
+
+  let disks = Disks::new_with_refreshed_list();
+  disks
+    .iter()
+    .filter(|disk| disk.is_removable() || is_removable_drive(disk.mount_point(), disk.name()))
+    .filter(|disk| fs::read_dir(disk.mount_point()).is_ok())
+    .map(|disk| RemovableDrive {
+      mount_point: disk.mount_point().to_path_buf(),
+      label: get_drive_label(disk.name()),
+      filesystem: disk.file_system().to_string_lossy().into_owned(),
+      total_bytes: disk.total_space(),
+      available_bytes: disk.available_space(),
+    })
+    .collect()
+}
+
+// Check the kernel's removable flag of a drive's block device (e.g. /dev/sdb1). Partitions
+// don't carry the flag themselves, so it is read from the whole disk above them too
+#[cfg(target_os = "linux")]
+fn is_removable_drive(_mount_point: &Path, device: &std::ffi::OsStr) -> bool {
+  let Some(name) = Path::new(device).file_name() else {
+    return false;
+  };
+  let Ok(sys_path) = Path::new("/sys/class/block").join(name).canonicalize() else {
+    return false;
+  };
+  sys_path
+    .ancestors()
+    .take(2)
+    .any(|dir| fs::read_to_string(dir.join("removable")).is_ok_and(|flag| flag.trim() == "1"))
+}
+
+#[cfg(target_os = "windows")]
+fn is_removable_drive(mount_point: &Path, _device: &std::ffi::OsStr) -> bool {
+  use std::os::windows::ffi::OsStrExt;
+  use winapi::um::fileapi::GetDriveTypeW;
+  use winapi::um::winbase::DRIVE_REMOVABLE;
+
+  let root: Vec<u16> = mount_point
+    .as_os_str()
+    .encode_wide()
+    .chain(std::iter::once(0))
+    .collect();
+  unsafe { GetDriveTypeW(root.as_ptr()) == DRIVE_REMOVABLE }
+}
+
+// Elsewhere the flag sysinfo reports is all there is
+#[cfg(not(any(target_os = "linux", target_os = "windows")))]
+fn is_removable_drive(_mount_point: &Path, _device: &std::ffi::OsStr) -> bool {
+  false
+}
+
+// On Linux sysinfo names a disk by its device, the label is found through the
+// /dev/disk/by-label links pointing at that device
+#[cfg(target_os = "linux")]
+fn get_drive_label(device: &std::ffi::OsStr) -> String {
+  let Ok(device) = Path::new(device).canonicalize() else {
+    return String::new();
+  };
+  let Ok(links) = fs::read_dir("/dev/disk/by-label") else {
+    return String::new();
+  };
+  links
+    .flatten()
+    .find(|link| {
+      link
+        .path()
+        .canonicalize()
+        .is_ok_and(|target| target == device)
+    })
+    .map(|link| decode_udev_label(&link.file_name().to_string_lossy()))
+    .unwrap_or_default()
+}
+
+// Other platforms already name a disk by its volume label
+#[cfg(not(target_os = "linux"))]
+fn get_drive_label(name: &std::ffi::OsStr) -> String {
+  name.to_string_lossy().into_owned()
+}
+
+/// Undo udev's escaping of label link names, which writes spaces and other unsafe bytes
+/// as `\xHH`
+#[cfg(target_os = "linux")]
+pub fn decode_udev_label(name: &str) -> String {
+  let mut bytes = Vec::with_capacity(name.len());
+  let mut rest = name.as_bytes();
+  while let Some((&first, tail)) = rest.split_first() {
+    let escaped = tail
+      .strip_prefix(b"x")
+      .filter(|_| first == b'\\')
+      .and_then(|hex| hex.get(..2))
+      .and_then(|hex| std::str::from_utf8(hex).ok())
+      .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+    match escaped {
+      Some(byte) => {
+        bytes.push(byte);
+        rest = &rest[4..];
+      }
+      None => {
+        bytes.push(first);
+        rest = tail;
+      }
+    }
+  }
+  String::from_utf8_lossy(&bytes).into_owned()
+}
+
+/// Key identifying the volume a path lives on, used to cache per-volume information
+#[cfg(target_family = "unix")]
+pub type VolumeKey = u64;
+#[cfg(not(target_family = "unix"))]
+pub type VolumeKey = std::path::PathBuf;
+
+/// Get the key of the volume a path lives on (device id on Unix, volume root on Windows,
+/// path prefix elsewhere)
+#[cfg(target_family = "unix")]
+pub fn get_volume_key<P: AsRef<Path>>(_path: P, path_info: &PathInfo) -> Option<VolumeKey> {
+  path_info.inode_device.map(|(_, device)| device)
+}
+
+#[cfg(target_os = "windows")]
+pub fn get_volume_key<P: AsRef<Path>>(path: P, _path_info: &PathInfo) -> Option<VolumeKey> {
+  // The cheap Windows metadata path doesn't give us a volume serial, so key by the volume
+  // root, which also tells apart volumes mounted in a folder (e.g. C:\mnt\data) from C:
+  use std::os::windows::ffi::OsStringExt;
+
+  let volume_root = get_volume_root(path)?;
+  let len = volume_root
+    .iter()
+    .position(|&c| c == 0)
+    .unwrap_or(volume_root.len());
+  Some(std::path::PathBuf::from(std::ffi::OsString::from_wide(
+    &volume_root[..len],
+  )))
+}
+
+#[cfg(not(any(target_family = "unix", target_os = "windows")))]
+pub fn get_volume_key<P: AsRef<Path>>(path: P, _path_info: &PathInfo) -> Option<VolumeKey> {
+  // No volume id without a platform call, so key by the drive letter or share prefix
+  path
+    .as_ref()
+    .components()
+    .next()
+    .map(|prefix| std::path::PathBuf::from(prefix.as_os_str()))
+}
+
+/// Get the allocation unit (cluster/fragment size) of the volume containing a path
+#[cfg(target_family = "unix")]
+pub fn get_cluster_size<P: AsRef<Path>>(path: P) -> Option<u64> {
+  use std::ffi::CString;
+  use std::os::unix::ffi::OsStrExt;
+
+  let c_path = CString::new(path.as_ref().as_os_str().as_bytes()).ok()?;
+  let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+  if unsafe { libc::statvfs(c_path.as_ptr(), &mut stat) } != 0 {
+    return None;
+  }
+
+  // f_frsize is the real allocation unit, some systems leave it 0 and only set f_bsize
+  let fragment_size = stat.f_frsize as u64;
+  if fragment_size > 0 {
+    Some(fragment_size)
+  } else {
+    Some(stat.f_bsize as u64).filter(|&size| size > 0)
+  }
+}
+
+/// What kind of link a symlink-like path is, for deciding whether to follow it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+// Junctions only exist on Windows
+#[cfg_attr(not(target_os = "windows"), allow(dead_code))]
+pub enum LinkKind {
+  FileSymlink,
+  DirSymlink,
+  Junction,
+}
+
+/// Classify a path that `Path::is_symlink` reported as a link, given whether its target is a
+/// directory
+#[cfg(target_os = "windows")]
+pub fn get_link_kind<P: AsRef<Path>>(path: P, target_is_dir: bool) -> LinkKind {
+  if is_junction(path) {
+    LinkKind::Junction
+  } else if target_is_dir {
+    LinkKind::DirSymlink
+  } else {
+    LinkKind::FileSymlink
+  }
+}
+
+#[cfg(not(target_os = "windows"))]
+pub fn get_link_kind<P: AsRef<Path>>(_path: P, target_is_dir: bool) -> LinkKind {
+  if target_is_dir {
+    LinkKind::DirSymlink
+  } else {
+    LinkKind::FileSymlink
+  }
+}
+
+// Junctions (mount points) are reparse points with their own tag, which std doesn't expose
+#[cfg(target_os = "windows")]
+fn is_junction<P: AsRef<Path>>(path: P) -> bool {
+  use std::os::windows::ffi::OsStrExt;
+  use winapi::um::fileapi::{FindClose, FindFirstFileW};
+  use winapi::um::handleapi::INVALID_HANDLE_VALUE;
+  use winapi::um::minwinbase::WIN32_FIND_DATAW;
+  use winapi::um::winnt::{FILE_ATTRIBUTE_REPARSE_POINT, IO_REPARSE_TAG_MOUNT_POINT};
+
+  let path_wide: Vec<u16> = path
+    .as_ref()
+    .as_os_str()
+    .encode_wide()
+    .chain(std::iter::once(0))
+    .collect();
+
+  unsafe {
+    let mut find_data: WIN32_FIND_DATAW = std::mem::zeroed();
+    let handle = FindFirstFileW(path_wide.as_ptr(), &mut find_data);
+    if handle == INVALID_HANDLE_VALUE {
+      return false;
+    }
+    FindClose(handle);
+
+    // For reparse points, dwReserved0 holds the reparse tag
+    find_data.dwFileAttributes & FILE_ATTRIBUTE_REPARSE_POINT != 0
+      && find_data.dwReserved0 == IO_REPARSE_TAG_MOUNT_POINT
+  }
+}
+
+// Resolve the volume root (e.g. C:\ or a mounted folder) containing a path, as a
+// null-terminated wide string
+#[cfg(target_os = "windows")]
+fn get_volume_root<P: AsRef<Path>>(path: P) -> Option<Vec<u16>> {
+  use std::os::windows::ffi::OsStrExt;
+  use winapi::um::fileapi::GetVolumePathNameW;
+
+  let path_wide: Vec<u16> = path
+    .as_ref()
+    .as_os_str()
+    .encode_wide()
+    .chain(std::iter::once(0))
+    .collect();
+
+  let mut volume_root = vec![0u16; 1024];
+  let ok = unsafe {
+    GetVolumePathNameW(
+      path_wide.as_ptr(),
+      volume_root.as_mut_ptr(),
+      volume_root.len() as u32,
+    )
+  };
+  if ok == 0 {
+    return None;
+  }
+  Some(volume_root)
+}
+
+#[cfg(target_os = "windows")]
+pub fn get_cluster_size<P: AsRef<Path>>(path: P) -> Option<u64> {
+  use winapi::um::fileapi::GetDiskFreeSpaceW;
+
+  let volume_root = get_volume_root(path)?;
+
+  unsafe {
+    let mut sectors_per_cluster = 0;
+    let mut bytes_per_sector = 0;
+    let mut free_clusters = 0;
+    let mut total_clusters = 0;
+    if GetDiskFreeSpaceW(
+      volume_root.as_ptr(),
+      &mut sectors_per_cluster,
+      &mut bytes_per_sector,
+      &mut free_clusters,
+      &mut total_clusters,
+    ) == 0
+    {
+      return None;
+    }
+
+    Some(sectors_per_cluster as u64 * bytes_per_sector as u64).filter(|&size| size > 0)
+  }
+}
+
+#[cfg(not(any(target_family = "unix", target_os = "windows")))]
+pub fn get_cluster_size<P: AsRef<Path>>(_path: P) -> Option<u64> {
+  None
+}
+
+/// Get the name of the filesystem (e.g. "NTFS", "exFAT") of the volume containing a path
+#[cfg(target_os = "windows")]
+pub fn get_filesystem_name<P: AsRef<Path>>(path: P) -> Option<String> {
+  use winapi::um::fileapi::GetVolumeInformationW;
+
+  let volume_root = get_volume_root(path)?;
+
+  let mut filesystem_name = vec![0u16; 64];
+  let ok = unsafe {
+    GetVolumeInformationW(
+      volume_root.as_ptr(),
+      std::ptr::null_mut(),
+      0,
+      std::ptr::null_mut(),
+      std::ptr::null_mut(),
+      std::ptr::null_mut(),
+      filesystem_name.as_mut_ptr(),
+      filesystem_name.len() as u32,
+    )
+  };
+  if ok == 0 {
+    return None;
+  }
+
+  let len = filesystem_name
+    .iter()
+    .position(|&c| c == 0)
+    .unwrap_or(filesystem_name.len());
+  Some(String::from_utf16_lossy(&filesystem_name[..len]))
+}
+
+// Other platforms already report real allocated sizes, so the filesystem name isn't needed
+#[cfg(not(target_os = "windows"))]
+pub fn get_filesystem_name<P: AsRef<Path>>(_path: P) -> Option<String> {
+  None
+}
+
+/// Whether a filesystem name belongs to the FAT family (FAT12/16/32, exFAT)
+pub fn is_fat_filesystem(name: &str) -> bool {
+  matches!(
+    name.to_ascii_uppercase().as_str(),
+    "FAT" | "FAT12" | "FAT16" | "FAT32" | "EXFAT"
+  )
+}
+
+/// Whether the app can read a folder macOS only opens up with Full Disk Access
+/// Returns true when the check doesn't apply, e.g. the folder doesn't exist
+#[cfg(target_os = "macos")]
+pub fn has_full_disk_access() -> bool {
+  let Some(protected_dir) = dirs::home_dir().map(|home| home.join("Library").join("Mail")) else {
+    return true;
+  };
+  match fs::read_dir(protected_dir) {
+    Ok(_) => true,
+    Err(e) => e.kind() != std::io::ErrorKind::PermissionDenied,
+  }
+}
+
+// Full Disk Access only exists on macOS
+#[cfg(not(target_os = "macos"))]
+pub fn has_full_disk_access() -> bool {
+  true
+}
+
+/// The path without Windows' verbatim prefix (`\\?\C:\Windows` becomes `C:\Windows`), as
+/// `canonicalize` returns every path with it and `Path` equality tells the forms apart
+#[cfg(target_os = "windows")]
+pub fn strip_verbatim_prefix(path: &Path) -> std::path::PathBuf {
+  use std::path::{Component, PathBuf, Prefix};
+
+  let mut components = path.components();
+  let prefix = match components.next() {
+    Some(Component::Prefix(prefix)) => match prefix.kind() {
+      Prefix::VerbatimDisk(drive) => format!("{}:", drive as char),
+      Prefix::VerbatimUNC(server, share) => format!(
+        "\\\\{}\\{}",
+        server.to_string_lossy(),
+        share.to_string_lossy()
+      ),
+      _ => return path.to_path_buf(),
+    },
+    _ => return path.to_path_buf(),
+  };
+  let mut stripped = PathBuf::from(prefix);
+  stripped.extend(components);
+  stripped
+}
+
+#[cfg(not(target_os = "windows"))]
+pub fn strip_verbatim_prefix(path: &Path) -> std::path::PathBuf {
+  path.to_path_buf()
+}
+
+/// Whether paths compare case-insensitively on the platform's default filesystems
+/// (NTFS, APFS and HFS+), so `Foo` and `foo` name the same file
+#[cfg(any(target_os = "windows", target_os = "macos"))]
+pub fn has_case_insensitive_paths() -> bool {
+  true
+}
+
+#[cfg(not(any(target_os = "windows", target_os = "macos")))]
+pub fn has_case_insensitive_paths() -> bool {
+  false
+}
+
+/// System directories that moving or deleting would break the OS, so mutations need an
+/// explicit opt-in. Only the directories themselves, not what's inside them
+#[cfg(target_os = "windows")]
+pub fn protected_roots() -> Vec<std::path::PathBuf> {
+  use std::path::PathBuf;
+
+  // Windows isn't necessarily on C:, so everything hangs off the configured system drive
+  let system_drive = std::env::var_os("SystemDrive").unwrap_or_else(|| "C:".into());
+  let drive_root = PathBuf::from(format!("{}\\", system_drive.to_string_lossy()));
+  let env_dir = |name: &str, default: &str| {
+    std::env::var_os(name)
+      .map(PathBuf::from)
+      .unwrap_or_else(|| drive_root.join(default))
+  };
+
+  vec![
+    env_dir("SystemRoot", "Windows"),
+    env_dir("ProgramFiles", "Program Files"),
+    env_dir("ProgramFiles(x86)", "Program Files (x86)"),
+    env_dir("ProgramData", "ProgramData"),
+    drive_root.join("Users"),
+    drive_root,
+  ]
+}
+
+#[cfg(target_os = "macos")]
+pub fn protected_roots() -> Vec<std::path::PathBuf> {
+  [
+    "/",
+    "/Applications",
+    "/Library",
+    "/System",
+    "/Users",
+    "/bin",
+    "/etc",
+    "/private",
+    "/sbin",
+    "/usr",
+    "/var",
+  ]
+  .iter()
+  .map(std::path::PathBuf::from)
+  .collect()
+}
+
+#[cfg(not(any(target_os = "windows", target_os = "macos")))]
+pub fn protected_roots() -> Vec<std::path::PathBuf> {
+  [
+    "/", "/bin", "/boot", "/dev", "/etc", "/home", "/lib", "/lib64", "/proc", "/root", "/sbin",
+    "/sys", "/usr", "/var",
+  ]
+  .iter()
+  .map(std::path::PathBuf::from)
+  .collect()
+}
+
+/// Recreate a symlink at `destination` pointing to the same target as `source`
+#[cfg(target_family = "unix")]
+pub fn copy_symlink<P: AsRef<Path>, Q: AsRef<Path>>(
+  source: P,
+  destination: Q,
+) -> std::io::Result<()> {
+  let target = fs::read_link(source)?;
+  std::os::unix::fs::symlink(target, destination)
+}
+
+#[cfg(target_os = "windows")]
+pub fn copy_symlink<P: AsRef<Path>, Q: AsRef<Path>>(
+  source: P,
+  destination: Q,
+) -> std::io::Result<()> {
+  use std::os::windows::fs::{symlink_dir, symlink_file};
+
+  let target = fs::read_link(source.as_ref())?;
+  // Windows distinguishes file and directory symlinks, so mirror the original's kind
+  let is_dir_link = fs::metadata(source.as_ref())
+    .map(|md| md.is_dir())
+    .unwrap_or(false);
+  if is_dir_link {
+    symlink_dir(target, destination)
+  } else {
+    symlink_file(target, destination)
+  }
+}
+
+#[cfg(not(any(target_family = "unix", target_os = "windows")))]
+pub fn copy_symlink<P: AsRef<Path>, Q: AsRef<Path>>(
+  _source: P,
+  _destination: Q,
+) -> std::io::Result<()> {
+  Err(std::io::Error::new(
+    std::io::ErrorKind::Unsupported,
+    "Copying symlinks is not supported on this platform",
+  ))
+}
+
 #[cfg(target_family = "unix")]
 fn get_owner_name<P: AsRef<Path>>(_path: P, metadata: &std::fs::Metadata) -> Option<String> {
   use std::os::unix::fs::MetadataExt;